@@ -0,0 +1,8 @@
+CREATE TRIGGER user_dns_records_updated_at
+AFTER UPDATE ON user_dns_records
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE user_dns_records SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE INDEX user_dns_records_lookup ON user_dns_records(domain_name, record_type, is_active);
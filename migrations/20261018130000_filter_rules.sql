@@ -0,0 +1,20 @@
+CREATE TABLE filter_rules (
+    id INTEGER PRIMARY KEY,
+    -- 'allow' or 'block', allow rules take precedence
+    action TEXT NOT NULL CHECK (action IN ('allow', 'block')),
+    -- 'exact' (domain and its subdomains), 'wildcard' or 'regex'
+    kind TEXT NOT NULL CHECK (kind IN ('exact', 'wildcard', 'regex')),
+    pattern TEXT NOT NULL,
+    comment TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    is_active BOOLEAN NOT NULL DEFAULT 1,
+    UNIQUE (action, kind, pattern)
+);
+
+CREATE TRIGGER filter_rules_updated_at
+AFTER UPDATE ON filter_rules
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE filter_rules SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
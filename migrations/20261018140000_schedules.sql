@@ -0,0 +1,42 @@
+CREATE TABLE schedules (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- comma separated weekdays the window starts on, e.g. 'sun,mon,tue,wed,thu'
+    days TEXT NOT NULL,
+    -- windows ending before they start run past midnight
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- rules may now be limited to a schedule and a client group,
+-- so the same pattern can show up once per group
+CREATE TABLE filter_rules_new (
+    id INTEGER PRIMARY KEY,
+    action TEXT NOT NULL CHECK (action IN ('allow', 'block')),
+    kind TEXT NOT NULL CHECK (kind IN ('exact', 'wildcard', 'regex')),
+    pattern TEXT NOT NULL,
+    comment TEXT,
+    -- NULL for rules applying to clients outside of any group
+    client_group TEXT,
+    -- NULL for rules that are always in effect
+    schedule_id INTEGER REFERENCES schedules(id),
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    is_active BOOLEAN NOT NULL DEFAULT 1
+);
+
+INSERT INTO filter_rules_new (id, action, kind, pattern, comment, created_at, updated_at, is_active)
+    SELECT id, action, kind, pattern, comment, created_at, updated_at, is_active FROM filter_rules;
+
+DROP TABLE filter_rules;
+ALTER TABLE filter_rules_new RENAME TO filter_rules;
+
+CREATE UNIQUE INDEX filter_rules_unique ON filter_rules(action, kind, pattern, IFNULL(client_group, ''), IFNULL(schedule_id, 0));
+
+CREATE TRIGGER filter_rules_updated_at
+AFTER UPDATE ON filter_rules
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE filter_rules SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
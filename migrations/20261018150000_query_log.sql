@@ -0,0 +1,15 @@
+CREATE TABLE query_log (
+    id INTEGER PRIMARY KEY,
+    -- UTC
+    timestamp DATETIME NOT NULL,
+    client TEXT NOT NULL,
+    qname TEXT NOT NULL,
+    qtype INTEGER NOT NULL,
+    rcode INTEGER NOT NULL,
+    -- 'local', 'cache', 'upstream' or 'blocked', NULL if the query failed
+    answered_from TEXT,
+    latency_us INTEGER NOT NULL
+);
+
+CREATE INDEX query_log_timestamp ON query_log(timestamp);
+CREATE INDEX query_log_client ON query_log(client);
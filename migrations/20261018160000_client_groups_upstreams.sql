@@ -0,0 +1,26 @@
+CREATE TABLE client_groups (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    -- comma separated addresses, networks and MACs, e.g. '192.168.1.10,10.0.0.0/24'
+    clients TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TRIGGER client_groups_updated_at
+AFTER UPDATE ON client_groups
+FOR EACH ROW WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE client_groups SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+END;
+
+CREATE TABLE upstream_servers (
+    id INTEGER PRIMARY KEY,
+    address TEXT NOT NULL,
+    port INTEGER NOT NULL DEFAULT 53,
+    -- NULL for the servers of clients outside of any group
+    client_group TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX upstream_servers_unique ON upstream_servers(address, port, IFNULL(client_group, ''));
@@ -0,0 +1,80 @@
+-- bumped on every change of the tables the server loads on startup,
+-- so that it notices changes made by other processes like tinydns-ctl
+CREATE TABLE config_version (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL
+);
+
+INSERT INTO config_version (id, version) VALUES (1, 0);
+
+CREATE TRIGGER filter_rules_insert_config_version
+AFTER INSERT ON filter_rules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER filter_rules_update_config_version
+AFTER UPDATE ON filter_rules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER filter_rules_delete_config_version
+AFTER DELETE ON filter_rules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER schedules_insert_config_version
+AFTER INSERT ON schedules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER schedules_update_config_version
+AFTER UPDATE ON schedules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER schedules_delete_config_version
+AFTER DELETE ON schedules
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER client_groups_insert_config_version
+AFTER INSERT ON client_groups
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER client_groups_update_config_version
+AFTER UPDATE ON client_groups
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER client_groups_delete_config_version
+AFTER DELETE ON client_groups
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER upstream_servers_insert_config_version
+AFTER INSERT ON upstream_servers
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER upstream_servers_update_config_version
+AFTER UPDATE ON upstream_servers
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
+
+CREATE TRIGGER upstream_servers_delete_config_version
+AFTER DELETE ON upstream_servers
+BEGIN
+    UPDATE config_version SET version = version + 1;
+END;
@@ -0,0 +1,294 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{middleware, Router};
+
+use crate::{dashboard, database::Database, error::DnsError, server::ServerConfig};
+
+use super::{
+    audit, auth as api_auth, groups, querylog, records, rules, schedules, stats, upstreams,
+    users, ApiError,
+};
+
+#[derive(Clone)]
+pub struct ApiState {
+    pub db: Arc<Database>,
+    pub config: Arc<ServerConfig>,
+}
+
+impl ApiState {
+    /*
+        Applies changes made to the database to the running server
+    */
+    pub async fn reload(&self) -> Result<(), ApiError> {
+        Ok(self.config.reload(&self.db).await?)
+    }
+}
+
+/*
+    JSON API for managing records, filter rules, schedules, client groups, upstream servers
+    and users, and for reading the query log and statistics. Everything but logging in takes
+    a session token or API key. The dashboard is served next to it.
+*/
+pub fn router(state: ApiState) -> Router {
+    let managed = Router::new()
+        .merge(records::routes())
+        .merge(rules::routes())
+        .merge(schedules::routes())
+        .merge(groups::routes())
+        .merge(upstreams::routes())
+        .merge(querylog::routes())
+        .merge(stats::routes())
+        .merge(users::routes())
+        .merge(audit::routes())
+        .route_layer(middleware::from_fn(api_auth::authorize));
+
+    let authenticated = Router::new()
+        .merge(api_auth::account_routes())
+        .merge(managed)
+        .route_layer(middleware::from_fn_with_state(state.clone(), api_auth::authenticate));
+
+    let api = Router::new()
+        .merge(api_auth::public_routes())
+        .merge(authenticated);
+
+    Router::new()
+        .nest("/api", api)
+        .merge(dashboard::routes())
+        .with_state(state)
+}
+
+/*
+    Serves the API and the dashboard. Without any users nobody can log in,
+    auth::ensure_admin creates the first one.
+*/
+pub async fn serve_api(config: Arc<ServerConfig>, db: Arc<Database>) -> Result<(), DnsError> {
+    let listener =
+        tokio::net::TcpListener::bind(format!("{}:{}", config.listen_addr(), config.admin_port()))
+            .await?;
+
+    // client addresses end up in the audit log
+    let app = router(ApiState { db, config }).into_make_service_with_connect_info::<SocketAddr>();
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{Request, StatusCode},
+    };
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use std::{net::IpAddr, time::Duration};
+
+    use crate::{
+        auth, database::QueryLogEntity, filter::Verdict, nameserver::Nameserver,
+        protocol::answer::AnswerSource,
+    };
+
+    use super::*;
+
+    struct TestApp {
+        router: Router,
+        // session token sent along, if any
+        token: Option<String>,
+    }
+
+    async fn send(app: &TestApp, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json");
+        if let Some(token) = &app.token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let request = request
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+
+        let response = app.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    async fn login(app: &TestApp, username: &str, password: &str) -> Option<String> {
+        let (_, session) = send(app, "POST", "/api/login", Some(json!({"username": username, "password": password}))).await;
+        session["token"].as_str().map(str::to_string)
+    }
+
+    /*
+        App with an admin logged in
+    */
+    async fn setup() -> (TestApp, Arc<ServerConfig>, Arc<Database>) {
+        let db = Arc::new(Database::init_mem().await.unwrap());
+        let config = Arc::new(ServerConfig::default().with_nameserver(Nameserver::new(db.clone())));
+        auth::create_user(&db, "admin", "admin password", auth::Role::Admin).await.unwrap();
+
+        let mut app = TestApp {
+            router: router(ApiState { db: db.clone(), config: config.clone() }),
+            token: None,
+        };
+        app.token = login(&app, "admin", "admin password").await;
+        (app, config, db)
+    }
+
+    #[tokio::test]
+    async fn test_records() {
+        let (app, _, _) = setup().await;
+
+        let (status, created) = send(
+            &app,
+            "POST",
+            "/api/records",
+            Some(json!({"name": "Mail.Example.com", "type": "MX", "value": "10 mx.example.com", "ttl": 600})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["name"], "mail.example.com");
+        assert_eq!(created["value"], "10 mx.example.com.");
+        let uri = format!("/api/records/{}", created["id"]);
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/api/records",
+            Some(json!({"name": "www.example.com", "type": "A", "value": "not an address"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["field"], "value");
+
+        let (status, updated) = send(
+            &app,
+            "PUT",
+            &uri,
+            Some(json!({"name": "mail.example.com", "type": "MX", "value": "20 mx.example.com", "active": false})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["value"], "20 mx.example.com.");
+        assert_eq!(updated["active"], false);
+
+        let (_, records) = send(&app, "GET", "/api/records?type=MX", None).await;
+        assert_eq!(records.as_array().unwrap().len(), 1);
+
+        assert_eq!(send(&app, "DELETE", &uri, None).await.0, StatusCode::NO_CONTENT);
+        assert_eq!(send(&app, "GET", &uri, None).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let (app, config, _) = setup().await;
+        let kid: IpAddr = "10.0.0.7".parse().unwrap();
+
+        let (status, body) =
+            send(&app, "POST", "/api/groups", Some(json!({"name": "kids", "clients": ["10.0.0.0/33"]}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["field"], "clients");
+
+        let (status, _) =
+            send(&app, "POST", "/api/groups", Some(json!({"name": "kids", "clients": ["10.0.0.0/24"]}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(config.policy_for(kid).group(), Some("kids"));
+
+        let rule = json!({"action": "block", "kind": "wildcard", "pattern": "*.games.example", "group": "kids"});
+        assert_eq!(send(&app, "POST", "/api/rules", Some(rule)).await.0, StatusCode::CREATED);
+        assert!(matches!(config.policy_for(kid).filter().check("play.games.example"), Some(Verdict::Blocked(_))));
+        assert!(config.filter().check("play.games.example").is_none());
+
+        let upstream = json!({"address": "9.9.9.9", "group": "kids"});
+        assert_eq!(send(&app, "POST", "/api/upstreams", Some(upstream)).await.0, StatusCode::CREATED);
+        assert_eq!(config.policy_for(kid).resolver().fallback_servers(), vec![("9.9.9.9".to_string(), 53)]);
+
+        let (status, body) =
+            send(&app, "POST", "/api/upstreams", Some(json!({"address": "9.9.9.9", "group": "adults"}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["field"], "group");
+
+        assert_eq!(send(&app, "DELETE", "/api/groups/kids", None).await.0, StatusCode::NO_CONTENT);
+        assert_eq!(config.policy_for(kid).group(), None);
+        let (_, rules) = send(&app, "GET", "/api/rules", None).await;
+        assert!(rules.as_array().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_querylog_and_dashboard() {
+        let (app, _, db) = setup().await;
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let entries = [
+            QueryLogEntity::new(client, "ads.example.com".to_string(), 1, 0, Some(AnswerSource::Blocked), Duration::ZERO),
+            QueryLogEntity::new(client, "www.example.com".to_string(), 28, 0, Some(AnswerSource::Upstream), Duration::from_millis(12)),
+        ];
+        QueryLogEntity::_insert_batch(&entries, db.get_pool(), db.query_log_tbl()).await.unwrap();
+
+        let (status, queries) = send(&app, "GET", "/api/querylog?name=Example.com.", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(queries[0]["name"], "www.example.com");
+        assert_eq!(queries[0]["type"], "AAAA");
+        assert_eq!(queries[0]["latency_ms"], 12.0);
+
+        let (_, queries) = send(&app, "GET", &format!("/api/querylog?source=blocked&after={}", queries[1]["id"]), None).await;
+        assert!(queries.as_array().unwrap().is_empty());
+        assert_eq!(send(&app, "GET", "/api/querylog?source=nowhere", None).await.0, StatusCode::BAD_REQUEST);
+
+        let (status, stats) = send(&app, "GET", "/api/stats?hours=1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(stats["totals"]["queries"], 2);
+        assert_eq!(stats["top_blocked_domains"][0]["name"], "ads.example.com");
+
+        let response = app
+            .router
+            .clone()
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains("<script src=\"/app.js\">"));
+    }
+
+    #[tokio::test]
+    async fn test_auth() {
+        let (admin, _, _) = setup().await;
+        let anonymous = TestApp { router: admin.router.clone(), token: None };
+        assert_eq!(send(&anonymous, "GET", "/api/records", None).await.0, StatusCode::UNAUTHORIZED);
+        assert!(login(&anonymous, "admin", "wrong password").await.is_none());
+
+        let viewer = json!({"username": "kim", "password": "viewer password", "role": "viewer"});
+        assert_eq!(send(&admin, "POST", "/api/users", Some(viewer)).await.0, StatusCode::CREATED);
+        let viewer = TestApp {
+            router: admin.router.clone(),
+            token: login(&anonymous, "kim", "viewer password").await,
+        };
+
+        // viewers may look, but not change anything or read the audit log
+        assert_eq!(send(&viewer, "GET", "/api/records", None).await.0, StatusCode::OK);
+        let record = json!({"name": "example.com", "type": "A", "value": "10.0.0.1"});
+        assert_eq!(send(&viewer, "POST", "/api/records", Some(record)).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(send(&viewer, "GET", "/api/audit", None).await.0, StatusCode::FORBIDDEN);
+        let (_, me) = send(&viewer, "GET", "/api/me", None).await;
+        assert_eq!(me["role"], "viewer");
+
+        let (_, failed) = send(&admin, "GET", "/api/audit?event=login_failed", None).await;
+        assert_eq!(failed.as_array().unwrap().len(), 1);
+        assert_eq!(failed[0]["username"], "admin");
+
+        // API keys act with the role of their user
+        let (status, key) = send(&admin, "POST", "/api/keys", Some(json!({"name": "backup"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let script = TestApp {
+            router: admin.router.clone(),
+            token: key["key"].as_str().map(str::to_string),
+        };
+        assert_eq!(send(&script, "GET", "/api/users", None).await.0, StatusCode::OK);
+        let (_, keys) = send(&admin, "GET", "/api/keys", None).await;
+        assert!(keys[0].get("key").is_none());
+
+        assert_eq!(send(&viewer, "POST", "/api/logout", None).await.0, StatusCode::NO_CONTENT);
+        assert_eq!(send(&viewer, "GET", "/api/records", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(send(&admin, "DELETE", "/api/users/admin", None).await.0, StatusCode::BAD_REQUEST);
+    }
+}
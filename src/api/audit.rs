@@ -0,0 +1,63 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::Identity, database::AuditEntity};
+
+use super::{auth::require_admin, ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+    id: u64,
+    // UTC
+    timestamp: chrono::NaiveDateTime,
+    event: String,
+    username: Option<String>,
+    client: Option<String>,
+    detail: Option<String>,
+}
+
+impl From<AuditEntity> for AuditResponse {
+    fn from(entity: AuditEntity) -> Self {
+        AuditResponse {
+            id: entity.id(),
+            timestamp: entity.timestamp(),
+            event: entity.event().to_string(),
+            username: entity.username().map(str::to_string),
+            client: entity.client().map(str::to_string),
+            detail: entity.detail().map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditFilter {
+    // e.g. "login_failed"
+    event: Option<String>,
+    limit: Option<u32>,
+}
+
+async fn list_audit(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+    Query(filter): Query<AuditFilter>,
+) -> Result<Json<Vec<AuditResponse>>, ApiError> {
+    require_admin(&identity)?;
+    let db = &state.db;
+    let entries = AuditEntity::_fetch_recent(
+        db.get_pool(),
+        db.audit_log_tbl(),
+        filter.event.as_deref(),
+        filter.limit.unwrap_or(100).min(1000),
+    )
+    .await?;
+
+    Ok(Json(entries.into_iter().map(AuditResponse::from).collect()))
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/audit", get(list_audit))
+}
@@ -0,0 +1,179 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{rejection::JsonRejection, ConnectInfo, Request, State},
+    http::{header, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{self, Identity, Role},
+    error::AuthError,
+};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct IdentityResponse {
+    username: String,
+    role: Role,
+}
+
+impl From<&Identity> for IdentityResponse {
+    fn from(identity: &Identity) -> Self {
+        IdentityResponse {
+            username: identity.username.clone(),
+            role: identity.role,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    token: String,
+    // UTC
+    expires_at: chrono::NaiveDateTime,
+    #[serde(flatten)]
+    identity: IdentityResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/*
+    Rejects requests without a valid session token or API key,
+    making the identity of the caller available to handlers
+*/
+pub async fn authenticate(State(state): State<ApiState>, mut request: Request, next: Next) -> Response {
+    let identity = match bearer_token(&request) {
+        Some(token) => auth::authenticate(&state.db, token).await,
+        None => Ok(None),
+    };
+
+    match identity {
+        Ok(Some(identity)) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Ok(None) => ([(header::WWW_AUTHENTICATE, "Bearer")], ApiError::unauthorized()).into_response(),
+        Err(err) => ApiError::from(err).into_response(),
+    }
+}
+
+/*
+    Viewers may read, everything else takes an admin
+*/
+pub async fn authorize(Extension(identity): Extension<Identity>, request: Request, next: Next) -> Response {
+    let required = match *request.method() {
+        Method::GET | Method::HEAD => Role::Viewer,
+        _ => Role::Admin,
+    };
+
+    if !identity.has_role(required) {
+        return ApiError::forbidden().into_response();
+    }
+    next.run(request).await
+}
+
+/*
+    For handlers that are admin only even when reading
+*/
+pub fn require_admin(identity: &Identity) -> Result<(), ApiError> {
+    if identity.has_role(Role::Admin) {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden())
+    }
+}
+
+async fn login(
+    State(state): State<ApiState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    payload: Result<Json<LoginRequest>, JsonRejection>,
+) -> Result<Json<SessionResponse>, ApiError> {
+    let Json(request) = payload?;
+    let client = connect_info.map(|ConnectInfo(addr)| addr.ip());
+
+    let session = auth::login(&state.db, request.username.trim(), &request.password, client)
+        .await?
+        .ok_or_else(|| ApiError::new(StatusCode::UNAUTHORIZED, "invalid_credentials", "wrong username or password"))?;
+
+    Ok(Json(SessionResponse {
+        token: session.token,
+        expires_at: session.expires_at,
+        identity: IdentityResponse::from(&session.identity),
+    }))
+}
+
+async fn logout(State(state): State<ApiState>, request: Request) -> Result<StatusCode, ApiError> {
+    if let Some(token) = bearer_token(&request) {
+        auth::logout(&state.db, token).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn me(Extension(identity): Extension<Identity>) -> Json<IdentityResponse> {
+    Json(IdentityResponse::from(&identity))
+}
+
+/*
+    Lets users of any role change their own password
+*/
+async fn change_password(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+    payload: Result<Json<PasswordRequest>, JsonRejection>,
+) -> Result<StatusCode, ApiError> {
+    let Json(request) = payload?;
+    if !auth::check_password(&state.db, &identity.username, &request.current_password).await? {
+        return Err(ApiError::invalid("current_password", "wrong password"));
+    }
+
+    auth::update_user(&state.db, &identity.username, Some(&request.new_password), identity.role)
+        .await
+        .map_err(|err| match err {
+            AuthError::InvalidAccount(_) => ApiError::invalid("new_password", err),
+            _ => err.into(),
+        })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/*
+    Routes reachable without logging in
+*/
+pub fn public_routes() -> Router<ApiState> {
+    Router::new().route("/login", post(login))
+}
+
+/*
+    Routes any logged in user may use, whatever their role
+*/
+pub fn account_routes() -> Router<ApiState> {
+    Router::new()
+        .route("/logout", post(logout))
+        .route("/me", get(me))
+        .route("/me/password", put(change_password))
+}
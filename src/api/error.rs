@@ -0,0 +1,117 @@
+use std::fmt::Display;
+
+use axum::{
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::error::{AuthError, DnsError, RuleError, StorageError};
+
+/*
+    Error returned by the admin API, serialized as {"error": {"code": ..., "message": ..., "field": ...}}
+*/
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: StatusCode,
+    // machine readable, e.g. "invalid_value"
+    code: &'static str,
+    message: String,
+    // the request field that failed validation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Display) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.to_string(),
+            field: None,
+        }
+    }
+
+    /*
+        A request field holds a value that can't be used
+    */
+    pub fn invalid(field: &'static str, message: impl Display) -> Self {
+        ApiError {
+            field: Some(field),
+            ..ApiError::new(StatusCode::BAD_REQUEST, "invalid_value", message)
+        }
+    }
+
+    /*
+        The request carries no valid session token or API key
+    */
+    pub fn unauthorized() -> Self {
+        ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", "authentication required")
+    }
+
+    /*
+        The caller is authenticated, but their role doesn't allow the request
+    */
+    pub fn forbidden() -> Self {
+        ApiError::new(StatusCode::FORBIDDEN, "forbidden", "this requires the admin role")
+    }
+
+    pub fn not_found(what: impl Display) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, "not_found", format!("{} not found", what))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self }))).into_response()
+    }
+}
+
+impl From<DnsError> for ApiError {
+    fn from(err: DnsError) -> Self {
+        match &err {
+            DnsError::Storage(StorageError::Query(sqlx::Error::Database(db_err)))
+                if db_err.is_unique_violation() =>
+            {
+                ApiError::new(StatusCode::CONFLICT, "conflict", "an identical entry already exists")
+            }
+            DnsError::Storage(StorageError::Query(sqlx::Error::Database(db_err)))
+                if db_err.is_foreign_key_violation() =>
+            {
+                ApiError::new(StatusCode::CONFLICT, "in_use", "the entry is still referred to")
+            }
+            _ => {
+                log::error!("Admin API request failed: {}", err);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", err)
+            }
+        }
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(err: AuthError) -> Self {
+        match err {
+            AuthError::InvalidAccount(_) => ApiError::new(StatusCode::BAD_REQUEST, "invalid_value", err),
+            AuthError::Database(err) => err.into(),
+            AuthError::Hashing(_) => {
+                log::error!("Admin API request failed: {}", err);
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", err)
+            }
+        }
+    }
+}
+
+impl From<RuleError> for ApiError {
+    fn from(err: RuleError) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, "invalid_value", err)
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        ApiError::new(rejection.status(), "invalid_json", rejection.body_text())
+    }
+}
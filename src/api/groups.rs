@@ -0,0 +1,147 @@
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{ClientGroupEntity, FilterRuleEntity, UpstreamEntity},
+    server::ClientMatcher,
+};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct GroupResponse {
+    id: u64,
+    name: String,
+    clients: Vec<String>,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<ClientGroupEntity> for GroupResponse {
+    fn from(entity: ClientGroupEntity) -> Self {
+        GroupResponse {
+            id: entity.id(),
+            name: entity.name().to_string(),
+            clients: entity.clients(),
+            created_at: entity.created_at(),
+            updated_at: entity.updated_at(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GroupRequest {
+    name: String,
+    // addresses, networks and MACs, e.g. "192.168.1.10", "10.0.0.0/24" or "aa:bb:cc:dd:ee:ff"
+    clients: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientsRequest {
+    clients: Vec<String>,
+}
+
+/*
+    Checks every client parses, returning them trimmed
+*/
+fn validate_clients(clients: &[String]) -> Result<Vec<String>, ApiError> {
+    clients
+        .iter()
+        .map(|client| {
+            client
+                .parse::<ClientMatcher>()
+                .map(|_| client.trim().to_string())
+                .map_err(|err| ApiError::invalid("clients", err))
+        })
+        .collect()
+}
+
+async fn fetch_group(state: &ApiState, name: &str) -> Result<GroupResponse, ApiError> {
+    let db = &state.db;
+    ClientGroupEntity::_fetch_by_name(db.get_pool(), db.client_groups_tbl(), name)
+        .await?
+        .map(GroupResponse::from)
+        .ok_or_else(|| ApiError::not_found(format!("client group {}", name)))
+}
+
+async fn list_groups(State(state): State<ApiState>) -> Result<Json<Vec<GroupResponse>>, ApiError> {
+    let db = &state.db;
+    let groups = ClientGroupEntity::_fetch_all(db.get_pool(), db.client_groups_tbl()).await?;
+    Ok(Json(groups.into_iter().map(GroupResponse::from).collect()))
+}
+
+async fn get_group(State(state): State<ApiState>, Path(name): Path<String>) -> Result<Json<GroupResponse>, ApiError> {
+    Ok(Json(fetch_group(&state, &name).await?))
+}
+
+async fn create_group(
+    State(state): State<ApiState>,
+    payload: Result<Json<GroupRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<GroupResponse>), ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let name = request.name.trim();
+    if name.is_empty() || name.contains(',') {
+        return Err(ApiError::invalid("name", format!("invalid group name {}", request.name)));
+    }
+
+    ClientGroupEntity::default()
+        .with_name(name.to_string())
+        .with_clients(&validate_clients(&request.clients)?)
+        ._insert(db.get_pool(), db.client_groups_tbl())
+        .await?;
+    state.reload().await?;
+    Ok((StatusCode::CREATED, Json(fetch_group(&state, name).await?)))
+}
+
+/*
+    Replaces the clients of a group
+*/
+async fn update_group(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    payload: Result<Json<ClientsRequest>, JsonRejection>,
+) -> Result<Json<GroupResponse>, ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let entity = ClientGroupEntity::default()
+        .with_name(name.clone())
+        .with_clients(&validate_clients(&request.clients)?);
+    if !entity._update(db.get_pool(), db.client_groups_tbl()).await? {
+        return Err(ApiError::not_found(format!("client group {}", name)));
+    }
+    state.reload().await?;
+    Ok(Json(fetch_group(&state, &name).await?))
+}
+
+/*
+    Deletes a group along with its rules and upstream servers
+*/
+async fn delete_group(State(state): State<ApiState>, Path(name): Path<String>) -> Result<StatusCode, ApiError> {
+    let db = &state.db;
+    if !ClientGroupEntity::default()
+        .with_name(name.clone())
+        ._delete(db.get_pool(), db.client_groups_tbl())
+        .await?
+    {
+        return Err(ApiError::not_found(format!("client group {}", name)));
+    }
+
+    FilterRuleEntity::_delete_for_group(db.get_pool(), db.filter_rules_tbl(), &name).await?;
+    UpstreamEntity::_delete_for_group(db.get_pool(), db.upstream_servers_tbl(), &name).await?;
+    state.reload().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/groups", get(list_groups).post(create_group))
+        .route("/groups/:name", get(get_group).put(update_group).delete(delete_group))
+}
@@ -0,0 +1,15 @@
+mod api;
+mod audit;
+mod auth;
+mod error;
+mod groups;
+mod querylog;
+mod records;
+mod rules;
+mod schedules;
+mod stats;
+mod upstreams;
+mod users;
+
+pub use api::{serve_api, ApiState};
+pub use error::ApiError;
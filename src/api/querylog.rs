@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::QueryLogEntity,
+    protocol::{
+        answer::AnswerSource,
+        packet::{flags::ResponseCode, type_mnemonic},
+    },
+};
+
+use super::{ApiError, ApiState};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct QueryLogResponse {
+    id: u64,
+    // UTC
+    timestamp: chrono::NaiveDateTime,
+    client: String,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    rcode: String,
+    // None if the query couldn't be answered
+    source: Option<String>,
+    latency_ms: f64,
+}
+
+impl From<QueryLogEntity> for QueryLogResponse {
+    fn from(entity: QueryLogEntity) -> Self {
+        QueryLogResponse {
+            id: entity.id(),
+            timestamp: entity.timestamp(),
+            client: entity.client().to_string(),
+            name: entity.qname().to_string(),
+            record_type: type_mnemonic(entity.qtype()),
+            rcode: ResponseCode::from_u16(entity.rcode()).mnemonic().to_string(),
+            source: entity.answered_from().map(str::to_string),
+            latency_ms: entity.latency().as_secs_f64() * 1000.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryLogFilter {
+    client: Option<String>,
+    // part of the queried name
+    name: Option<String>,
+    source: Option<String>,
+    // only entries newer than the one with this id, for polling
+    after: Option<u64>,
+    limit: Option<u32>,
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+/*
+    Latest handled queries, newest first. Queries show up once the query log flushed them.
+*/
+async fn list_queries(
+    State(state): State<ApiState>,
+    Query(filter): Query<QueryLogFilter>,
+) -> Result<Json<Vec<QueryLogResponse>>, ApiError> {
+    let sources = [AnswerSource::Local, AnswerSource::Cache, AnswerSource::Upstream, AnswerSource::Blocked];
+    if let Some(source) = &filter.source {
+        if !sources.iter().any(|known| known.as_str() == source) {
+            return Err(ApiError::invalid("source", format!("unknown answer source {}", source)));
+        }
+    }
+
+    let db = &state.db;
+    let entries = QueryLogEntity::_search(
+        db.get_pool(),
+        db.query_log_tbl(),
+        non_empty(&filter.client),
+        non_empty(&filter.name).map(|name| name.trim_end_matches('.').to_lowercase()).as_deref(),
+        filter.source.as_deref(),
+        filter.after,
+        filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT),
+    )
+    .await?;
+
+    Ok(Json(entries.into_iter().map(QueryLogResponse::from).collect()))
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/querylog", get(list_queries))
+}
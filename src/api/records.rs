@@ -0,0 +1,171 @@
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{RecordEntity, RecordQuery},
+    filter::{is_valid_domain, normalize_domain},
+    nameserver::Nameserver,
+    protocol::packet::{parse_type_mnemonic, type_mnemonic, RData, RecordType},
+};
+
+use super::{ApiError, ApiState};
+
+/*
+    A record with its value in presentation format, e.g. "10 mail.example.com." for MX
+*/
+#[derive(Debug, Serialize)]
+pub struct RecordResponse {
+    id: u64,
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    value: String,
+    ttl: u32,
+    priority: Option<u32>,
+    active: bool,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<RecordEntity> for RecordResponse {
+    type Error = ApiError;
+
+    fn try_from(entity: RecordEntity) -> Result<Self, Self::Error> {
+        Ok(RecordResponse {
+            id: entity.id(),
+            name: entity.domain_name().to_string(),
+            record_type: type_mnemonic(entity.record_type()),
+            value: entity.rdata()?.data_to_string(),
+            ttl: entity.ttl(),
+            priority: entity.priority(),
+            active: entity.is_active(),
+            created_at: entity.created_at(),
+            updated_at: entity.updated_at(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecordRequest {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    value: String,
+    ttl: Option<u32>,
+    priority: Option<u32>,
+    active: Option<bool>,
+}
+
+impl RecordRequest {
+    /*
+        Validates the request, converting the value to wire format
+    */
+    fn to_entity(&self) -> Result<RecordEntity, ApiError> {
+        let name = normalize_domain(&self.name);
+        if !is_valid_domain(&name) {
+            return Err(ApiError::invalid("name", format!("invalid domain name {}", self.name)));
+        }
+
+        let rtype = parse_type_mnemonic(&self.record_type).map_err(|err| ApiError::invalid("type", err))?;
+        let rdata = RData::parse(rtype, &self.value).map_err(|err| ApiError::invalid("value", err))?;
+
+        let mut entity = RecordEntity::default()
+            .with_domain_name(name)
+            .with_rdata(rdata)
+            .map_err(|err| ApiError::invalid("value", err))?
+            .with_active(self.active.unwrap_or(true));
+        if let Some(ttl) = self.ttl {
+            entity = entity.with_ttl(ttl);
+        }
+        if let Some(priority) = self.priority {
+            entity = entity.with_priority(priority);
+        }
+
+        Ok(entity)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecordFilter {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    record_type: Option<String>,
+}
+
+fn nameserver(state: &ApiState) -> Result<&Nameserver, ApiError> {
+    state.config.nameserver().ok_or_else(|| {
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "unavailable", "no local nameserver is configured")
+    })
+}
+
+async fn fetch_record(nameserver: &Nameserver, id: u64) -> Result<RecordResponse, ApiError> {
+    nameserver
+        .query_record(&RecordQuery::default().with_id(id).with_inactive())
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("record {}", id)))?
+        .try_into()
+}
+
+async fn list_records(
+    State(state): State<ApiState>,
+    Query(filter): Query<RecordFilter>,
+) -> Result<Json<Vec<RecordResponse>>, ApiError> {
+    let mut query = RecordQuery::default().with_all().with_inactive();
+    if let Some(name) = &filter.name {
+        query = query.with_domain_name(normalize_domain(name));
+    }
+    if let Some(record_type) = &filter.record_type {
+        let rtype = parse_type_mnemonic(record_type).map_err(|err| ApiError::invalid("type", err))?;
+        query = query.with_record_type(RecordType::from(rtype));
+    }
+
+    let records = nameserver(&state)?.query_records(&query).await?;
+    Ok(Json(records.into_iter().map(RecordResponse::try_from).collect::<Result<_, _>>()?))
+}
+
+async fn get_record(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<Json<RecordResponse>, ApiError> {
+    Ok(Json(fetch_record(nameserver(&state)?, id).await?))
+}
+
+async fn create_record(
+    State(state): State<ApiState>,
+    payload: Result<Json<RecordRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<RecordResponse>), ApiError> {
+    let Json(request) = payload?;
+    let nameserver = nameserver(&state)?;
+
+    let id = nameserver.insert_record(request.to_entity()?).await?;
+    Ok((StatusCode::CREATED, Json(fetch_record(nameserver, id).await?)))
+}
+
+async fn update_record(
+    State(state): State<ApiState>,
+    Path(id): Path<u64>,
+    payload: Result<Json<RecordRequest>, JsonRejection>,
+) -> Result<Json<RecordResponse>, ApiError> {
+    let Json(request) = payload?;
+    let nameserver = nameserver(&state)?;
+
+    if !nameserver.update_record(request.to_entity()?.with_id(id)).await? {
+        return Err(ApiError::not_found(format!("record {}", id)));
+    }
+    Ok(Json(fetch_record(nameserver, id).await?))
+}
+
+async fn delete_record(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    if !nameserver(&state)?.delete_record(id).await? {
+        return Err(ApiError::not_found(format!("record {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/records", get(list_records).post(create_record))
+        .route("/records/:id", get(get_record).put(update_record).delete(delete_record))
+}
@@ -0,0 +1,180 @@
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{ClientGroupEntity, FilterRuleEntity, ScheduleEntity},
+    filter::{FilterRule, RuleAction, RuleKind, RuleSet},
+};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct RuleResponse {
+    id: u64,
+    action: String,
+    kind: String,
+    pattern: String,
+    comment: Option<String>,
+    // None for rules applying to all clients
+    group: Option<String>,
+    schedule_id: Option<u64>,
+    active: bool,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<FilterRuleEntity> for RuleResponse {
+    fn from(entity: FilterRuleEntity) -> Self {
+        RuleResponse {
+            id: entity.id(),
+            action: entity.action().to_string(),
+            kind: entity.kind().to_string(),
+            pattern: entity.pattern().to_string(),
+            comment: entity.comment().map(str::to_string),
+            group: entity.client_group().map(str::to_string),
+            schedule_id: entity.schedule_id(),
+            active: entity.is_active(),
+            created_at: entity.created_at(),
+            updated_at: entity.updated_at(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleRequest {
+    action: String,
+    kind: String,
+    pattern: String,
+    comment: Option<String>,
+    group: Option<String>,
+    schedule_id: Option<u64>,
+    active: Option<bool>,
+}
+
+impl RuleRequest {
+    /*
+        Validates the request, including that the pattern compiles
+        and that group and schedule exist
+    */
+    async fn to_entity(&self, state: &ApiState) -> Result<FilterRuleEntity, ApiError> {
+        let action: RuleAction = self.action.parse().map_err(|err| ApiError::invalid("action", err))?;
+        let kind: RuleKind = self.kind.parse().map_err(|err| ApiError::invalid("kind", err))?;
+        RuleSet::new()
+            .add_rule(FilterRule {
+                id: 0,
+                action,
+                kind,
+                pattern: self.pattern.clone(),
+                schedule: None,
+            })
+            .map_err(|err| ApiError::invalid("pattern", err))?;
+
+        let db = &state.db;
+        let mut entity = FilterRuleEntity::default()
+            .with_action(action)
+            .with_kind(kind)
+            .with_pattern(self.pattern.clone())
+            .with_active(self.active.unwrap_or(true));
+        if let Some(comment) = &self.comment {
+            entity = entity.with_comment(comment.clone());
+        }
+
+        if let Some(group) = &self.group {
+            if ClientGroupEntity::_fetch_by_name(db.get_pool(), db.client_groups_tbl(), group).await?.is_none() {
+                return Err(ApiError::invalid("group", format!("unknown client group {}", group)));
+            }
+            entity = entity.with_client_group(group.clone());
+        }
+
+        if let Some(schedule_id) = self.schedule_id {
+            let schedules = ScheduleEntity::_fetch_all(db.get_pool(), db.schedules_tbl()).await?;
+            if !schedules.iter().any(|schedule| schedule.id() == schedule_id) {
+                return Err(ApiError::invalid("schedule_id", format!("unknown schedule {}", schedule_id)));
+            }
+            entity = entity.with_schedule_id(schedule_id);
+        }
+
+        Ok(entity)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RuleFilter {
+    group: Option<String>,
+}
+
+async fn fetch_rule(state: &ApiState, id: u64) -> Result<RuleResponse, ApiError> {
+    let db = &state.db;
+    FilterRuleEntity::_fetch_one(db.get_pool(), db.filter_rules_tbl(), id)
+        .await?
+        .map(RuleResponse::from)
+        .ok_or_else(|| ApiError::not_found(format!("rule {}", id)))
+}
+
+async fn list_rules(
+    State(state): State<ApiState>,
+    Query(filter): Query<RuleFilter>,
+) -> Result<Json<Vec<RuleResponse>>, ApiError> {
+    let db = &state.db;
+    let rules = FilterRuleEntity::_fetch_all(db.get_pool(), db.filter_rules_tbl(), true).await?;
+
+    Ok(Json(
+        rules
+            .into_iter()
+            .filter(|rule| filter.group.is_none() || rule.client_group() == filter.group.as_deref())
+            .map(RuleResponse::from)
+            .collect(),
+    ))
+}
+
+async fn get_rule(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<Json<RuleResponse>, ApiError> {
+    Ok(Json(fetch_rule(&state, id).await?))
+}
+
+async fn create_rule(
+    State(state): State<ApiState>,
+    payload: Result<Json<RuleRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<RuleResponse>), ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let id = request.to_entity(&state).await?._insert(db.get_pool(), db.filter_rules_tbl()).await?;
+    state.reload().await?;
+    Ok((StatusCode::CREATED, Json(fetch_rule(&state, id).await?)))
+}
+
+async fn update_rule(
+    State(state): State<ApiState>,
+    Path(id): Path<u64>,
+    payload: Result<Json<RuleRequest>, JsonRejection>,
+) -> Result<Json<RuleResponse>, ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let entity = request.to_entity(&state).await?.with_id(id);
+    if !entity._update(db.get_pool(), db.filter_rules_tbl()).await? {
+        return Err(ApiError::not_found(format!("rule {}", id)));
+    }
+    state.reload().await?;
+    Ok(Json(fetch_rule(&state, id).await?))
+}
+
+async fn delete_rule(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    let db = &state.db;
+    if !FilterRuleEntity::default().with_id(id)._delete(db.get_pool(), db.filter_rules_tbl()).await? {
+        return Err(ApiError::not_found(format!("rule {}", id)));
+    }
+    state.reload().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/rules", get(list_rules).post(create_rule))
+        .route("/rules/:id", get(get_rule).put(update_rule).delete(delete_rule))
+}
@@ -0,0 +1,114 @@
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{database::ScheduleEntity, filter::Schedule};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleResponse {
+    id: u64,
+    name: String,
+    // comma separated weekdays, e.g. "sun,mon,tue,wed,thu"
+    days: String,
+    start: String,
+    end: String,
+    created_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<ScheduleEntity> for ScheduleResponse {
+    fn from(entity: ScheduleEntity) -> Self {
+        ScheduleResponse {
+            id: entity.id(),
+            name: entity.name().to_string(),
+            days: entity.days().to_string(),
+            start: entity.start_time().format("%H:%M").to_string(),
+            end: entity.end_time().format("%H:%M").to_string(),
+            created_at: entity.created_at(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScheduleRequest {
+    name: String,
+    days: String,
+    // "21:00", windows ending before they start run past midnight
+    start: String,
+    end: String,
+}
+
+fn parse_time(field: &'static str, time: &str) -> Result<NaiveTime, ApiError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+        .map_err(|_| ApiError::invalid(field, format!("invalid time {}, expected HH:MM", time)))
+}
+
+impl ScheduleRequest {
+    fn to_schedule(&self) -> Result<Schedule, ApiError> {
+        if self.name.trim().is_empty() {
+            return Err(ApiError::invalid("name", "name must not be empty"));
+        }
+
+        let days = Schedule::parse_days(&self.days).map_err(|err| ApiError::invalid("days", err))?;
+        if days.is_empty() {
+            return Err(ApiError::invalid("days", "at least one weekday is required"));
+        }
+
+        Ok(Schedule::new(
+            self.name.trim(),
+            days,
+            parse_time("start", &self.start)?,
+            parse_time("end", &self.end)?,
+        ))
+    }
+}
+
+async fn list_schedules(State(state): State<ApiState>) -> Result<Json<Vec<ScheduleResponse>>, ApiError> {
+    let db = &state.db;
+    let schedules = ScheduleEntity::_fetch_all(db.get_pool(), db.schedules_tbl()).await?;
+    Ok(Json(schedules.into_iter().map(ScheduleResponse::from).collect()))
+}
+
+async fn create_schedule(
+    State(state): State<ApiState>,
+    payload: Result<Json<ScheduleRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<ScheduleResponse>), ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let id = ScheduleEntity::default()
+        .with_schedule(&request.to_schedule()?)
+        ._insert(db.get_pool(), db.schedules_tbl())
+        .await?;
+
+    let schedule = ScheduleEntity::_fetch_all(db.get_pool(), db.schedules_tbl())
+        .await?
+        .into_iter()
+        .find(|schedule| schedule.id() == id)
+        .ok_or_else(|| ApiError::not_found(format!("schedule {}", id)))?;
+    Ok((StatusCode::CREATED, Json(schedule.into())))
+}
+
+/*
+    Schedules can only be deleted once no rule refers to them anymore
+*/
+async fn delete_schedule(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    let db = &state.db;
+    if !ScheduleEntity::default().with_id(id)._delete(db.get_pool(), db.schedules_tbl()).await? {
+        return Err(ApiError::not_found(format!("schedule {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/schedules", get(list_schedules).post(create_schedule))
+        .route("/schedules/:id", delete(delete_schedule))
+}
@@ -0,0 +1,38 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::TimeDelta;
+use serde::Deserialize;
+
+use crate::stats::{self, QueryStats};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Deserialize)]
+pub struct StatsWindow {
+    // how far back to look, defaults to a day
+    hours: Option<u32>,
+    // length of the top lists
+    limit: Option<u32>,
+}
+
+async fn get_stats(
+    State(state): State<ApiState>,
+    Query(window): Query<StatsWindow>,
+) -> Result<Json<QueryStats>, ApiError> {
+    let hours = window.hours.unwrap_or(24);
+    if hours == 0 || hours > 24 * 31 {
+        return Err(ApiError::invalid("hours", "hours must be between 1 and 744"));
+    }
+
+    let until = chrono::Utc::now().naive_utc();
+    let since = until - TimeDelta::hours(hours as i64);
+    let summary = stats::summary(&state.db, since, until, window.limit.unwrap_or(10).min(100)).await?;
+    Ok(Json(summary))
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new().route("/stats", get(get_stats))
+}
@@ -0,0 +1,101 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{ClientGroupEntity, UpstreamEntity};
+
+use super::{ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct UpstreamResponse {
+    id: u64,
+    address: String,
+    port: u16,
+    // None for the servers of clients outside of any group
+    group: Option<String>,
+    created_at: Option<chrono::NaiveDateTime>,
+}
+
+impl From<UpstreamEntity> for UpstreamResponse {
+    fn from(entity: UpstreamEntity) -> Self {
+        UpstreamResponse {
+            id: entity.id(),
+            address: entity.address().to_string(),
+            port: entity.port(),
+            group: entity.client_group().map(str::to_string),
+            created_at: entity.created_at(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpstreamRequest {
+    address: String,
+    port: Option<u16>,
+    group: Option<String>,
+}
+
+async fn list_upstreams(State(state): State<ApiState>) -> Result<Json<Vec<UpstreamResponse>>, ApiError> {
+    let db = &state.db;
+    let upstreams = UpstreamEntity::_fetch_all(db.get_pool(), db.upstream_servers_tbl()).await?;
+    Ok(Json(upstreams.into_iter().map(UpstreamResponse::from).collect()))
+}
+
+async fn create_upstream(
+    State(state): State<ApiState>,
+    payload: Result<Json<UpstreamRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<UpstreamResponse>), ApiError> {
+    let Json(request) = payload?;
+    let db = &state.db;
+
+    let address: IpAddr = request
+        .address
+        .trim()
+        .parse()
+        .map_err(|_| ApiError::invalid("address", format!("invalid IP address {}", request.address)))?;
+    let port = request.port.unwrap_or(53);
+    if port == 0 {
+        return Err(ApiError::invalid("port", "port must not be 0"));
+    }
+
+    let mut entity = UpstreamEntity::default()
+        .with_address(address.to_string())
+        .with_port(port);
+    if let Some(group) = &request.group {
+        if ClientGroupEntity::_fetch_by_name(db.get_pool(), db.client_groups_tbl(), group).await?.is_none() {
+            return Err(ApiError::invalid("group", format!("unknown client group {}", group)));
+        }
+        entity = entity.with_client_group(group.clone());
+    }
+
+    let id = entity._insert(db.get_pool(), db.upstream_servers_tbl()).await?;
+    state.reload().await?;
+
+    let upstream = UpstreamEntity::_fetch_all(db.get_pool(), db.upstream_servers_tbl())
+        .await?
+        .into_iter()
+        .find(|upstream| upstream.id() == id)
+        .ok_or_else(|| ApiError::not_found(format!("upstream server {}", id)))?;
+    Ok((StatusCode::CREATED, Json(upstream.into())))
+}
+
+async fn delete_upstream(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    let db = &state.db;
+    if !UpstreamEntity::default().with_id(id)._delete(db.get_pool(), db.upstream_servers_tbl()).await? {
+        return Err(ApiError::not_found(format!("upstream server {}", id)));
+    }
+    state.reload().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/upstreams", get(list_upstreams).post(create_upstream))
+        .route("/upstreams/:id", delete(delete_upstream))
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, State},
+    http::StatusCode,
+    routing::{delete, get, put},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::{self, Identity, Role},
+    database::{ApiKeyEntity, UserEntity},
+};
+
+use super::{auth::require_admin, ApiError, ApiState};
+
+#[derive(Debug, Serialize)]
+pub struct UserResponse {
+    id: u64,
+    username: String,
+    role: Role,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl TryFrom<UserEntity> for UserResponse {
+    type Error = ApiError;
+
+    fn try_from(entity: UserEntity) -> Result<Self, Self::Error> {
+        Ok(UserResponse {
+            id: entity.id(),
+            username: entity.username().to_string(),
+            role: entity.role()?,
+            created_at: entity.created_at(),
+            updated_at: entity.updated_at(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserRequest {
+    username: String,
+    password: String,
+    role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserUpdateRequest {
+    // keeps the current password if left out
+    password: Option<String>,
+    role: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiKeyResponse {
+    id: u64,
+    name: String,
+    username: Option<String>,
+    created_at: Option<chrono::NaiveDateTime>,
+    // only ever returned when the key is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRequest {
+    name: String,
+    // the user the key acts as, defaults to the caller
+    username: Option<String>,
+}
+
+fn parse_role(role: &str) -> Result<Role, ApiError> {
+    role.parse().map_err(|err| ApiError::invalid("role", err))
+}
+
+async fn fetch_user(state: &ApiState, username: &str) -> Result<UserResponse, ApiError> {
+    let db = &state.db;
+    UserEntity::_fetch_by_name(db.get_pool(), db.users_tbl(), username)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("user {}", username)))?
+        .try_into()
+}
+
+async fn list_users(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+) -> Result<Json<Vec<UserResponse>>, ApiError> {
+    require_admin(&identity)?;
+    let db = &state.db;
+    let users = UserEntity::_fetch_all(db.get_pool(), db.users_tbl()).await?;
+    Ok(Json(users.into_iter().map(UserResponse::try_from).collect::<Result<_, _>>()?))
+}
+
+async fn create_user(
+    State(state): State<ApiState>,
+    payload: Result<Json<UserRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<UserResponse>), ApiError> {
+    let Json(request) = payload?;
+    let role = parse_role(&request.role)?;
+
+    auth::create_user(&state.db, &request.username, &request.password, role).await?;
+    Ok((StatusCode::CREATED, Json(fetch_user(&state, request.username.trim()).await?)))
+}
+
+async fn update_user(
+    State(state): State<ApiState>,
+    Path(username): Path<String>,
+    payload: Result<Json<UserUpdateRequest>, JsonRejection>,
+) -> Result<Json<UserResponse>, ApiError> {
+    let Json(request) = payload?;
+    let role = parse_role(&request.role)?;
+
+    if !auth::update_user(&state.db, &username, request.password.as_deref(), role).await? {
+        return Err(ApiError::not_found(format!("user {}", username)));
+    }
+    Ok(Json(fetch_user(&state, &username).await?))
+}
+
+async fn delete_user(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+    Path(username): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    // someone has to stay able to manage users
+    if username == identity.username {
+        return Err(ApiError::invalid("username", "you can't delete your own account"));
+    }
+
+    let db = &state.db;
+    if !UserEntity::default()
+        .with_username(username.clone())
+        ._delete(db.get_pool(), db.users_tbl())
+        .await?
+    {
+        return Err(ApiError::not_found(format!("user {}", username)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_api_keys(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+) -> Result<Json<Vec<ApiKeyResponse>>, ApiError> {
+    require_admin(&identity)?;
+    let db = &state.db;
+    let usernames: HashMap<u64, String> = UserEntity::_fetch_all(db.get_pool(), db.users_tbl())
+        .await?
+        .into_iter()
+        .map(|user| (user.id(), user.username().to_string()))
+        .collect();
+
+    let keys = ApiKeyEntity::_fetch_all(db.get_pool(), db.api_keys_tbl()).await?;
+    Ok(Json(
+        keys.into_iter()
+            .map(|key| ApiKeyResponse {
+                id: key.id(),
+                name: key.name().to_string(),
+                username: usernames.get(&key.user_id()).cloned(),
+                created_at: key.created_at(),
+                key: None,
+            })
+            .collect(),
+    ))
+}
+
+async fn create_api_key(
+    State(state): State<ApiState>,
+    Extension(identity): Extension<Identity>,
+    payload: Result<Json<ApiKeyRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<ApiKeyResponse>), ApiError> {
+    let Json(request) = payload?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(ApiError::invalid("name", "name must not be empty"));
+    }
+
+    let username = request.username.unwrap_or(identity.username);
+    let (id, key) = auth::create_api_key(&state.db, &username, name)
+        .await?
+        .ok_or_else(|| ApiError::invalid("username", format!("unknown user {}", username)))?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ApiKeyResponse {
+            id,
+            name: name.to_string(),
+            username: Some(username),
+            created_at: None,
+            key: Some(key),
+        }),
+    ))
+}
+
+async fn delete_api_key(State(state): State<ApiState>, Path(id): Path<u64>) -> Result<StatusCode, ApiError> {
+    let db = &state.db;
+    if !ApiKeyEntity::_delete(db.get_pool(), db.api_keys_tbl(), id).await? {
+        return Err(ApiError::not_found(format!("API key {}", id)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn routes() -> Router<ApiState> {
+    Router::new()
+        .route("/users", get(list_users).post(create_user))
+        .route("/users/:username", put(update_user).delete(delete_user))
+        .route("/keys", get(list_api_keys).post(create_api_key))
+        .route("/keys/:id", delete(delete_api_key))
+}
@@ -0,0 +1,347 @@
+use std::{net::IpAddr, str::FromStr, sync::OnceLock, time::Duration};
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::{ApiKeyEntity, AuditEntity, Database, SessionEntity, UserEntity},
+    error::AuthError,
+};
+
+/*
+    How long a login stays valid
+*/
+const SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/*
+    Admins may change anything, viewers may only look
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = AuthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "viewer" => Ok(Role::Viewer),
+            "admin" => Ok(Role::Admin),
+            _ => Err(AuthError::InvalidAccount(format!("unknown role {}", s))),
+        }
+    }
+}
+
+/*
+    The user a request was authenticated as
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub user_id: u64,
+    pub username: String,
+    pub role: Role,
+}
+
+impl Identity {
+    fn from_user(user: &UserEntity) -> Result<Self, AuthError> {
+        Ok(Identity {
+            user_id: user.id(),
+            username: user.username().to_string(),
+            role: user.role()?,
+        })
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.role >= role
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    // handed out once, only its hash is stored
+    pub token: String,
+    // UTC
+    pub expires_at: chrono::NaiveDateTime,
+    pub identity: Identity,
+}
+
+/*
+    Hashes a password into an argon2 PHC string, with a random salt
+*/
+pub fn hash_password(password: &str) -> Result<String, AuthError> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AuthError::InvalidAccount(format!(
+            "password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|err| AuthError::Hashing(err.to_string()))
+}
+
+fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/*
+    Hash verified for unknown users, so that they take as long to reject as wrong passwords
+*/
+fn dummy_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_HASH.get_or_init(|| hash_password("not the password").unwrap_or_default())
+}
+
+/*
+    argon2 takes a while on purpose, which shouldn't stall the runtime
+*/
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Result<T, AuthError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| AuthError::Hashing(err.to_string()))
+}
+
+/*
+    Random token of 256 bits, hex encoded
+*/
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/*
+    Tokens are random enough to be stored as a plain SHA-256 hash, which is cheap to look up
+*/
+fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+async fn audit(db: &Database, event: &str, username: Option<&str>, client: Option<IpAddr>, detail: Option<&str>) -> Result<(), AuthError> {
+    AuditEntity::new(event, username, client, detail)
+        ._insert(db.get_pool(), db.audit_log_tbl())
+        .await?;
+    Ok(())
+}
+
+pub async fn create_user(db: &Database, username: &str, password: &str, role: Role) -> Result<u64, AuthError> {
+    let username = username.trim();
+    if username.is_empty() || username.chars().any(char::is_whitespace) {
+        return Err(AuthError::InvalidAccount(format!("invalid username {}", username)));
+    }
+
+    let password = password.to_string();
+    let password_hash = blocking(move || hash_password(&password)).await??;
+    Ok(UserEntity::default()
+        .with_username(username.to_string())
+        .with_password_hash(password_hash)
+        .with_role(role)
+        ._insert(db.get_pool(), db.users_tbl())
+        .await?)
+}
+
+/*
+    Changes the role and optionally the password of a user. A new password ends all of their sessions.
+    Returns false if there is no such user.
+*/
+pub async fn update_user(db: &Database, username: &str, password: Option<&str>, role: Role) -> Result<bool, AuthError> {
+    let Some(user) = UserEntity::_fetch_by_name(db.get_pool(), db.users_tbl(), username).await? else {
+        return Ok(false);
+    };
+
+    let mut updated = user.clone().with_role(role);
+    if let Some(password) = password {
+        let password = password.to_string();
+        updated = updated.with_password_hash(blocking(move || hash_password(&password)).await??);
+        SessionEntity::_delete_for_user(db.get_pool(), db.sessions_tbl(), user.id()).await?;
+    }
+    Ok(updated._update(db.get_pool(), db.users_tbl()).await?)
+}
+
+/*
+    Checks a password without logging in, e.g. before letting a user change it
+*/
+pub async fn check_password(db: &Database, username: &str, password: &str) -> Result<bool, AuthError> {
+    let Some(user) = UserEntity::_fetch_by_name(db.get_pool(), db.users_tbl(), username).await? else {
+        return Ok(false);
+    };
+
+    let hash = user.password_hash().to_string();
+    let password = password.to_string();
+    blocking(move || verify_password(&password, &hash)).await
+}
+
+/*
+    Checks the credentials and starts a session. Every attempt ends up in the audit log.
+    Returns None if the credentials are wrong.
+*/
+pub async fn login(db: &Database, username: &str, password: &str, client: Option<IpAddr>) -> Result<Option<Session>, AuthError> {
+    let user = UserEntity::_fetch_by_name(db.get_pool(), db.users_tbl(), username).await?;
+
+    let hash = user
+        .as_ref()
+        .map_or_else(|| dummy_hash().to_string(), |user| user.password_hash().to_string());
+    let password = password.to_string();
+    let verified = blocking(move || verify_password(&password, &hash)).await?;
+
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
+            let reason = if user.is_some() { "wrong password" } else { "unknown user" };
+            log::warn!("Failed login of {} from {:?}: {}", username, client, reason);
+            audit(db, "login_failed", Some(username), client, Some(reason)).await?;
+            return Ok(None);
+        }
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+    SessionEntity::_delete_expired(db.get_pool(), db.sessions_tbl(), now).await?;
+
+    let token = generate_token();
+    let expires_at = now + SESSION_LIFETIME;
+    SessionEntity::new(user.id(), hash_token(&token), expires_at)
+        ._insert(db.get_pool(), db.sessions_tbl())
+        .await?;
+    audit(db, "login", Some(user.username()), client, None).await?;
+
+    Ok(Some(Session {
+        token,
+        expires_at,
+        identity: Identity::from_user(&user)?,
+    }))
+}
+
+/*
+    Ends the session of a token. Returns false if there was no such session.
+*/
+pub async fn logout(db: &Database, token: &str) -> Result<bool, AuthError> {
+    Ok(SessionEntity::_delete_by_token(db.get_pool(), db.sessions_tbl(), &hash_token(token)).await?)
+}
+
+/*
+    Looks up who a session token or API key belongs to
+*/
+pub async fn authenticate(db: &Database, token: &str) -> Result<Option<Identity>, AuthError> {
+    let token_hash = hash_token(token);
+    let now = chrono::Utc::now().naive_utc();
+
+    let mut user = UserEntity::_fetch_by_session(db.get_pool(), db.users_tbl(), db.sessions_tbl(), &token_hash, now).await?;
+    if user.is_none() {
+        user = UserEntity::_fetch_by_api_key(db.get_pool(), db.users_tbl(), db.api_keys_tbl(), &token_hash).await?;
+    }
+
+    user.as_ref().map(Identity::from_user).transpose()
+}
+
+/*
+    Creates an API key acting as the given user, returning its id and the key itself,
+    which can't be retrieved later on. Returns None if there is no such user.
+*/
+pub async fn create_api_key(db: &Database, username: &str, name: &str) -> Result<Option<(u64, String)>, AuthError> {
+    let Some(user) = UserEntity::_fetch_by_name(db.get_pool(), db.users_tbl(), username).await? else {
+        return Ok(None);
+    };
+
+    let key = generate_token();
+    let id = ApiKeyEntity::new(user.id(), name.to_string(), hash_token(&key))
+        ._insert(db.get_pool(), db.api_keys_tbl())
+        .await?;
+    Ok(Some((id, key)))
+}
+
+/*
+    Creates the user "admin" with the given password if there are no users yet, returning
+    whether it did. Nobody could log in without users, so then a password is required.
+*/
+pub async fn ensure_admin(db: &Database, password: Option<&str>) -> Result<bool, AuthError> {
+    if UserEntity::_count(db.get_pool(), db.users_tbl()).await? > 0 {
+        return Ok(false);
+    }
+
+    let Some(password) = password else {
+        return Err(AuthError::InvalidAccount("there are no users yet, the first admin needs a password".to_string()));
+    };
+    create_user(db, "admin", password, Role::Admin).await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_login() {
+        let db = Database::init_mem().await.unwrap();
+        let client: IpAddr = "192.168.1.20".parse().unwrap();
+
+        assert!(create_user(&db, "kim", "short", Role::Viewer).await.is_err());
+        create_user(&db, "kim", "correct horse", Role::Viewer).await.unwrap();
+
+        assert!(login(&db, "kim", "wrong horse", Some(client)).await.unwrap().is_none());
+        assert!(login(&db, "lee", "correct horse", None).await.unwrap().is_none());
+        let failed = AuditEntity::_fetch_recent(db.get_pool(), db.audit_log_tbl(), Some("login_failed"), 10)
+            .await
+            .unwrap();
+        assert_eq!(failed.len(), 2);
+        assert_eq!(failed[1].username(), Some("kim"));
+        assert_eq!(failed[1].client(), Some("192.168.1.20"));
+        assert_eq!(failed[1].detail(), Some("wrong password"));
+
+        let session = login(&db, "kim", "correct horse", Some(client)).await.unwrap().unwrap();
+        let identity = authenticate(&db, &session.token).await.unwrap().unwrap();
+        assert_eq!(identity.username, "kim");
+        assert!(identity.has_role(Role::Viewer));
+        assert!(!identity.has_role(Role::Admin));
+
+        // API keys act as their user, changing the password ends sessions
+        let (_, key) = create_api_key(&db, "kim", "backup script").await.unwrap().unwrap();
+        assert!(update_user(&db, "kim", Some("battery staple"), Role::Admin).await.unwrap());
+        assert!(authenticate(&db, &session.token).await.unwrap().is_none());
+        assert_eq!(authenticate(&db, &key).await.unwrap().unwrap().role, Role::Admin);
+
+        assert!(check_password(&db, "kim", "battery staple").await.unwrap());
+        let session = login(&db, "kim", "battery staple", None).await.unwrap().unwrap();
+        assert!(logout(&db, &session.token).await.unwrap());
+        assert!(authenticate(&db, &session.token).await.unwrap().is_none());
+
+        assert!(!ensure_admin(&db, None).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ensure_admin() {
+        let db = Database::init_mem().await.unwrap();
+        assert!(ensure_admin(&db, None).await.is_err());
+        assert!(ensure_admin(&db, Some("correct horse")).await.unwrap());
+        assert!(login(&db, "admin", "correct horse", None).await.unwrap().is_some());
+        // the password only counts as long as there are no users
+        assert!(!ensure_admin(&db, Some("battery staple")).await.unwrap());
+    }
+}
@@ -0,0 +1,6 @@
+mod auth;
+
+pub use auth::{
+    authenticate, check_password, create_api_key, create_user, ensure_admin, login, logout,
+    update_user, Identity, Role,
+};
@@ -0,0 +1,64 @@
+use std::{error::Error, path::PathBuf};
+
+use clap::Subcommand;
+use tinydns::{
+    database::{ClientGroupEntity, Database, FilterRuleEntity},
+    error::RuleError,
+    filter::{ListFormat, RuleAction, RuleKind},
+};
+
+#[derive(Subcommand)]
+pub enum ListCommand {
+    /// Import the domains of a blocklist file as exact block rules
+    Import {
+        file: PathBuf,
+        /// hosts, plain, adblock or auto
+        #[arg(long, default_value = "auto")]
+        format: String,
+        /// Client group the rules apply to, instead of all clients
+        #[arg(long)]
+        group: Option<String>,
+    },
+}
+
+pub async fn run(db: &Database, command: ListCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        ListCommand::Import { file, format, group } => {
+            let format: ListFormat = format.parse()?;
+            if let Some(group) = &group {
+                if ClientGroupEntity::_fetch_by_name(db.get_pool(), db.client_groups_tbl(), group).await?.is_none() {
+                    return Err(RuleError(format!("unknown client group {}", group)).into());
+                }
+            }
+
+            let comment = format!("imported from {}", file.display());
+            let content = tokio::fs::read_to_string(&file).await?;
+            let rules: Vec<_> = content
+                .lines()
+                .flat_map(|line| format.parse_line(line))
+                .map(|domain| {
+                    let rule = FilterRuleEntity::default()
+                        .with_action(RuleAction::Block)
+                        .with_kind(RuleKind::Exact)
+                        .with_pattern(domain)
+                        .with_comment(comment.clone());
+                    match &group {
+                        Some(group) => rule.with_client_group(group.clone()),
+                        None => rule,
+                    }
+                })
+                .collect();
+
+            let inserted = FilterRuleEntity::_insert_batch(&rules, db.get_pool(), db.filter_rules_tbl()).await?;
+            println!(
+                "Imported {} of {} domains from {} ({} were blocked already)",
+                inserted,
+                rules.len(),
+                file.display(),
+                rules.len() as u64 - inserted
+            );
+        }
+    }
+
+    Ok(())
+}
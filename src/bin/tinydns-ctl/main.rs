@@ -0,0 +1,75 @@
+use std::{error::Error, path::Path, process::ExitCode, sync::Arc};
+
+use clap::{Parser, Subcommand};
+use tinydns::{database::Database, filter::RuleAction};
+
+mod lists;
+mod querylog;
+mod records;
+mod rules;
+mod stats;
+
+/*
+    Manages tinydns by working on its database directly. A running server notices changes to
+    rules, groups and upstream servers within a few seconds, records take effect right away.
+*/
+#[derive(Parser)]
+#[command(name = "tinydns-ctl", version, about = "Manage a tinydns server through its database")]
+struct Cli {
+    /// SQLite database of the server
+    #[arg(long, global = true, default_value = "sqlite.db")]
+    db: String,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Local DNS records
+    #[command(subcommand)]
+    Record(records::RecordCommand),
+    /// Rules blocking domains
+    #[command(subcommand)]
+    Block(rules::RuleCommand),
+    /// Rules allowing domains despite blocklists and block rules
+    #[command(subcommand)]
+    Allow(rules::RuleCommand),
+    /// Blocklists
+    #[command(subcommand)]
+    List(lists::ListCommand),
+    /// Statistics computed from the query log
+    #[command(subcommand)]
+    Stats(stats::StatsCommand),
+    /// Handled queries
+    #[command(subcommand, name = "query-log")]
+    QueryLog(querylog::QueryLogCommand),
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    // don't create an empty database next to the real one because of a typo
+    if !Path::new(&cli.db).exists() {
+        return Err(format!("database {} not found", cli.db).into());
+    }
+    let db = Arc::new(Database::init(&cli.db).await?);
+
+    match cli.command {
+        Command::Record(command) => records::run(db, command).await,
+        Command::Block(command) => rules::run(&db, RuleAction::Block, command).await,
+        Command::Allow(command) => rules::run(&db, RuleAction::Allow, command).await,
+        Command::List(command) => lists::run(&db, command).await,
+        Command::Stats(command) => stats::run(&db, command).await,
+        Command::QueryLog(command) => querylog::run(&db, command).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
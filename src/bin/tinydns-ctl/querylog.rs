@@ -0,0 +1,92 @@
+use std::{error::Error, time::Duration};
+
+use clap::Subcommand;
+use tinydns::{
+    database::{Database, QueryLogEntity},
+    protocol::packet::{flags::ResponseCode, type_mnemonic},
+};
+
+// how often to look for new entries when following the log
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+// most entries printed per interval when following
+const FOLLOW_BATCH: u32 = 1000;
+
+#[derive(Subcommand)]
+pub enum QueryLogCommand {
+    /// Show the latest queries
+    Tail {
+        /// Number of queries to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        lines: u32,
+        /// Keep printing queries as they are logged
+        #[arg(short, long)]
+        follow: bool,
+        #[arg(long)]
+        client: Option<String>,
+        /// Part of the queried name
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long, value_parser = ["local", "cache", "upstream", "blocked"])]
+        source: Option<String>,
+    },
+}
+
+fn print_entry(entry: &QueryLogEntity) {
+    println!(
+        "{}  {:<15}  {}  {}  {}  {}  {:.1}ms",
+        entry.timestamp().format("%Y-%m-%d %H:%M:%S"),
+        entry.client(),
+        entry.qname(),
+        type_mnemonic(entry.qtype()),
+        ResponseCode::from_u16(entry.rcode()).mnemonic(),
+        entry.answered_from().unwrap_or("-"),
+        entry.latency().as_secs_f64() * 1000.0
+    );
+}
+
+pub async fn run(db: &Database, command: QueryLogCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        QueryLogCommand::Tail { lines, follow, client, name, source } => {
+            let search = |after_id, limit| {
+                QueryLogEntity::_search(
+                    db.get_pool(),
+                    db.query_log_tbl(),
+                    client.as_deref(),
+                    name.as_deref(),
+                    source.as_deref(),
+                    after_id,
+                    limit,
+                )
+            };
+
+            // newest first
+            let entries = search(None, lines).await?;
+            let mut last_id = match entries.first() {
+                Some(entry) => entry.id(),
+                // only follow what is logged from now on, even if nothing matched so far
+                None => QueryLogEntity::_fetch_recent(db.get_pool(), db.query_log_tbl(), 1)
+                    .await?
+                    .first()
+                    .map_or(0, QueryLogEntity::id),
+            };
+            for entry in entries.iter().rev() {
+                print_entry(entry);
+            }
+
+            if follow {
+                loop {
+                    tokio::time::sleep(FOLLOW_INTERVAL).await;
+                    let entries = search(Some(last_id), FOLLOW_BATCH).await?;
+                    if let Some(entry) = entries.first() {
+                        last_id = entry.id();
+                    }
+                    for entry in entries.iter().rev() {
+                        print_entry(entry);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
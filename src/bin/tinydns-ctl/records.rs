@@ -0,0 +1,101 @@
+use std::{error::Error, sync::Arc};
+
+use clap::Subcommand;
+use tinydns::{
+    database::{Database, RecordEntity, RecordQuery},
+    error::PresentationError,
+    filter::{is_valid_domain, normalize_domain},
+    nameserver::Nameserver,
+    protocol::packet::{parse_type_mnemonic, type_mnemonic, RData, RecordType},
+};
+
+#[derive(Subcommand)]
+pub enum RecordCommand {
+    /// Add a record, e.g. `record add example.com MX 10 mail.example.com.`
+    Add {
+        name: String,
+        #[arg(value_name = "TYPE")]
+        record_type: String,
+        /// Value in presentation format, as in zone files
+        #[arg(required = true)]
+        value: Vec<String>,
+        #[arg(long)]
+        ttl: Option<u32>,
+        /// Store the record without serving it
+        #[arg(long)]
+        inactive: bool,
+    },
+    /// List records
+    List {
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(long = "type", value_name = "TYPE")]
+        record_type: Option<String>,
+    },
+    /// Remove a record by id
+    Rm { id: u64 },
+}
+
+/*
+    Converts a record given in presentation format into what the database stores
+*/
+fn to_entity(name: &str, record_type: &str, value: &str) -> Result<RecordEntity, Box<dyn Error>> {
+    let name = normalize_domain(name);
+    if !is_valid_domain(&name) {
+        return Err(PresentationError(format!("Invalid domain name {}", name)).into());
+    }
+
+    let rdata = RData::parse(parse_type_mnemonic(record_type)?, value)?;
+    Ok(RecordEntity::default().with_domain_name(name).with_rdata(rdata)?)
+}
+
+fn print_record(record: &RecordEntity) -> Result<(), Box<dyn Error>> {
+    println!(
+        "{}\t{}.\t{}\tIN\t{}\t{}{}",
+        record.id(),
+        record.domain_name(),
+        record.ttl(),
+        type_mnemonic(record.record_type()),
+        record.rdata()?.data_to_string(),
+        if record.is_active() { "" } else { "\t; inactive" }
+    );
+    Ok(())
+}
+
+pub async fn run(db: Arc<Database>, command: RecordCommand) -> Result<(), Box<dyn Error>> {
+    let nameserver = Nameserver::new(db);
+
+    match command {
+        RecordCommand::Add { name, record_type, value, ttl, inactive } => {
+            let mut record = to_entity(&name, &record_type, &value.join(" "))?.with_active(!inactive);
+            if let Some(ttl) = ttl {
+                record = record.with_ttl(ttl);
+            }
+
+            let id = nameserver.insert_record(record).await?;
+            if let Some(record) = nameserver.query_record(&RecordQuery::default().with_id(id).with_inactive()).await? {
+                print_record(&record)?;
+            }
+        }
+        RecordCommand::List { name, record_type } => {
+            let mut query = RecordQuery::default().with_all().with_inactive();
+            if let Some(name) = name {
+                query = query.with_domain_name(normalize_domain(&name));
+            }
+            if let Some(record_type) = record_type {
+                query = query.with_record_type(RecordType::from(parse_type_mnemonic(&record_type)?));
+            }
+
+            for record in nameserver.query_records(&query).await? {
+                print_record(&record)?;
+            }
+        }
+        RecordCommand::Rm { id } => {
+            if !nameserver.delete_record(id).await? {
+                return Err(format!("There is no record {}", id).into());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::error::Error;
+
+use clap::Subcommand;
+use tinydns::{
+    database::{ClientGroupEntity, Database, FilterRuleEntity, ScheduleEntity},
+    error::RuleError,
+    filter::{FilterRule, RuleAction, RuleKind, RuleSet},
+};
+
+#[derive(Subcommand)]
+pub enum RuleCommand {
+    /// Add a rule, e.g. `block add '*.ads.example.com' --kind wildcard`
+    Add {
+        pattern: String,
+        /// exact, wildcard or regex
+        #[arg(long, default_value = "exact")]
+        kind: String,
+        /// Client group the rule applies to, instead of all clients
+        #[arg(long)]
+        group: Option<String>,
+        /// Name of the schedule limiting when the rule applies
+        #[arg(long)]
+        schedule: Option<String>,
+        #[arg(long)]
+        comment: Option<String>,
+    },
+    /// Remove a rule by id or by pattern
+    Rm {
+        rule: String,
+        /// Client group of the rule when removing by pattern
+        #[arg(long)]
+        group: Option<String>,
+    },
+    /// List rules
+    List {
+        #[arg(long)]
+        group: Option<String>,
+    },
+}
+
+async fn to_entity(
+    db: &Database,
+    action: RuleAction,
+    pattern: String,
+    kind: &str,
+    group: Option<String>,
+    schedule: Option<String>,
+    comment: Option<String>,
+) -> Result<FilterRuleEntity, Box<dyn Error>> {
+    let kind: RuleKind = kind.parse()?;
+    // reject patterns the server would fail to compile
+    RuleSet::new().add_rule(FilterRule {
+        id: 0,
+        action,
+        kind,
+        pattern: pattern.clone(),
+        schedule: None,
+    })?;
+
+    let mut entity = FilterRuleEntity::default()
+        .with_action(action)
+        .with_kind(kind)
+        .with_pattern(pattern);
+    if let Some(comment) = comment {
+        entity = entity.with_comment(comment);
+    }
+
+    if let Some(group) = group {
+        if ClientGroupEntity::_fetch_by_name(db.get_pool(), db.client_groups_tbl(), &group).await?.is_none() {
+            return Err(RuleError(format!("unknown client group {}", group)).into());
+        }
+        entity = entity.with_client_group(group);
+    }
+
+    if let Some(schedule) = schedule {
+        let schedule = ScheduleEntity::_fetch_all(db.get_pool(), db.schedules_tbl())
+            .await?
+            .into_iter()
+            .find(|entity| entity.name() == schedule)
+            .ok_or_else(|| RuleError(format!("unknown schedule {}", schedule)))?;
+        entity = entity.with_schedule_id(schedule.id());
+    }
+
+    Ok(entity)
+}
+
+/*
+    Rules with the given action, of a single group if one is given
+*/
+async fn fetch_rules(db: &Database, action: RuleAction, group: Option<&str>) -> Result<Vec<FilterRuleEntity>, Box<dyn Error>> {
+    Ok(FilterRuleEntity::_fetch_all(db.get_pool(), db.filter_rules_tbl(), true)
+        .await?
+        .into_iter()
+        .filter(|rule| rule.action() == action.as_str())
+        .filter(|rule| group.is_none() || rule.client_group() == group)
+        .collect())
+}
+
+fn print_rule(rule: &FilterRuleEntity) {
+    println!(
+        "{}\t{}\t{}\t{}{}{}",
+        rule.id(),
+        rule.kind(),
+        rule.pattern(),
+        rule.client_group().unwrap_or("-"),
+        rule.comment().map(|comment| format!("\t; {}", comment)).unwrap_or_default(),
+        if rule.is_active() { "" } else { "\t(inactive)" }
+    );
+}
+
+pub async fn run(db: &Database, action: RuleAction, command: RuleCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        RuleCommand::Add { pattern, kind, group, schedule, comment } => {
+            let entity = to_entity(db, action, pattern, &kind, group, schedule, comment).await?;
+            let id = entity._insert(db.get_pool(), db.filter_rules_tbl()).await?;
+            if let Some(rule) = FilterRuleEntity::_fetch_one(db.get_pool(), db.filter_rules_tbl(), id).await? {
+                print_rule(&rule);
+            }
+        }
+        RuleCommand::Rm { rule, group } => {
+            let rules: Vec<_> = match rule.parse::<u64>() {
+                Ok(id) => fetch_rules(db, action, None).await?.into_iter().filter(|rule| rule.id() == id).collect(),
+                // without a group only rules for all clients are matched by pattern
+                Err(_) => fetch_rules(db, action, None)
+                    .await?
+                    .into_iter()
+                    .filter(|entity| entity.pattern() == rule && entity.client_group() == group.as_deref())
+                    .collect(),
+            };
+            if rules.is_empty() {
+                return Err(RuleError(format!("there is no {} rule {}", action.as_str(), rule)).into());
+            }
+
+            for rule in rules {
+                rule._delete(db.get_pool(), db.filter_rules_tbl()).await?;
+                print_rule(&rule);
+            }
+        }
+        RuleCommand::List { group } => {
+            for rule in fetch_rules(db, action, group.as_deref()).await? {
+                print_rule(&rule);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+
+use clap::Subcommand;
+use tinydns::{
+    database::Database,
+    stats::{self, TopEntry},
+};
+
+#[derive(Subcommand)]
+pub enum StatsCommand {
+    /// Totals and the most queried domains and most active clients
+    Top {
+        /// Number of hours to look back
+        #[arg(long, default_value_t = 24, value_parser = clap::value_parser!(u32).range(1..=744))]
+        hours: u32,
+        /// Number of entries per list
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+    },
+}
+
+fn print_top(title: &str, entries: &[TopEntry]) {
+    println!("\n{}", title);
+    for entry in entries {
+        println!("{:>10}  {}", entry.count, entry.name);
+    }
+}
+
+pub async fn run(db: &Database, command: StatsCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        StatsCommand::Top { hours, limit } => {
+            let until = chrono::Utc::now().naive_utc();
+            let since = until - chrono::Duration::hours(hours as i64);
+            let stats = stats::summary(db, since, until, limit).await?;
+
+            let totals = &stats.totals;
+            println!("Last {} hours", hours);
+            println!(
+                "{} queries: {} local, {} cached, {} upstream, {} blocked ({:.1}%), {} failed",
+                totals.queries,
+                totals.local,
+                totals.cached,
+                totals.upstream,
+                totals.blocked,
+                totals.blocked_percentage,
+                totals.failed
+            );
+            print_top("Top domains", &stats.top_domains);
+            print_top("Top blocked domains", &stats.top_blocked_domains);
+            print_top("Top clients", &stats.top_clients);
+        }
+    }
+
+    Ok(())
+}
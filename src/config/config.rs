@@ -0,0 +1,671 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::LevelFilter;
+use log4rs::{
+    append::{console::ConsoleAppender, file::FileAppender},
+    config::{Appender, Root},
+    encode::pattern::PatternEncoder,
+};
+use serde::Deserialize;
+
+use crate::{
+    database::Database,
+    error::ConfigError,
+    filter::{BlockMode, Blocklist, ListFormat},
+    metrics::Metrics,
+    nameserver::{Nameserver, RRsetOrder},
+    protocol::packet::EDNS_DEFAULT_PAYLOAD_SIZE,
+    querylog::{QueryLog, QueryLogConfig},
+    resolver::Resolver,
+    server::{Backpressure, ServerConfig},
+};
+
+pub const DEFAULT_CONFIG_PATH: &str = "config/tinydns.toml";
+
+// a century, far beyond any sensible retention but safe from overflowing durations
+const MAX_QUERY_LOG_AGE_DAYS: u64 = 36500;
+
+const LOG_PATTERN: &str = "{d(%Y-%m-%d %H:%M:%S)} [{l}] {t} - {m}{n}";
+
+fn invalid(key: &str, reason: impl Display) -> ConfigError {
+    ConfigError(format!("{}: {}", key, reason))
+}
+
+/*
+    Parses an upstream server given as an IP address with an optional port,
+    e.g. "9.9.9.9", "9.9.9.9:53", "2620:fe::fe" or "[2620:fe::fe]:53"
+*/
+fn parse_upstream(server: &str) -> Option<(String, u16)> {
+    let (address, port) = match server.parse::<SocketAddr>() {
+        Ok(addr) => (addr.ip(), addr.port()),
+        Err(_) => (server.parse::<IpAddr>().ok()?, 53),
+    };
+    (port != 0).then(|| (address.to_string(), port))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DatabaseSection {
+    path: String,
+}
+
+impl Default for DatabaseSection {
+    fn default() -> Self {
+        DatabaseSection {
+            path: "sqlite.db".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LoggingSection {
+    // log4rs configuration file, which makes level and file irrelevant
+    config: Option<PathBuf>,
+    level: String,
+    // also log to this file
+    file: Option<PathBuf>,
+}
+
+impl Default for LoggingSection {
+    fn default() -> Self {
+        LoggingSection {
+            config: None,
+            level: "info".to_string(),
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSection {
+    listen_addr: IpAddr,
+    udp_port: u16,
+    tcp_port: u16,
+    tcp_idle_timeout_secs: u64,
+    udp_payload_size: u16,
+    max_concurrent_queries: usize,
+    max_tcp_connections: usize,
+    backpressure: Backpressure,
+    reload_interval_secs: u64,
+}
+
+impl Default for ServerSection {
+    fn default() -> Self {
+        ServerSection {
+            listen_addr: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            udp_port: 53,
+            tcp_port: 53,
+            tcp_idle_timeout_secs: 10,
+            udp_payload_size: EDNS_DEFAULT_PAYLOAD_SIZE,
+            max_concurrent_queries: 128,
+            max_tcp_connections: 64,
+            backpressure: Backpressure::Wait,
+            reload_interval_secs: 5,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MetricsSection {
+    // serve Prometheus metrics on /metrics
+    enabled: bool,
+    port: u16,
+}
+
+impl Default for MetricsSection {
+    fn default() -> Self {
+        MetricsSection {
+            enabled: true,
+            port: 9153,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AdminSection {
+    // serve the admin API and the dashboard
+    enabled: bool,
+    port: u16,
+}
+
+impl Default for AdminSection {
+    fn default() -> Self {
+        AdminSection {
+            enabled: true,
+            port: 8053,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ResolverSection {
+    // used while the database has no upstream servers for clients outside of any group
+    upstreams: Vec<String>,
+    // RRsets kept in the response cache, zero disables caching
+    cache_size: usize,
+}
+
+impl Default for ResolverSection {
+    fn default() -> Self {
+        ResolverSection {
+            upstreams: Vec::new(),
+            cache_size: 10000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct NameserverSection {
+    rrset_order: RRsetOrder,
+}
+
+impl Default for NameserverSection {
+    fn default() -> Self {
+        NameserverSection {
+            rrset_order: RRsetOrder::RoundRobin,
+        }
+    }
+}
+
+/*
+    How blocked queries are answered, see BlockMode
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+enum BlockModeName {
+    #[default]
+    #[serde(rename = "nxdomain")]
+    NxDomain,
+    #[serde(rename = "nodata")]
+    NoData,
+    #[serde(rename = "refused")]
+    Refused,
+    #[serde(rename = "null")]
+    NullIp,
+    #[serde(rename = "sinkhole")]
+    Sinkhole,
+}
+
+fn to_block_mode(mode: BlockModeName, ttl: u32, ipv4: Option<Ipv4Addr>, ipv6: Option<Ipv6Addr>) -> BlockMode {
+    match mode {
+        BlockModeName::NxDomain => BlockMode::NxDomain,
+        BlockModeName::NoData => BlockMode::NoData,
+        BlockModeName::Refused => BlockMode::Refused,
+        BlockModeName::NullIp => BlockMode::NullIp { ttl },
+        BlockModeName::Sinkhole => BlockMode::Sinkhole { ipv4, ipv6, ttl },
+    }
+}
+
+/*
+    Sinkhole mode needs at least one address, the other modes none
+*/
+fn check_sinkhole(
+    key: &str,
+    mode: BlockModeName,
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+) -> Result<(), ConfigError> {
+    let has_sinkhole = ipv4.is_some() || ipv6.is_some();
+    if mode == BlockModeName::Sinkhole && !has_sinkhole {
+        return Err(invalid(key, "mode sinkhole needs sinkhole_ipv4 or sinkhole_ipv6"));
+    }
+    if mode != BlockModeName::Sinkhole && has_sinkhole {
+        return Err(invalid(key, "sinkhole addresses are only used with mode \"sinkhole\""));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListSection {
+    path: PathBuf,
+    #[serde(default = "default_list_format")]
+    format: ListFormat,
+    // client groups the list blocks for, empty for all clients
+    #[serde(default)]
+    groups: Vec<String>,
+    // answer domains of this list differently than blocking.mode says
+    mode: Option<BlockModeName>,
+    // defaults to blocking.ttl
+    ttl: Option<u32>,
+    sinkhole_ipv4: Option<Ipv4Addr>,
+    sinkhole_ipv6: Option<Ipv6Addr>,
+}
+
+fn default_list_format() -> ListFormat {
+    ListFormat::Auto
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct BlockingSection {
+    mode: BlockModeName,
+    // TTL of the addresses blocked queries are answered with
+    ttl: u32,
+    sinkhole_ipv4: Option<Ipv4Addr>,
+    sinkhole_ipv6: Option<Ipv6Addr>,
+    lists: Vec<ListSection>,
+}
+
+impl Default for BlockingSection {
+    fn default() -> Self {
+        BlockingSection {
+            mode: BlockModeName::NxDomain,
+            ttl: 60,
+            sinkhole_ipv4: None,
+            sinkhole_ipv6: None,
+            lists: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QueryLogSection {
+    enabled: bool,
+    max_age_days: u64,
+    max_rows: u64,
+}
+
+impl Default for QueryLogSection {
+    fn default() -> Self {
+        QueryLogSection {
+            enabled: true,
+            max_age_days: 7,
+            max_rows: 1_000_000,
+        }
+    }
+}
+
+/*
+    Contents of the TOML configuration file. Everything has a default,
+    so an empty file configures the same server as no file at all.
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    database: DatabaseSection,
+    logging: LoggingSection,
+    server: ServerSection,
+    metrics: MetricsSection,
+    admin: AdminSection,
+    resolver: ResolverSection,
+    nameserver: NameserverSection,
+    blocking: BlockingSection,
+    query_log: QueryLogSection,
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Config = toml::from_str(s).map_err(|err| ConfigError(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| ConfigError(format!("can't read {}: {}", path.display(), err)))?;
+
+        content
+            .parse()
+            .map_err(|ConfigError(reason)| ConfigError(format!("{}: {}", path.display(), reason)))
+    }
+
+    /*
+        Checks what the types alone don't, reporting the first invalid value
+    */
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database.path.is_empty() {
+            return Err(invalid("database.path", "must not be empty"));
+        }
+
+        self.log_level()?;
+
+        let server = &self.server;
+        for (key, port) in [
+            ("server.udp_port", server.udp_port),
+            ("server.tcp_port", server.tcp_port),
+            ("metrics.port", self.metrics.port),
+            ("admin.port", self.admin.port),
+        ] {
+            if port == 0 {
+                return Err(invalid(key, "port must not be 0"));
+            }
+        }
+        if server.udp_payload_size < 512 {
+            return Err(invalid("server.udp_payload_size", "must be at least 512"));
+        }
+        if server.max_concurrent_queries == 0 {
+            return Err(invalid("server.max_concurrent_queries", "must be at least 1"));
+        }
+        if server.max_tcp_connections == 0 {
+            return Err(invalid("server.max_tcp_connections", "must be at least 1"));
+        }
+        if server.tcp_idle_timeout_secs == 0 {
+            return Err(invalid("server.tcp_idle_timeout_secs", "must be at least 1"));
+        }
+        if server.reload_interval_secs == 0 {
+            return Err(invalid("server.reload_interval_secs", "must be at least 1"));
+        }
+
+        for upstream in &self.resolver.upstreams {
+            if parse_upstream(upstream).is_none() {
+                return Err(invalid(
+                    "resolver.upstreams",
+                    format!("{} is not an IP address with an optional port", upstream),
+                ));
+            }
+        }
+
+        let blocking = &self.blocking;
+        check_sinkhole("blocking", blocking.mode, blocking.sinkhole_ipv4, blocking.sinkhole_ipv6)?;
+        for list in &blocking.lists {
+            let key = format!("blocking.lists ({})", list.path.display());
+            match list.mode {
+                Some(mode) => check_sinkhole(&key, mode, list.sinkhole_ipv4, list.sinkhole_ipv6)?,
+                None if list.ttl.is_some() || list.sinkhole_ipv4.is_some() || list.sinkhole_ipv6.is_some() => {
+                    return Err(invalid(&key, "ttl and sinkhole addresses are only used along with a mode"));
+                }
+                None => {}
+            }
+        }
+
+        if !(1..=MAX_QUERY_LOG_AGE_DAYS).contains(&self.query_log.max_age_days) {
+            return Err(invalid(
+                "query_log.max_age_days",
+                format!("must be between 1 and {}", MAX_QUERY_LOG_AGE_DAYS),
+            ));
+        }
+        if blocking.lists.iter().flat_map(|list| &list.groups).any(|group| group.is_empty()) {
+            return Err(invalid("blocking.lists", "group names must not be empty"));
+        }
+
+        if self.query_log.max_rows == 0 {
+            return Err(invalid("query_log.max_rows", "must be at least 1"));
+        }
+
+        Ok(())
+    }
+
+    fn log_level(&self) -> Result<LevelFilter, ConfigError> {
+        self.logging.level.parse().map_err(|_| {
+            invalid(
+                "logging.level",
+                format!("unknown level {}, expected off, error, warn, info, debug or trace", self.logging.level),
+            )
+        })
+    }
+
+    pub fn database_path(&self) -> &str {
+        &self.database.path
+    }
+
+    pub fn admin_enabled(&self) -> bool {
+        self.admin.enabled
+    }
+
+    /*
+        Sets up logging, either from a log4rs configuration file or to stdout
+        (and a file, if one is given) at the configured level
+    */
+    pub fn init_logging(&self) -> Result<(), ConfigError> {
+        if let Some(path) = &self.logging.config {
+            return log4rs::init_file(path, Default::default())
+                .map_err(|err| invalid("logging.config", format!("{}: {}", path.display(), err)));
+        }
+
+        let stdout = ConsoleAppender::builder()
+            .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+            .build();
+        let mut builder = log4rs::Config::builder().appender(Appender::builder().build("stdout", Box::new(stdout)));
+        let mut root = Root::builder().appender("stdout");
+
+        if let Some(path) = &self.logging.file {
+            let file = FileAppender::builder()
+                .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+                .build(path)
+                .map_err(|err| invalid("logging.file", format!("{}: {}", path.display(), err)))?;
+            builder = builder.appender(Appender::builder().build("file", Box::new(file)));
+            root = root.appender("file");
+        }
+
+        let config = builder
+            .build(root.build(self.log_level()?))
+            .map_err(|err| invalid("logging", err))?;
+        log4rs::init_config(config).map_err(|err| invalid("logging", err))?;
+        Ok(())
+    }
+
+    pub fn resolver(&self, metrics: Option<Arc<Metrics>>) -> Resolver {
+        let mut resolver = Resolver::default().with_cache_capacity(self.resolver.cache_size);
+        for upstream in self.resolver.upstreams.iter().filter_map(|upstream| parse_upstream(upstream)) {
+            resolver = resolver.with_fallback_server(upstream);
+        }
+        if let Some(metrics) = metrics {
+            resolver = resolver.with_metrics(metrics);
+        }
+        resolver
+    }
+
+    pub fn nameserver(&self, db: Arc<Database>) -> Nameserver {
+        Nameserver::new(db).with_rrset_order(self.nameserver.rrset_order)
+    }
+
+    pub fn block_mode(&self) -> BlockMode {
+        let blocking = &self.blocking;
+        to_block_mode(blocking.mode, blocking.ttl, blocking.sinkhole_ipv4, blocking.sinkhole_ipv6)
+    }
+
+    /*
+        How domains of a list are answered, if the list overrides the global block mode
+    */
+    fn list_block_mode(&self, list: &ListSection) -> Option<BlockMode> {
+        let ttl = list.ttl.unwrap_or(self.blocking.ttl);
+        list.mode
+            .map(|mode| to_block_mode(mode, ttl, list.sinkhole_ipv4, list.sinkhole_ipv6))
+    }
+
+    /*
+        Reads the configured blocklists for all clients, failing if any of them can't be read
+    */
+    pub fn blocklist(&self) -> Result<Blocklist, ConfigError> {
+        self.load_lists(|list| list.groups.is_empty())
+    }
+
+    /*
+        Reads the blocklists of every client group some list is for. A group's blocklist
+        also holds the lists for all clients, as it replaces theirs for the group.
+    */
+    pub fn group_blocklists(&self) -> Result<Vec<(String, Blocklist)>, ConfigError> {
+        let mut groups: Vec<&String> = self.blocking.lists.iter().flat_map(|list| &list.groups).collect();
+        groups.sort();
+        groups.dedup();
+
+        groups
+            .into_iter()
+            .map(|group| {
+                let blocklist = self.load_lists(|list| list.groups.is_empty() || list.groups.contains(group))?;
+                Ok((group.clone(), blocklist))
+            })
+            .collect()
+    }
+
+    fn load_lists(&self, include: impl Fn(&ListSection) -> bool) -> Result<Blocklist, ConfigError> {
+        let mut blocklist = Blocklist::new().with_mode(self.block_mode());
+        for list in self.blocking.lists.iter().filter(|list| include(list)) {
+            blocklist
+                .load_file(&list.path, list.format, self.list_block_mode(list))
+                .map_err(|err| invalid("blocking.lists", format!("{}: {}", list.path.display(), err)))?;
+        }
+        Ok(blocklist)
+    }
+
+    pub fn query_log_config(&self) -> QueryLogConfig {
+        QueryLogConfig::default()
+            .with_max_age(Duration::from_secs(self.query_log.max_age_days * 24 * 60 * 60))
+            .with_max_rows(self.query_log.max_rows)
+    }
+
+    /*
+        Builds the server with everything the file configures. Filter rules, client groups
+        and upstream servers stored in the database are applied by ServerConfig::reload.
+        Starts the query log writer, so this has to run inside the tokio runtime.
+    */
+    pub fn server_config(&self, db: Arc<Database>) -> Result<ServerConfig, ConfigError> {
+        let server = &self.server;
+        // nothing records metrics nobody can see
+        let metrics = self.metrics.enabled.then(|| Arc::new(Metrics::new()));
+
+        let mut config = ServerConfig::default()
+            .with_listen_addr(server.listen_addr.to_string())
+            .with_udp_port(server.udp_port)
+            .with_tcp_port(server.tcp_port)
+            .with_tcp_idle_timeout(Duration::from_secs(server.tcp_idle_timeout_secs))
+            .with_udp_payload_size(server.udp_payload_size)
+            .with_max_concurrent_queries(server.max_concurrent_queries)
+            .with_max_tcp_connections(server.max_tcp_connections)
+            .with_backpressure(server.backpressure)
+            .with_reload_interval(Duration::from_secs(server.reload_interval_secs))
+            .with_metrics_port(self.metrics.port)
+            .with_admin_port(self.admin.port)
+            .with_resolver(self.resolver(metrics.clone()))
+            .with_nameserver(self.nameserver(db.clone()))
+            .with_blocklist(self.blocklist()?);
+
+        if let Some(metrics) = metrics {
+            config = config.with_metrics(metrics);
+        }
+
+        for (group, blocklist) in self.group_blocklists()? {
+            config = config.with_group_blocklist(&group, blocklist);
+        }
+
+        if self.query_log.enabled {
+            config = config.with_query_log(QueryLog::start(db, self.query_log_config()));
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        // the example shipped with the repository has to stay valid
+        include_str!("../../config/tinydns.toml").parse::<Config>().unwrap();
+
+        let config: Config = r#"
+            [database]
+            path = "/var/lib/tinydns/tinydns.db"
+
+            [server]
+            listen_addr = "::"
+            udp_port = 5353
+            backpressure = "refuse"
+
+            [resolver]
+            upstreams = ["9.9.9.9", "1.1.1.1:5353", "[2620:fe::fe]:53"]
+
+            [nameserver]
+            rrset_order = "shuffle"
+
+            [blocking]
+            mode = "sinkhole"
+            sinkhole_ipv4 = "192.0.2.1"
+        "#
+        .parse()
+        .unwrap();
+
+        assert_eq!(config.database_path(), "/var/lib/tinydns/tinydns.db");
+        assert_eq!(config.server.udp_port, 5353);
+        // unset values keep their defaults
+        assert_eq!(config.server.tcp_port, 53);
+        assert_eq!(config.server.backpressure, Backpressure::Refuse);
+        assert_eq!(config.nameserver.rrset_order, RRsetOrder::Shuffle);
+        assert_eq!(
+            config.resolver(None).fallback_servers(),
+            vec![
+                ("9.9.9.9".to_string(), 53),
+                ("1.1.1.1".to_string(), 5353),
+                ("2620:fe::fe".to_string(), 53)
+            ]
+        );
+        assert_eq!(
+            config.block_mode(),
+            BlockMode::Sinkhole {
+                ipv4: Some(Ipv4Addr::new(192, 0, 2, 1)),
+                ipv6: None,
+                ttl: 60
+            }
+        );
+
+        let error = |toml: &str| toml.parse::<Config>().unwrap_err().to_string();
+        assert!(error("[server]\nudp_port = 0").contains("server.udp_port"));
+        assert!(error("[admin]\nport = 0").contains("admin.port"));
+        assert!(error("[query_log]\nmax_age_days = 1000000000000000").contains("max_age_days"));
+        assert!(error("[server]\nlisten_addr = \"localhost\"").contains("listen_addr"));
+        assert!(error("[server]\nbackpressure = \"block\"").contains("backpressure"));
+        assert!(error("[server]\nudp_prot = 53").contains("udp_prot"));
+        assert!(error("[resolver]\nupstreams = [\"dns.google\"]").contains("dns.google"));
+        assert!(error("[logging]\nlevel = \"loud\"").contains("logging.level"));
+        assert!(error("[blocking]\nmode = \"sinkhole\"").contains("sinkhole_ipv4"));
+        assert!(error("[[blocking.lists]]\npath = \"games.txt\"\ngroups = [\"\"]").contains("blocking.lists"));
+        assert!(error("[[blocking.lists]]\npath = \"ads.txt\"\nmode = \"sinkhole\"").contains("ads.txt"));
+        assert!(error("[[blocking.lists]]\npath = \"ads.txt\"\nttl = 5").contains("ads.txt"));
+    }
+
+    #[test]
+    fn test_list_block_mode() {
+        let dir = std::env::temp_dir().join(format!("tinydns-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ads.txt"), "ads.example\n").unwrap();
+        std::fs::write(dir.join("malware.txt"), "malware.example\n").unwrap();
+
+        let config: Config = format!(
+            r#"
+            [blocking]
+            mode = "nxdomain"
+            ttl = 30
+
+            [[blocking.lists]]
+            path = "{}"
+            format = "plain"
+            mode = "null"
+
+            [[blocking.lists]]
+            path = "{}"
+            format = "plain"
+            "#,
+            dir.join("ads.txt").display(),
+            dir.join("malware.txt").display()
+        )
+        .parse()
+        .unwrap();
+        let blocklist = config.blocklist().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // the list's own mode wins, its ttl falls back to the global one
+        assert_eq!(blocklist.find("ads.example").unwrap().mode, BlockMode::NullIp { ttl: 30 });
+        assert_eq!(blocklist.find("malware.example").unwrap().mode, BlockMode::NxDomain);
+    }
+}
@@ -0,0 +1,517 @@
+"use strict";
+
+// how often the live query log and the overview are refreshed, in milliseconds
+const QUERYLOG_INTERVAL = 2000;
+const STATS_INTERVAL = 30000;
+// rows kept in the live query log
+const QUERYLOG_ROWS = 500;
+
+const $ = (selector) => document.querySelector(selector);
+
+// session token, kept for as long as the tab is open
+let token = sessionStorage.getItem("token");
+
+/*
+    Calls the admin API, throwing the message of the structured error it returns.
+    Asks to log in again once the session is gone.
+*/
+async function api(method, path, body) {
+    const options = { method, headers: {} };
+    if (body !== undefined) {
+        options.headers["Content-Type"] = "application/json";
+        options.body = JSON.stringify(body);
+    }
+    if (token) {
+        options.headers["Authorization"] = "Bearer " + token;
+    }
+
+    const response = await fetch("/api" + path, options);
+    if (response.status === 401 && path !== "/login") {
+        showLogin();
+    }
+    if (response.status === 204) {
+        return null;
+    }
+    const data = await response.json().catch(() => null);
+    if (!response.ok) {
+        const error = data && data.error;
+        const message = error ? (error.field ? `${error.field}: ${error.message}` : error.message) : response.statusText;
+        throw new Error(message);
+    }
+    return data;
+}
+
+function showError(err) {
+    const box = $("#error");
+    if (err) {
+        box.textContent = err.message || String(err);
+        box.hidden = false;
+    } else {
+        box.hidden = true;
+    }
+}
+
+/*
+    Runs an action, showing what went wrong instead of failing silently
+*/
+async function attempt(action) {
+    try {
+        await action();
+        showError(null);
+    } catch (err) {
+        showError(err);
+    }
+}
+
+/*
+    Creates an element with text content and attributes
+*/
+function el(tag, text, attrs) {
+    const element = document.createElement(tag);
+    if (text !== undefined && text !== null) {
+        element.textContent = text;
+    }
+    Object.entries(attrs || {}).forEach(([name, value]) => element.setAttribute(name, value));
+    return element;
+}
+
+function row(cells, className) {
+    const tr = el("tr", null, className ? { class: className } : {});
+    cells.forEach((cell) => tr.appendChild(cell instanceof Node ? cell : el("td", cell)));
+    return tr;
+}
+
+function button(label, onClick) {
+    const b = el("button", label, { type: "button", class: "link" });
+    b.addEventListener("click", () => attempt(onClick));
+    return b;
+}
+
+function actions(...buttons) {
+    const td = el("td", null, { class: "admin-only" });
+    buttons.forEach((b) => td.appendChild(b));
+    return td;
+}
+
+// timestamps are sent in UTC without a zone
+function parseUtc(timestamp) {
+    return new Date(timestamp + "Z");
+}
+
+function formatNumber(n) {
+    return n.toLocaleString();
+}
+
+/* ---------- login ---------- */
+
+function showLogin() {
+    token = null;
+    sessionStorage.removeItem("token");
+    activeTab = null;
+    document.querySelectorAll(".tab").forEach((tab) => (tab.hidden = true));
+    $("#account").hidden = true;
+    $("#login").hidden = false;
+}
+
+function showAccount(identity) {
+    $("#login").hidden = true;
+    $("#account").hidden = false;
+    $("#account-name").textContent = `${identity.username} (${identity.role})`;
+    document.body.classList.toggle("viewer", identity.role !== "admin");
+}
+
+$("#login-form").addEventListener("submit", (event) => {
+    event.preventDefault();
+    attempt(async () => {
+        const form = event.target.elements;
+        const session = await api("POST", "/login", { username: form.username.value, password: form.password.value });
+        token = session.token;
+        sessionStorage.setItem("token", token);
+        event.target.reset();
+        showAccount(session);
+        showTab(location.hash.slice(1));
+    });
+});
+
+$("#logout").addEventListener("click", () =>
+    attempt(async () => {
+        await api("POST", "/logout");
+        showLogin();
+    }),
+);
+
+/* ---------- tabs ---------- */
+
+let activeTab = null;
+
+function showTab(name) {
+    if (!token) {
+        showLogin();
+        return;
+    }
+    if (!document.getElementById(name)) {
+        name = "overview";
+    }
+    activeTab = name;
+    document.querySelectorAll(".tab").forEach((tab) => (tab.hidden = tab.id !== name));
+    document.querySelectorAll("nav a").forEach((link) => link.classList.toggle("active", link.dataset.tab === name));
+    showError(null);
+
+    const loaders = { overview: loadStats, querylog: resetQueryLog, records: loadRecords, rules: loadRules };
+    attempt(loaders[name]);
+}
+
+window.addEventListener("hashchange", () => showTab(location.hash.slice(1)));
+
+/* ---------- overview ---------- */
+
+async function loadStats() {
+    const stats = await api("GET", `/stats?hours=${$("#stats-hours").value}`);
+    const totals = stats.totals;
+    $("#total-queries").textContent = formatNumber(totals.queries);
+    $("#total-blocked").textContent = formatNumber(totals.blocked);
+    $("#total-ratio").textContent = totals.blocked_percentage.toFixed(1) + " %";
+    $("#total-cached").textContent = formatNumber(totals.cached);
+    $("#total-failed").textContent = formatNumber(totals.failed);
+
+    drawChart(stats.hourly);
+    fillTop("#top-domains", stats.top_domains);
+    fillTop("#top-blocked", stats.top_blocked_domains);
+    fillTop("#top-clients", stats.top_clients);
+}
+
+function fillTop(selector, entries) {
+    const table = $(selector);
+    table.replaceChildren();
+    if (entries.length === 0) {
+        table.appendChild(row(["No queries yet", ""]));
+    }
+    entries.forEach((entry) => table.appendChild(row([entry.name, formatNumber(entry.count)])));
+}
+
+/*
+    Stacked bars of allowed and blocked queries per hour, with the blocked ratio as a line
+*/
+function drawChart(hourly) {
+    const svgNs = "http://www.w3.org/2000/svg";
+    const width = 800, height = 220, top = 10, bottom = 20, left = 40;
+    const plotHeight = height - top - bottom;
+    const max = Math.max(1, ...hourly.map((hour) => hour.queries));
+    const step = (width - left) / Math.max(1, hourly.length);
+
+    const svg = document.createElementNS(svgNs, "svg");
+    svg.setAttribute("viewBox", `0 0 ${width} ${height}`);
+    svg.setAttribute("preserveAspectRatio", "none");
+
+    const tooltip = (text) => {
+        const title = document.createElementNS(svgNs, "title");
+        title.textContent = text;
+        return title;
+    };
+    const shape = (tag, attrs, text) => {
+        const element = document.createElementNS(svgNs, tag);
+        Object.entries(attrs).forEach(([name, value]) => element.setAttribute(name, value));
+        if (text !== undefined) {
+            element.textContent = text;
+        }
+        svg.appendChild(element);
+        return element;
+    };
+
+    shape("text", { x: 0, y: top + 8 }, formatNumber(max));
+    shape("text", { x: 0, y: top + plotHeight }, "0");
+
+    const ratioPoints = [];
+    const labelEvery = Math.ceil(hourly.length / 12);
+    hourly.forEach((hour, i) => {
+        const x = left + i * step;
+        const barWidth = Math.max(1, step - 2);
+        const allowed = hour.queries - hour.blocked;
+        const allowedHeight = (allowed / max) * plotHeight;
+        const blockedHeight = (hour.blocked / max) * plotHeight;
+        const base = top + plotHeight;
+
+        const title = `${parseUtc(hour.hour).toLocaleString()}: ${hour.queries} queries, ${hour.blocked} blocked`;
+        shape("rect", { x, y: base - allowedHeight, width: barWidth, height: allowedHeight, class: "allowed" })
+            .appendChild(tooltip(title));
+        shape("rect", { x, y: base - allowedHeight - blockedHeight, width: barWidth, height: blockedHeight, class: "blocked" })
+            .appendChild(tooltip(title));
+
+        const ratio = hour.queries > 0 ? hour.blocked / hour.queries : 0;
+        ratioPoints.push(`${x + barWidth / 2},${base - ratio * plotHeight}`);
+
+        if (i % labelEvery === 0) {
+            const label = parseUtc(hour.hour).toLocaleTimeString([], { hour: "2-digit", minute: "2-digit" });
+            shape("text", { x, y: height - 5 }, label);
+        }
+    });
+    if (ratioPoints.length > 1) {
+        shape("polyline", { points: ratioPoints.join(" "), class: "ratio" });
+    }
+
+    $("#chart").replaceChildren(svg);
+}
+
+$("#stats-hours").addEventListener("change", () => attempt(loadStats));
+
+/* ---------- query log ---------- */
+
+// id of the newest entry shown, later polls only fetch what came after it
+let lastQueryId = null;
+
+function queryLogParams() {
+    const form = new FormData($("#querylog-filter"));
+    const params = new URLSearchParams();
+    for (const [name, value] of form.entries()) {
+        if (value.trim() !== "") {
+            params.set(name, value.trim());
+        }
+    }
+    return params;
+}
+
+function queryLogRow(entry) {
+    return row(
+        [
+            parseUtc(entry.timestamp).toLocaleTimeString(),
+            entry.client,
+            el("td", entry.name, { class: "wrap" }),
+            entry.type,
+            entry.rcode,
+            entry.source || "failed",
+            entry.latency_ms.toFixed(1) + " ms",
+        ],
+        entry.source === "blocked" ? "blocked" : null,
+    );
+}
+
+async function resetQueryLog() {
+    lastQueryId = null;
+    $("#querylog-rows").replaceChildren();
+    await pollQueryLog();
+}
+
+async function pollQueryLog() {
+    const params = queryLogParams();
+    if (lastQueryId !== null) {
+        params.set("after", lastQueryId);
+    }
+    const entries = await api("GET", `/querylog?${params}`);
+    if (entries.length === 0) {
+        return;
+    }
+    lastQueryId = entries[0].id;
+
+    // entries arrive newest first
+    const rows = $("#querylog-rows");
+    const fragment = document.createDocumentFragment();
+    entries.forEach((entry) => fragment.appendChild(queryLogRow(entry)));
+    rows.insertBefore(fragment, rows.firstChild);
+    while (rows.children.length > QUERYLOG_ROWS) {
+        rows.removeChild(rows.lastChild);
+    }
+}
+
+$("#querylog-filter").addEventListener("submit", (event) => {
+    event.preventDefault();
+    attempt(resetQueryLog);
+});
+
+/* ---------- records ---------- */
+
+async function loadRecords() {
+    const records = await api("GET", "/records");
+    const rows = $("#record-rows");
+    rows.replaceChildren();
+    records.forEach((record) => {
+        rows.appendChild(
+            row(
+                [
+                    record.name,
+                    record.type,
+                    el("td", record.value, { class: "wrap" }),
+                    record.ttl,
+                    record.active ? "yes" : "no",
+                    actions(
+                        button("Edit", () => editRecord(record)),
+                        button("Delete", () => deleteRecord(record)),
+                    ),
+                ],
+                record.active ? null : "inactive",
+            ),
+        );
+    });
+}
+
+function editRecord(record) {
+    const form = $("#record-form");
+    form.elements.id.value = record.id;
+    form.elements.name.value = record.name;
+    form.elements.type.value = record.type;
+    form.elements.value.value = record.value;
+    form.elements.ttl.value = record.ttl;
+    form.elements.active.checked = record.active;
+}
+
+async function deleteRecord(record) {
+    if (confirm(`Delete the ${record.type} record of ${record.name}?`)) {
+        await api("DELETE", `/records/${record.id}`);
+        await loadRecords();
+    }
+}
+
+$("#record-form").addEventListener("submit", (event) => {
+    event.preventDefault();
+    attempt(async () => {
+        const form = event.target.elements;
+        const body = {
+            name: form.name.value,
+            type: form.type.value,
+            value: form.value.value,
+            active: form.active.checked,
+        };
+        if (form.ttl.value !== "") {
+            body.ttl = Number(form.ttl.value);
+        }
+
+        if (form.id.value) {
+            await api("PUT", `/records/${form.id.value}`, body);
+        } else {
+            await api("POST", "/records", body);
+        }
+        event.target.reset();
+        await loadRecords();
+    });
+});
+
+/* ---------- rules ---------- */
+
+async function loadRules() {
+    const [rules, groups, schedules] = await Promise.all([
+        api("GET", "/rules"),
+        api("GET", "/groups"),
+        api("GET", "/schedules"),
+    ]);
+
+    const groupSelect = $("#rule-group");
+    groupSelect.replaceChildren(el("option", "All clients", { value: "" }));
+    groups.forEach((group) => groupSelect.appendChild(el("option", group.name, { value: group.name })));
+
+    const scheduleNames = {};
+    const scheduleSelect = $("#rule-schedule");
+    scheduleSelect.replaceChildren(el("option", "Always", { value: "" }));
+    schedules.forEach((schedule) => {
+        scheduleNames[schedule.id] = schedule.name;
+        scheduleSelect.appendChild(
+            el("option", `${schedule.name} (${schedule.days} ${schedule.start}-${schedule.end})`, { value: schedule.id }),
+        );
+    });
+
+    const rows = $("#rule-rows");
+    rows.replaceChildren();
+    rules.forEach((rule) => {
+        rows.appendChild(
+            row(
+                [
+                    rule.action,
+                    rule.kind,
+                    el("td", rule.pattern, { class: "wrap" }),
+                    rule.group || "all",
+                    rule.schedule_id ? scheduleNames[rule.schedule_id] : "always",
+                    rule.comment || "",
+                    rule.active ? "yes" : "no",
+                    actions(
+                        button(rule.active ? "Disable" : "Enable", () => saveRule(rule.id, { ...ruleBody(rule), active: !rule.active })),
+                        button("Edit", () => editRule(rule)),
+                        button("Delete", () => deleteRule(rule)),
+                    ),
+                ],
+                rule.active ? (rule.action === "block" ? "blocked" : null) : "inactive",
+            ),
+        );
+    });
+}
+
+function ruleBody(rule) {
+    return {
+        action: rule.action,
+        kind: rule.kind,
+        pattern: rule.pattern,
+        comment: rule.comment || null,
+        group: rule.group || null,
+        schedule_id: rule.schedule_id || null,
+        active: rule.active,
+    };
+}
+
+async function saveRule(id, body) {
+    if (id) {
+        await api("PUT", `/rules/${id}`, body);
+    } else {
+        await api("POST", "/rules", body);
+    }
+    await loadRules();
+}
+
+function editRule(rule) {
+    const form = $("#rule-form");
+    form.elements.id.value = rule.id;
+    form.elements.action.value = rule.action;
+    form.elements.kind.value = rule.kind;
+    form.elements.pattern.value = rule.pattern;
+    form.elements.group.value = rule.group || "";
+    form.elements.schedule_id.value = rule.schedule_id || "";
+    form.elements.comment.value = rule.comment || "";
+    form.elements.active.checked = rule.active;
+}
+
+async function deleteRule(rule) {
+    if (confirm(`Delete the ${rule.action} rule for ${rule.pattern}?`)) {
+        await api("DELETE", `/rules/${rule.id}`);
+        await loadRules();
+    }
+}
+
+$("#rule-form").addEventListener("submit", (event) => {
+    event.preventDefault();
+    attempt(async () => {
+        const form = event.target.elements;
+        const id = form.id.value;
+        await saveRule(id, {
+            action: form.action.value,
+            kind: form.kind.value,
+            pattern: form.pattern.value,
+            comment: form.comment.value || null,
+            group: form.group.value || null,
+            schedule_id: form.schedule_id.value ? Number(form.schedule_id.value) : null,
+            active: form.active.checked,
+        });
+        event.target.reset();
+    });
+});
+
+// the hidden id decides between creating and updating, so it has to be cleared explicitly
+["#record-form", "#rule-form"].forEach((selector) =>
+    $(selector).addEventListener("reset", (event) => (event.target.elements.id.value = "")),
+);
+
+/* ---------- refreshing ---------- */
+
+setInterval(() => {
+    if (activeTab === "querylog" && $("#querylog-live").checked) {
+        attempt(pollQueryLog);
+    }
+}, QUERYLOG_INTERVAL);
+
+setInterval(() => {
+    if (activeTab === "overview") {
+        attempt(loadStats);
+    }
+}, STATS_INTERVAL);
+
+attempt(async () => {
+    if (token) {
+        showAccount(await api("GET", "/me"));
+    }
+    showTab(location.hash.slice(1));
+});
@@ -0,0 +1,66 @@
+use crate::error::DnsError;
+
+/*
+    Long-lived credential of a user for scripts, acting with the role of that user
+*/
+#[derive(sqlx::FromRow, Default)]
+pub struct ApiKeyEntity {
+    id: u64,
+    user_id: u64,
+    name: String,
+    key_hash: String,
+    created_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ApiKeyEntity {
+    pub fn new(user_id: u64, name: String, key_hash: String) -> Self {
+        ApiKeyEntity {
+            user_id,
+            name,
+            key_hash,
+            ..Default::default()
+        }
+    }
+
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<ApiKeyEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id", tbl_name))
+            .fetch_all(db).await?)
+    }
+
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(user_id, name, key_hash) VALUES (?, ?, ?);", tbl_name))
+            .bind(self.user_id as i64)
+            .bind(self.name)
+            .bind(self.key_hash)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Revokes the key with the given id. Returns false if there is no such key.
+    */
+    pub async fn _delete(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, id: u64) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", tbl_name))
+            .bind(id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+}
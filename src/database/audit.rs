@@ -0,0 +1,76 @@
+use std::net::IpAddr;
+
+use crate::error::DnsError;
+
+/*
+    Security relevant event, e.g. a failed login
+*/
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct AuditEntity {
+    id: u64,
+    timestamp: chrono::NaiveDateTime,
+    event: String,
+    username: Option<String>,
+    client: Option<String>,
+    detail: Option<String>,
+}
+
+impl AuditEntity {
+    pub fn new(event: &str, username: Option<&str>, client: Option<IpAddr>, detail: Option<&str>) -> Self {
+        AuditEntity {
+            id: 0,
+            timestamp: chrono::Utc::now().naive_utc(),
+            event: event.to_string(),
+            username: username.map(str::to_string),
+            client: client.map(|client| client.to_string()),
+            detail: detail.map(str::to_string),
+        }
+    }
+
+    /*
+        Fetches the latest entries, newest first, optionally only those of one event
+    */
+    pub async fn _fetch_recent(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, event: Option<&str>, limit: u32) -> Result<Vec<AuditEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE ? IS NULL OR event = ? ORDER BY id DESC LIMIT ?", tbl_name))
+            .bind(event)
+            .bind(event)
+            .bind(limit)
+            .fetch_all(db).await?)
+    }
+
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(timestamp, event, username, client, detail) VALUES (?, ?, ?, ?, ?);", tbl_name))
+            .bind(self.timestamp)
+            .bind(self.event)
+            .bind(self.username)
+            .bind(self.client)
+            .bind(self.detail)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn timestamp(&self) -> chrono::NaiveDateTime {
+        self.timestamp
+    }
+
+    pub fn event(&self) -> &str {
+        &self.event
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn client(&self) -> Option<&str> {
+        self.client.as_deref()
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        self.detail.as_deref()
+    }
+}
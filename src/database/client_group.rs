@@ -0,0 +1,107 @@
+use crate::{
+    error::{DnsError, RuleError},
+    server::ClientMatcher,
+};
+
+#[derive(sqlx::FromRow, Default)]
+pub struct ClientGroupEntity {
+    id: u64,
+    name: String,
+    clients: String,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ClientGroupEntity {
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<ClientGroupEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id", tbl_name))
+            .fetch_all(db).await?)
+    }
+
+    pub async fn _fetch_by_name(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, name: &str) -> Result<Option<ClientGroupEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE name = ?", tbl_name))
+            .bind(name)
+            .fetch_optional(db).await?)
+    }
+
+    /*
+        Inserts the group, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(name, clients) VALUES (?, ?);", tbl_name))
+            .bind(self.name)
+            .bind(self.clients)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Replaces the clients of the group with the same name. Returns false if there is no such group.
+    */
+    pub async fn _update(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET clients = ? WHERE name = ?;", tbl_name))
+            .bind(self.clients)
+            .bind(self.name)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes the group with the same name. Returns false if there is no such group.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE name = ?;", tbl_name))
+            .bind(&self.name)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn to_matchers(&self) -> Result<Vec<ClientMatcher>, RuleError> {
+        self.clients
+            .split(',')
+            .map(str::trim)
+            .filter(|client| !client.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /*
+        Stores clients as given, e.g. "192.168.1.10" or "aa:bb:cc:dd:ee:ff"
+    */
+    pub fn with_clients(mut self, clients: &[String]) -> Self {
+        self.clients = clients.join(",");
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn clients(&self) -> Vec<String> {
+        self.clients
+            .split(',')
+            .filter(|client| !client.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.updated_at
+    }
+}
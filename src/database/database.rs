@@ -1,37 +1,85 @@
-pub struct Database {
-    sqlite_pool: sqlx::Pool<sqlx::Sqlite>,
-}
-
-impl Database {
-    pub async fn init(db_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let db_pool = sqlx::SqlitePool::connect_with(
-            sqlx::sqlite::SqliteConnectOptions::new()
-            .filename(db_path)
-            .create_if_missing(true)).await?;
-        
-        sqlx::migrate!("./migrations").run(&db_pool).await?;
-    
-        Ok(Database{
-            sqlite_pool: db_pool,
-        })
-    }
-
-    #[allow(unused)] // TODO: this might come in handy if we want to chain "virtual" nameservers (e.g. for blocklists)
-    pub async fn init_mem() -> Result<Self, Box<dyn std::error::Error>> {
-        let db_pool = sqlx::SqlitePool::connect("sqlite::memory:").await?;
-
-        sqlx::migrate!("./migrations").run(&db_pool).await?;
-
-        Ok(Database{
-            sqlite_pool: db_pool,
-        })
-    }
-
-    pub fn config_dns_tbl(&self) -> String {
-        "user_dns_records".to_string()
-    }
-
-    pub fn get_pool(&self) -> &sqlx::Pool<sqlx::Sqlite> {
-        &self.sqlite_pool
-    }
-}
+use crate::error::DnsError;
+
+pub struct Database {
+    sqlite_pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+impl Database {
+    pub async fn init(db_path: &str) -> Result<Self, DnsError> {
+        let db_pool = sqlx::SqlitePool::connect_with(
+            sqlx::sqlite::SqliteConnectOptions::new()
+            .filename(db_path)
+            .create_if_missing(true)).await?;
+        
+        sqlx::migrate!("./migrations").run(&db_pool).await?;
+    
+        Ok(Database{
+            sqlite_pool: db_pool,
+        })
+    }
+
+    #[allow(unused)] // TODO: this might come in handy if we want to chain "virtual" nameservers (e.g. for blocklists)
+    pub async fn init_mem() -> Result<Self, DnsError> {
+        let db_pool = sqlx::SqlitePool::connect("sqlite::memory:").await?;
+
+        sqlx::migrate!("./migrations").run(&db_pool).await?;
+
+        Ok(Database{
+            sqlite_pool: db_pool,
+        })
+    }
+
+    pub fn config_dns_tbl(&self) -> String {
+        "user_dns_records".to_string()
+    }
+
+    pub fn filter_rules_tbl(&self) -> String {
+        "filter_rules".to_string()
+    }
+
+    pub fn schedules_tbl(&self) -> String {
+        "schedules".to_string()
+    }
+
+    pub fn client_groups_tbl(&self) -> String {
+        "client_groups".to_string()
+    }
+
+    pub fn upstream_servers_tbl(&self) -> String {
+        "upstream_servers".to_string()
+    }
+
+    pub fn query_log_tbl(&self) -> String {
+        "query_log".to_string()
+    }
+
+    pub fn users_tbl(&self) -> String {
+        "users".to_string()
+    }
+
+    pub fn sessions_tbl(&self) -> String {
+        "sessions".to_string()
+    }
+
+    pub fn api_keys_tbl(&self) -> String {
+        "api_keys".to_string()
+    }
+
+    pub fn audit_log_tbl(&self) -> String {
+        "audit_log".to_string()
+    }
+
+    /*
+        Counter bumped by triggers whenever rules, schedules, client groups or upstream servers change
+    */
+    pub async fn config_version(&self) -> Result<i64, DnsError> {
+        let (version,): (i64,) = sqlx::query_as("SELECT version FROM config_version WHERE id = 1")
+            .fetch_one(&self.sqlite_pool).await?;
+
+        Ok(version)
+    }
+
+    pub fn get_pool(&self) -> &sqlx::Pool<sqlx::Sqlite> {
+        &self.sqlite_pool
+    }
+}
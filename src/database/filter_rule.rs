@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{DnsError, RuleError},
+    filter::{FilterRule, RuleAction, RuleKind, Schedule},
+};
+
+#[derive(sqlx::FromRow)]
+pub struct FilterRuleEntity {
+    id: u64,
+    action: String,
+    kind: String,
+    pattern: String,
+    comment: Option<String>,
+    client_group: Option<String>,
+    schedule_id: Option<u64>,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+    is_active: bool,
+}
+
+impl Default for FilterRuleEntity {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            action: RuleAction::Block.as_str().to_string(),
+            kind: RuleKind::Exact.as_str().to_string(),
+            pattern: String::default(),
+            comment: None,
+            client_group: None,
+            schedule_id: None,
+            created_at: None,
+            updated_at: None,
+            is_active: true,
+        }
+    }
+}
+
+impl FilterRuleEntity {
+    /*
+        Fetches all rules, optionally including the deactivated ones
+    */
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, include_inactive: bool) -> Result<Vec<FilterRuleEntity>, DnsError> {
+        let mut builder = sqlx::QueryBuilder::new(format!("SELECT * FROM {} WHERE 1=1", tbl_name));
+        if !include_inactive {
+            builder.push(" AND is_active = 1");
+        }
+        builder.push(" ORDER BY id");
+
+        Ok(builder.build_query_as().fetch_all(db).await?)
+    }
+
+    /*
+        Fetches the active rules applying to a client group, which are its own rules
+        and those for all clients. If client_group is None, only the latter.
+    */
+    pub async fn _fetch_for_group(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, client_group: Option<&str>) -> Result<Vec<FilterRuleEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE is_active = 1 AND (client_group IS NULL OR client_group IS ?) ORDER BY id", tbl_name))
+            .bind(client_group)
+            .fetch_all(db).await?)
+    }
+
+    pub async fn _fetch_one(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, id: u64) -> Result<Option<FilterRuleEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE id = ?", tbl_name))
+            .bind(id as i64)
+            .fetch_optional(db).await?)
+    }
+
+    /*
+        Inserts the rule, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(action, kind, pattern, comment, client_group, schedule_id, is_active) VALUES (?, ?, ?, ?, ?, ?, ?);", tbl_name))
+            .bind(self.action)
+            .bind(self.kind)
+            .bind(self.pattern)
+            .bind(self.comment)
+            .bind(self.client_group)
+            .bind(self.schedule_id.map(|id| id as i64))
+            .bind(self.is_active)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Inserts all rules in a single transaction, skipping those that exist already.
+        Returns the number of rules inserted.
+    */
+    pub async fn _insert_batch(entities: &[FilterRuleEntity], db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let mut tx = db.begin().await?;
+        let sql = format!("INSERT OR IGNORE INTO {}(action, kind, pattern, comment, client_group, schedule_id, is_active) VALUES (?, ?, ?, ?, ?, ?, ?);", tbl_name);
+        let mut inserted = 0;
+        for entity in entities {
+            inserted += sqlx::query(&sql)
+                .bind(&entity.action)
+                .bind(&entity.kind)
+                .bind(&entity.pattern)
+                .bind(&entity.comment)
+                .bind(&entity.client_group)
+                .bind(entity.schedule_id.map(|id| id as i64))
+                .bind(entity.is_active)
+                .execute(&mut *tx).await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+
+        Ok(inserted)
+    }
+
+    /*
+        Overwrites the rule with the same id. Returns false if there is no such rule.
+    */
+    pub async fn _update(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET action = ?, kind = ?, pattern = ?, comment = ?, client_group = ?, schedule_id = ?, is_active = ? WHERE id = ?;", tbl_name))
+            .bind(self.action)
+            .bind(self.kind)
+            .bind(self.pattern)
+            .bind(self.comment)
+            .bind(self.client_group)
+            .bind(self.schedule_id.map(|id| id as i64))
+            .bind(self.is_active)
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Activates or deactivates the rule with the same id. Returns false if there is no such rule.
+    */
+    pub async fn _set_active(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, is_active: bool) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET is_active = ? WHERE id = ?;", tbl_name))
+            .bind(is_active)
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes the rule with the same id. Returns false if there is no such rule.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", tbl_name))
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes all rules of a client group, returning how many there were
+    */
+    pub async fn _delete_for_group(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, client_group: &str) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE client_group = ?;", tbl_name))
+            .bind(client_group)
+            .execute(db).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /*
+        Converts the stored rule into the one the filter evaluates,
+        looking up its schedule by id
+    */
+    pub fn to_rule(&self, schedules: &HashMap<u64, Schedule>) -> Result<FilterRule, RuleError> {
+        let schedule = match self.schedule_id {
+            Some(id) => Some(
+                schedules
+                    .get(&id)
+                    .cloned()
+                    .ok_or_else(|| RuleError(format!("unknown schedule {}", id)))?,
+            ),
+            None => None,
+        };
+
+        Ok(FilterRule {
+            id: self.id,
+            action: self.action.parse()?,
+            kind: self.kind.parse()?,
+            pattern: self.pattern.clone(),
+            schedule,
+        })
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_action(mut self, action: RuleAction) -> Self {
+        self.action = action.as_str().to_string();
+        self
+    }
+
+    pub fn with_kind(mut self, kind: RuleKind) -> Self {
+        self.kind = kind.as_str().to_string();
+        self
+    }
+
+    pub fn with_pattern(mut self, pattern: String) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub fn with_comment(mut self, comment: String) -> Self {
+        self.comment = Some(comment);
+        self
+    }
+
+    pub fn with_client_group(mut self, client_group: String) -> Self {
+        self.client_group = Some(client_group);
+        self
+    }
+
+    pub fn with_schedule_id(mut self, schedule_id: u64) -> Self {
+        self.schedule_id = Some(schedule_id);
+        self
+    }
+
+    pub fn with_active(mut self, is_active: bool) -> Self {
+        self.is_active = is_active;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn action(&self) -> &str {
+        &self.action
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn client_group(&self) -> Option<&str> {
+        self.client_group.as_deref()
+    }
+
+    pub fn schedule_id(&self) -> Option<u64> {
+        self.schedule_id
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.updated_at
+    }
+}
@@ -1,43 +1,124 @@
-mod database;
-mod record_query;
-
-pub use record_query::RecordQuery;
-pub use record_query::RecordEntity;
-pub use database::Database;
-
-#[cfg(test)]
-mod tests {
-    use crate::{database::record_query::RecordEntity, nameserver::Nameserver, protocol::{packet::RecordType, util::{self}}};
-
-    use super::*;
-
-    #[tokio::test]
-    async fn test_database() {
-        // check if database can be created
-        let db = Database::init_mem().await.unwrap();
-
-        let query = RecordQuery::default()
-            .with_domain_name("dns.is.tiny".to_string())
-            .with_record_type(crate::protocol::packet::RecordType::CNAME);
-
-        let nameserver = Nameserver::new(&db);
-
-        // check for nonexistent resource
-        let record = nameserver.query_record(&query).await;
-        assert!(record.is_none());
-
-        let encoded_domain_name = util::encode_domain("dns.is.tidy".to_string()).unwrap();
-        let record = RecordEntity::default()
-            .with_domain_name("dns.is.tiny".to_string())
-            .with_record_type(RecordType::CNAME)
-            .with_record_value(encoded_domain_name.clone());
-
-        // check for database insert
-        assert!(nameserver.insert_record(record).await.is_ok());
-
-        let record = nameserver.query_record(&query).await;
-        assert!(record.is_some());
-
-        assert_eq!(record.unwrap().serialize().rdata(), encoded_domain_name);
-    }
-}
+mod api_key;
+mod audit;
+mod client_group;
+mod database;
+mod filter_rule;
+mod query_log;
+mod record_query;
+mod schedule;
+mod session;
+mod upstream;
+mod user;
+
+pub use api_key::ApiKeyEntity;
+pub use audit::AuditEntity;
+pub use client_group::ClientGroupEntity;
+pub use filter_rule::FilterRuleEntity;
+pub use query_log::QueryLogEntity;
+pub use record_query::RecordQuery;
+pub use schedule::ScheduleEntity;
+pub use session::SessionEntity;
+pub use upstream::UpstreamEntity;
+pub use user::UserEntity;
+pub use record_query::RecordEntity;
+pub use database::Database;
+
+#[cfg(test)]
+mod tests {
+    use crate::{database::record_query::RecordEntity, nameserver::Nameserver, protocol::packet::RData};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_database() {
+        // check if database can be created
+        let db = Database::init_mem().await.unwrap();
+
+        let query = RecordQuery::default()
+            .with_domain_name("dns.is.tiny".to_string())
+            .with_record_type(crate::protocol::packet::RecordType::CNAME);
+
+        let nameserver = Nameserver::new(std::sync::Arc::new(db));
+
+        // check for nonexistent resource
+        let record = nameserver.query_record(&query).await.unwrap();
+        assert!(record.is_none());
+
+        let rdata = RData::CNAME("dns.is.tidy".to_string());
+        let record = RecordEntity::default()
+            .with_domain_name("dns.is.tiny".to_string())
+            .with_rdata(rdata.clone())
+            .unwrap();
+
+        // check for database insert
+        assert!(nameserver.insert_record(record).await.is_ok());
+
+        let record = nameserver.query_record(&query).await.unwrap();
+        assert!(record.is_some());
+
+        assert_eq!(record.unwrap().serialize().unwrap().rdata(), rdata);
+    }
+
+    #[tokio::test]
+    async fn test_record_lifecycle() {
+        let db = std::sync::Arc::new(Database::init_mem().await.unwrap());
+        let nameserver = Nameserver::new(db.clone());
+
+        let record = RecordEntity::default()
+            .with_domain_name("dns.is.tiny".to_string())
+            .with_rdata(RData::CNAME("dns.is.tidy".to_string()))
+            .unwrap();
+        let id = nameserver.insert_record(record).await.unwrap();
+
+        let query = RecordQuery::default().with_domain_name("dns.is.tiny".to_string());
+        let all_query = RecordQuery::default().with_id(id).with_inactive();
+
+        // inactive records are never served
+        assert!(nameserver.set_active(id, false).await.unwrap());
+        assert!(nameserver.query_records(&query).await.unwrap().is_empty());
+        let record = nameserver.query_record(&all_query).await.unwrap().unwrap();
+        assert!(!record.is_active());
+
+        assert!(nameserver.set_active(id, true).await.unwrap());
+        assert_eq!(nameserver.query_records(&query).await.unwrap().len(), 1);
+
+        // updates bump updated_at
+        sqlx::query("UPDATE user_dns_records SET updated_at = '2000-01-01 00:00:00'")
+            .execute(db.get_pool())
+            .await
+            .unwrap();
+        let record = nameserver.query_record(&all_query).await.unwrap().unwrap().with_ttl(60);
+        assert!(nameserver.update_record(record).await.unwrap());
+
+        let record = nameserver.query_record(&all_query).await.unwrap().unwrap();
+        assert_eq!(record.ttl(), 60);
+        assert_ne!(record.updated_at().unwrap().date().to_string(), "2000-01-01");
+
+        assert!(nameserver.delete_record(id).await.unwrap());
+        assert!(!nameserver.delete_record(id).await.unwrap());
+        assert!(!nameserver.set_active(id, true).await.unwrap());
+        assert!(nameserver.query_record(&all_query).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_config_version() {
+        let db = Database::init_mem().await.unwrap();
+        let version = db.config_version().await.unwrap();
+
+        // records are read live and don't count as configuration
+        let record = RecordEntity::default()
+            .with_domain_name("dns.is.tiny".to_string())
+            .with_rdata(RData::CNAME("dns.is.tidy".to_string()))
+            .unwrap();
+        record._insert(db.get_pool(), db.config_dns_tbl()).await.unwrap();
+        assert_eq!(db.config_version().await.unwrap(), version);
+
+        let rules: Vec<_> = ["ads.example.com", "ads.example.com", "tracker.example.com"]
+            .into_iter()
+            .map(|pattern| FilterRuleEntity::default().with_pattern(pattern.to_string()))
+            .collect();
+        let inserted = FilterRuleEntity::_insert_batch(&rules, db.get_pool(), db.filter_rules_tbl()).await.unwrap();
+        assert_eq!(inserted, 2);
+        assert!(db.config_version().await.unwrap() > version);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{net::IpAddr, time::Duration};
+
+use crate::{error::DnsError, protocol::answer::AnswerSource};
+
+/*
+    A single handled query
+*/
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct QueryLogEntity {
+    id: u64,
+    timestamp: chrono::NaiveDateTime,
+    client: String,
+    qname: String,
+    qtype: u16,
+    rcode: u16,
+    answered_from: Option<String>,
+    latency_us: u64,
+}
+
+impl QueryLogEntity {
+    pub fn new(
+        client: IpAddr,
+        qname: String,
+        qtype: u16,
+        rcode: u16,
+        answered_from: Option<AnswerSource>,
+        latency: Duration,
+    ) -> Self {
+        QueryLogEntity {
+            id: 0,
+            timestamp: chrono::Utc::now().naive_utc(),
+            client: client.to_string(),
+            qname,
+            qtype,
+            rcode,
+            answered_from: answered_from.map(|source| source.as_str().to_string()),
+            latency_us: latency.as_micros() as u64,
+        }
+    }
+
+    /*
+        Fetches the latest entries, newest first
+    */
+    pub async fn _fetch_recent(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, limit: u32) -> Result<Vec<QueryLogEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id DESC LIMIT ?", tbl_name))
+            .bind(limit)
+            .fetch_all(db).await?)
+    }
+
+    /*
+        Fetches the latest entries newer than after_id, newest first. The client and source
+        have to match exactly, while qname only has to contain the given text.
+    */
+    pub async fn _search(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, client: Option<&str>, qname: Option<&str>, answered_from: Option<&str>, after_id: Option<u64>, limit: u32) -> Result<Vec<QueryLogEntity>, DnsError> {
+        let mut builder = sqlx::QueryBuilder::new(format!("SELECT * FROM {} WHERE 1=1", tbl_name));
+        if let Some(client) = client {
+            builder.push(" AND client = ");
+            builder.push_bind(client);
+        }
+        if let Some(qname) = qname {
+            builder.push(" AND instr(qname, ");
+            builder.push_bind(qname);
+            builder.push(") > 0");
+        }
+        if let Some(answered_from) = answered_from {
+            builder.push(" AND answered_from = ");
+            builder.push_bind(answered_from);
+        }
+        if let Some(after_id) = after_id {
+            builder.push(" AND id > ");
+            builder.push_bind(after_id as i64);
+        }
+        builder.push(" ORDER BY id DESC LIMIT ");
+        builder.push_bind(limit);
+
+        Ok(builder.build_query_as().fetch_all(db).await?)
+    }
+
+    /*
+        Inserts all entries in a single transaction
+    */
+    pub async fn _insert_batch(entries: &[QueryLogEntity], db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<(), DnsError> {
+        let mut tx = db.begin().await?;
+        let sql = format!("INSERT INTO {}(timestamp, client, qname, qtype, rcode, answered_from, latency_us) VALUES (?, ?, ?, ?, ?, ?, ?);", tbl_name);
+        for entry in entries {
+            sqlx::query(&sql)
+                .bind(entry.timestamp)
+                .bind(&entry.client)
+                .bind(&entry.qname)
+                .bind(entry.qtype)
+                .bind(entry.rcode)
+                .bind(&entry.answered_from)
+                .bind(entry.latency_us as i64)
+                .execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /*
+        Deletes entries logged before older_than (if given), then all but the newest max_rows.
+        Returns the number of entries deleted.
+    */
+    pub async fn _prune(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, older_than: Option<chrono::NaiveDateTime>, max_rows: u64) -> Result<u64, DnsError> {
+        let mut deleted = 0;
+        if let Some(older_than) = older_than {
+            deleted += sqlx::query(&format!("DELETE FROM {} WHERE timestamp < ?;", tbl_name))
+                .bind(older_than)
+                .execute(db).await?
+                .rows_affected();
+        }
+
+        let excess = sqlx::query(&format!(
+            "DELETE FROM {0} WHERE id <= (SELECT id FROM {0} ORDER BY id DESC LIMIT 1 OFFSET ?);",
+            tbl_name
+        ))
+            .bind(max_rows as i64)
+            .execute(db).await?;
+
+        Ok(deleted + excess.rows_affected())
+    }
+
+    /*
+        Number of entries logged within [since, until), per answer source
+    */
+    pub async fn _count_by_source(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, since: chrono::NaiveDateTime, until: chrono::NaiveDateTime) -> Result<Vec<(Option<String>, i64)>, DnsError> {
+        Ok(sqlx::query_as(&format!(
+            "SELECT answered_from, COUNT(*) FROM {} WHERE timestamp >= ? AND timestamp < ? GROUP BY answered_from",
+            tbl_name
+        ))
+            .bind(since)
+            .bind(until)
+            .fetch_all(db).await?)
+    }
+
+    /*
+        Most frequent values of column (qname or client) within [since, until),
+        optionally only counting entries answered from a certain source
+    */
+    pub async fn _top_values(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, column: &str, since: chrono::NaiveDateTime, until: chrono::NaiveDateTime, answered_from: Option<AnswerSource>, limit: u32) -> Result<Vec<(String, i64)>, DnsError> {
+        let mut builder = sqlx::QueryBuilder::new(format!("SELECT {0}, COUNT(*) AS count FROM {1} WHERE timestamp >= ", column, tbl_name));
+        builder.push_bind(since);
+        builder.push(" AND timestamp < ");
+        builder.push_bind(until);
+        if let Some(source) = answered_from {
+            builder.push(" AND answered_from = ");
+            builder.push_bind(source.as_str());
+        }
+        builder.push(format!(" GROUP BY {} ORDER BY count DESC, {} LIMIT ", column, column));
+        builder.push_bind(limit);
+
+        Ok(builder.build_query_as().fetch_all(db).await?)
+    }
+
+    /*
+        Number of entries and blocked entries per hour within [since, until).
+        Hours without entries are left out.
+    */
+    pub async fn _count_by_hour(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, since: chrono::NaiveDateTime, until: chrono::NaiveDateTime) -> Result<Vec<(chrono::NaiveDateTime, i64, i64)>, DnsError> {
+        Ok(sqlx::query_as(&format!(
+            "SELECT strftime('%Y-%m-%d %H:00:00', timestamp) AS hour, COUNT(*), SUM(answered_from IS 'blocked') \
+             FROM {} WHERE timestamp >= ? AND timestamp < ? GROUP BY hour ORDER BY hour",
+            tbl_name
+        ))
+            .bind(since)
+            .bind(until)
+            .fetch_all(db).await?)
+    }
+
+    pub fn with_timestamp(mut self, timestamp: chrono::NaiveDateTime) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn timestamp(&self) -> chrono::NaiveDateTime {
+        self.timestamp
+    }
+
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    pub fn qname(&self) -> &str {
+        &self.qname
+    }
+
+    pub fn qtype(&self) -> u16 {
+        self.qtype
+    }
+
+    pub fn rcode(&self) -> u16 {
+        self.rcode
+    }
+
+    pub fn answered_from(&self) -> Option<&str> {
+        self.answered_from.as_deref()
+    }
+
+    pub fn latency(&self) -> Duration {
+        Duration::from_micros(self.latency_us)
+    }
+}
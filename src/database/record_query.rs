@@ -1,135 +1,310 @@
-use crate::protocol::packet::{RecordType, ResourceRecord};
-
-#[derive(Default)]
-pub struct RecordQuery {
-    valid: bool,
-    domain_name: Option<String>,
-    record_type: Option<RecordType>,
-    // TODO: add more queryable fields
-}
-
-#[derive(sqlx::FromRow)]
-pub struct RecordEntity {
-    id: u64,
-    domain_name: String,
-    record_type: u16,
-    record_value: Vec<u8>,
-    ttl: u32,
-    priority: Option<u32>,
-    created_at: Option<chrono::NaiveDateTime>,
-    updated_at: Option<chrono::NaiveDateTime>,
-    is_active: bool
-}
-
-impl Default for RecordEntity {
-    fn default() -> Self {
-        Self {
-            id: 0,
-            domain_name: String::default(),
-            record_type: 0,
-            record_value: Vec::default(),
-            ttl: 3600,
-            priority: None,
-            created_at: None,
-            updated_at: None,
-            is_active: false
-        }
-    }
-}
-
-impl RecordQuery {
-    pub async fn _fetch_one(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Option<RecordEntity> {
-        if !self.valid {
-            return None;
-        }
-
-        let mut builder = sqlx::QueryBuilder::new(format!("SELECT * FROM {} WHERE 1=1", tbl_name));
-        
-        if let Some(domain_name) = self.domain_name() {
-            builder.push(" AND domain_name = ").push_bind(domain_name);
-        }
-
-        if let Some(record_type) = self.record_type() {
-            let record_type: u16 = record_type.into();
-            builder.push(" AND record_type = ").push_bind(record_type);
-        }
-
-        builder.push(" LIMIT 1");
-
-        log::trace!("querying domain name {} with record type {:?}", self.domain_name()?, self.record_type()?);
-        Some(
-            builder.build_query_as().fetch_one(db).await.ok()?
-        )
-    }
-
-    pub fn with_domain_name(mut self, domain_name: String) -> Self {
-        self.domain_name = Some(domain_name);
-        self.valid = true;
-        self
-    }
-
-    pub fn with_record_type(mut self, record_type: RecordType) -> Self {
-        self.record_type = Some(record_type);
-        self.valid = true;
-        self
-    }
-
-    pub fn domain_name(&self) -> Option<String> {
-        Some(self.domain_name.as_ref()?.to_owned())
-    }
-
-    pub fn record_type(&self) -> Option<RecordType> {
-        Some(self.record_type.as_ref()?.clone())
-    }
-
-
-}
-
-impl RecordEntity {
-    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query(&format!("INSERT INTO {}(domain_name, record_type, record_value, ttl, priority) VALUES (?, ?, ?, ?, ?);", tbl_name))
-            .bind(self.domain_name)
-            .bind(self.record_type)
-            .bind(self.record_value)
-            .bind(self.ttl)
-            .bind(self.priority)
-            .execute(db).await?;
-
-        Ok(())
-    }
-
-    pub fn serialize(self) -> ResourceRecord {
-        // TODO: if the name is not set, the packet will
-        //      still successfully serialize (but be malformed)
-        ResourceRecord::default()
-            .with_rtype(self.record_type.into())
-            .with_rdata(self.record_value)
-            .with_rclass(1) // TODO: this shouldn't be hardcoded
-            .with_ttl(self.ttl)
-    }
-
-    pub fn with_domain_name(mut self, domain_name: String) -> Self {
-        self.domain_name = domain_name;
-        self
-    }
-
-    pub fn with_record_type(mut self, record_type: RecordType) -> Self {
-        self.record_type = record_type.into();
-        self
-    }
-
-    pub fn with_record_value(mut self, record_value: Vec<u8>) -> Self {
-        self.record_value = record_value;
-        self
-    }
-
-    pub fn with_ttl(mut self, ttl: u32) -> Self {
-        self.ttl = ttl;
-        self
-    }
-
-    pub fn with_priority(mut self, priority: u32) -> Self {
-        self.priority = Some(priority);
-        self
-    }
-}
+use crate::{
+    error::DnsError,
+    protocol::packet::{RData, RecordType, ResourceRecord},
+};
+
+#[derive(Default)]
+pub struct RecordQuery {
+    valid: bool,
+    id: Option<u64>,
+    domain_name: Option<String>,
+    record_type: Option<RecordType>,
+    // inactive records are never served, but still need to be managed
+    include_inactive: bool,
+    // also match names below domain_name
+    include_descendants: bool,
+    // TODO: add more queryable fields
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RecordEntity {
+    id: u64,
+    domain_name: String,
+    record_type: u16,
+    record_value: Vec<u8>,
+    ttl: u32,
+    priority: Option<u32>,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+    is_active: bool
+}
+
+impl Default for RecordEntity {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            domain_name: String::default(),
+            record_type: 0,
+            record_value: Vec::default(),
+            ttl: 3600,
+            priority: None,
+            created_at: None,
+            updated_at: None,
+            is_active: true
+        }
+    }
+}
+
+impl RecordQuery {
+    fn select(&self, tbl_name: String) -> sqlx::QueryBuilder<'_, sqlx::Sqlite> {
+        let mut builder = sqlx::QueryBuilder::new(format!("SELECT * FROM {} WHERE 1=1", tbl_name));
+
+        if let Some(id) = self.id {
+            builder.push(" AND id = ").push_bind(id as i64);
+        }
+
+        if let Some(domain_name) = self.domain_name() {
+            if self.include_descendants {
+                // escape LIKE wildcards, '_' is common in names like _dmarc
+                let suffix = domain_name.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+                builder
+                    .push(" AND (domain_name = ")
+                    .push_bind(domain_name)
+                    .push(" OR domain_name LIKE '%.' || ")
+                    .push_bind(suffix)
+                    .push(" ESCAPE '\\')");
+            } else {
+                builder.push(" AND domain_name = ").push_bind(domain_name);
+            }
+        }
+
+        if let Some(record_type) = self.record_type() {
+            let record_type: u16 = record_type.into();
+            builder.push(" AND record_type = ").push_bind(record_type);
+        }
+
+        if !self.include_inactive {
+            builder.push(" AND is_active = 1");
+        }
+
+        builder
+    }
+
+    pub async fn _fetch_one(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Option<RecordEntity>, DnsError> {
+        if !self.valid {
+            return Ok(None);
+        }
+
+        let mut builder = self.select(tbl_name);
+        builder.push(" LIMIT 1");
+
+        log::trace!("querying domain name {:?} with record type {:?}", self.domain_name(), self.record_type());
+        Ok(builder.build_query_as().fetch_optional(db).await?)
+    }
+
+    /*
+        Fetches every matching record, e.g. all records of an RRset
+    */
+    pub async fn _fetch_all(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<RecordEntity>, DnsError> {
+        if !self.valid {
+            return Ok(Vec::new());
+        }
+
+        let mut builder = self.select(tbl_name);
+        // keep the order stable, reordering is up to the nameserver
+        builder.push(" ORDER BY id");
+
+        log::trace!("querying all records of domain name {:?} with record type {:?}", self.domain_name(), self.record_type());
+        Ok(builder.build_query_as().fetch_all(db).await?)
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = Some(id);
+        self.valid = true;
+        self
+    }
+
+    /*
+        Matches every record, unless narrowed down further
+    */
+    pub fn with_all(mut self) -> Self {
+        self.valid = true;
+        self
+    }
+
+    pub fn with_domain_name(mut self, domain_name: String) -> Self {
+        self.domain_name = Some(domain_name);
+        self.valid = true;
+        self
+    }
+
+    pub fn with_record_type(mut self, record_type: RecordType) -> Self {
+        self.record_type = Some(record_type);
+        self.valid = true;
+        self
+    }
+
+    /*
+        Also match records that have been deactivated
+    */
+    pub fn with_inactive(mut self) -> Self {
+        self.include_inactive = true;
+        self
+    }
+
+    /*
+        Also match records of names below the domain name, e.g. to find empty non-terminals
+    */
+    pub fn with_descendants(mut self) -> Self {
+        self.include_descendants = true;
+        self
+    }
+
+    pub fn domain_name(&self) -> Option<String> {
+        Some(self.domain_name.as_ref()?.to_owned())
+    }
+
+    pub fn record_type(&self) -> Option<RecordType> {
+        Some(self.record_type.as_ref()?.clone())
+    }
+
+
+}
+
+impl RecordEntity {
+    /*
+        Inserts the record, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(domain_name, record_type, record_value, ttl, priority, is_active) VALUES (?, ?, ?, ?, ?, ?);", tbl_name))
+            .bind(self.domain_name)
+            .bind(self.record_type)
+            .bind(self.record_value)
+            .bind(self.ttl)
+            .bind(self.priority)
+            .bind(self.is_active)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Overwrites the record with the same id. Returns false if there is no such record.
+        updated_at is maintained by the database.
+    */
+    pub async fn _update(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET domain_name = ?, record_type = ?, record_value = ?, ttl = ?, priority = ?, is_active = ? WHERE id = ?;", tbl_name))
+            .bind(self.domain_name)
+            .bind(self.record_type)
+            .bind(self.record_value)
+            .bind(self.ttl)
+            .bind(self.priority)
+            .bind(self.is_active)
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Activates or deactivates the record with the same id. Returns false if there is no such record.
+    */
+    pub async fn _set_active(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, is_active: bool) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET is_active = ? WHERE id = ?;", tbl_name))
+            .bind(is_active)
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes the record with the same id. Returns false if there is no such record.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", tbl_name))
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn serialize(self) -> Result<ResourceRecord, DnsError> {
+        // TODO: if the name is not set, the packet will
+        //      still successfully serialize (but be malformed)
+        Ok(ResourceRecord::default()
+            .with_rdata(RData::from_wire(self.record_type, &self.record_value)?)
+            .with_rclass(1) // TODO: this shouldn't be hardcoded
+            .with_ttl(self.ttl))
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_domain_name(mut self, domain_name: String) -> Self {
+        self.domain_name = domain_name;
+        self
+    }
+
+    pub fn with_record_type(mut self, record_type: RecordType) -> Self {
+        self.record_type = record_type.into();
+        self
+    }
+
+    pub fn with_record_value(mut self, record_value: Vec<u8>) -> Self {
+        self.record_value = record_value;
+        self
+    }
+
+    /*
+        Sets record type and value from typed RDATA, stored in uncompressed wire format
+    */
+    pub fn with_rdata(mut self, rdata: RData) -> Result<Self, DnsError> {
+        self.record_type = rdata.rtype();
+        self.record_value = rdata.to_wire()?;
+        Ok(self)
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn with_active(mut self, is_active: bool) -> Self {
+        self.is_active = is_active;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn domain_name(&self) -> &str {
+        &self.domain_name
+    }
+
+    pub fn record_type(&self) -> u16 {
+        self.record_type
+    }
+
+    pub fn rdata(&self) -> Result<RData, DnsError> {
+        RData::from_wire(self.record_type, &self.record_value)
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn priority(&self) -> Option<u32> {
+        self.priority
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.updated_at
+    }
+}
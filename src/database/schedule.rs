@@ -0,0 +1,109 @@
+use crate::{
+    error::{DnsError, RuleError},
+    filter::Schedule,
+};
+
+#[derive(sqlx::FromRow)]
+pub struct ScheduleEntity {
+    id: u64,
+    name: String,
+    days: String,
+    start_time: chrono::NaiveTime,
+    end_time: chrono::NaiveTime,
+    created_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Default for ScheduleEntity {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::default(),
+            days: String::default(),
+            start_time: chrono::NaiveTime::MIN,
+            end_time: chrono::NaiveTime::MIN,
+            created_at: None,
+        }
+    }
+}
+
+impl ScheduleEntity {
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<ScheduleEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id", tbl_name))
+            .fetch_all(db).await?)
+    }
+
+    /*
+        Inserts the schedule, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(name, days, start_time, end_time) VALUES (?, ?, ?, ?);", tbl_name))
+            .bind(self.name)
+            .bind(self.days)
+            .bind(self.start_time)
+            .bind(self.end_time)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Deletes the schedule with the same id. Fails while rules still refer to it,
+        returns false if there is no such schedule.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", tbl_name))
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn to_schedule(&self) -> Result<Schedule, RuleError> {
+        Ok(Schedule::new(
+            &self.name,
+            Schedule::parse_days(&self.days)?,
+            self.start_time,
+            self.end_time,
+        ))
+    }
+
+    /*
+        Takes name, days and time window from a schedule
+    */
+    pub fn with_schedule(mut self, schedule: &Schedule) -> Self {
+        self.name = schedule.name.clone();
+        self.days = Schedule::days_to_string(&schedule.days);
+        self.start_time = schedule.start;
+        self.end_time = schedule.end;
+        self
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn days(&self) -> &str {
+        &self.days
+    }
+
+    pub fn start_time(&self) -> chrono::NaiveTime {
+        self.start_time
+    }
+
+    pub fn end_time(&self) -> chrono::NaiveTime {
+        self.end_time
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+}
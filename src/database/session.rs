@@ -0,0 +1,75 @@
+use crate::error::DnsError;
+
+#[derive(sqlx::FromRow, Default)]
+pub struct SessionEntity {
+    id: u64,
+    user_id: u64,
+    token_hash: String,
+    // only read by looking at the database
+    #[allow(unused)]
+    created_at: Option<chrono::NaiveDateTime>,
+    expires_at: chrono::NaiveDateTime,
+}
+
+impl SessionEntity {
+    pub fn new(user_id: u64, token_hash: String, expires_at: chrono::NaiveDateTime) -> Self {
+        SessionEntity {
+            user_id,
+            token_hash,
+            expires_at,
+            ..Default::default()
+        }
+    }
+
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(user_id, token_hash, expires_at) VALUES (?, ?, ?);", tbl_name))
+            .bind(self.user_id as i64)
+            .bind(self.token_hash)
+            .bind(self.expires_at)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Ends the session with the given token hash. Returns false if there is no such session.
+    */
+    pub async fn _delete_by_token(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, token_hash: &str) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE token_hash = ?;", tbl_name))
+            .bind(token_hash)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Ends all sessions of a user, e.g. after their password changed
+    */
+    pub async fn _delete_for_user(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, user_id: u64) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE user_id = ?;", tbl_name))
+            .bind(user_id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub async fn _delete_expired(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, now: chrono::NaiveDateTime) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE expires_at <= ?;", tbl_name))
+            .bind(now)
+            .execute(db).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn expires_at(&self) -> chrono::NaiveDateTime {
+        self.expires_at
+    }
+}
@@ -0,0 +1,121 @@
+use crate::error::DnsError;
+
+#[derive(sqlx::FromRow)]
+pub struct UpstreamEntity {
+    id: u64,
+    address: String,
+    port: u16,
+    client_group: Option<String>,
+    created_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Default for UpstreamEntity {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            address: String::default(),
+            port: 53,
+            client_group: None,
+            created_at: None,
+        }
+    }
+}
+
+impl UpstreamEntity {
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<UpstreamEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id", tbl_name))
+            .fetch_all(db).await?)
+    }
+
+    /*
+        Fetches the servers of a client group, or those for clients
+        outside of any group if client_group is None
+    */
+    pub async fn _fetch_for_group(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, client_group: Option<&str>) -> Result<Vec<UpstreamEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE client_group IS ? ORDER BY id", tbl_name))
+            .bind(client_group)
+            .fetch_all(db).await?)
+    }
+
+    /*
+        Inserts the server, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(address, port, client_group) VALUES (?, ?, ?);", tbl_name))
+            .bind(self.address)
+            .bind(self.port)
+            .bind(self.client_group)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Deletes the server with the same id. Returns false if there is no such server.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE id = ?;", tbl_name))
+            .bind(self.id as i64)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes all servers of a client group, returning how many there were
+    */
+    pub async fn _delete_for_group(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, client_group: &str) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE client_group = ?;", tbl_name))
+            .bind(client_group)
+            .execute(db).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    pub fn with_address(mut self, address: String) -> Self {
+        self.address = address;
+        self
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn with_client_group(mut self, client_group: String) -> Self {
+        self.client_group = Some(client_group);
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /*
+        Server in the form the resolver expects
+    */
+    pub fn server(&self) -> (String, u16) {
+        (self.address.clone(), self.port)
+    }
+
+    pub fn client_group(&self) -> Option<&str> {
+        self.client_group.as_deref()
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+}
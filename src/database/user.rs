@@ -0,0 +1,146 @@
+use crate::{
+    auth::Role,
+    error::{AuthError, DnsError},
+};
+
+#[derive(sqlx::FromRow, Clone)]
+pub struct UserEntity {
+    id: u64,
+    username: String,
+    password_hash: String,
+    role: String,
+    created_at: Option<chrono::NaiveDateTime>,
+    updated_at: Option<chrono::NaiveDateTime>,
+}
+
+impl Default for UserEntity {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            username: String::default(),
+            password_hash: String::default(),
+            role: Role::Viewer.as_str().to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl UserEntity {
+    pub async fn _fetch_all(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<Vec<UserEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} ORDER BY id", tbl_name))
+            .fetch_all(db).await?)
+    }
+
+    pub async fn _fetch_by_name(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, username: &str) -> Result<Option<UserEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!("SELECT * FROM {} WHERE username = ?", tbl_name))
+            .bind(username)
+            .fetch_optional(db).await?)
+    }
+
+    /*
+        Fetches the user a session belongs to, unless it expired before now
+    */
+    pub async fn _fetch_by_session(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, sessions_tbl: String, token_hash: &str, now: chrono::NaiveDateTime) -> Result<Option<UserEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!(
+            "SELECT u.* FROM {} u JOIN {} s ON s.user_id = u.id WHERE s.token_hash = ? AND s.expires_at > ?",
+            tbl_name, sessions_tbl
+        ))
+            .bind(token_hash)
+            .bind(now)
+            .fetch_optional(db).await?)
+    }
+
+    pub async fn _fetch_by_api_key(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String, api_keys_tbl: String, key_hash: &str) -> Result<Option<UserEntity>, DnsError> {
+        Ok(sqlx::query_as(&format!(
+            "SELECT u.* FROM {} u JOIN {} k ON k.user_id = u.id WHERE k.key_hash = ?",
+            tbl_name, api_keys_tbl
+        ))
+            .bind(key_hash)
+            .fetch_optional(db).await?)
+    }
+
+    pub async fn _count(db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT COUNT(*) FROM {}", tbl_name))
+            .fetch_one(db).await?;
+
+        Ok(count as u64)
+    }
+
+    /*
+        Inserts the user, returning the id it was assigned
+    */
+    pub async fn _insert(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<u64, DnsError> {
+        let result = sqlx::query(&format!("INSERT INTO {}(username, password_hash, role) VALUES (?, ?, ?);", tbl_name))
+            .bind(self.username)
+            .bind(self.password_hash)
+            .bind(self.role)
+            .execute(db).await?;
+
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    /*
+        Overwrites password and role of the user with the same name. Returns false if there is no such user.
+    */
+    pub async fn _update(self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("UPDATE {} SET password_hash = ?, role = ? WHERE username = ?;", tbl_name))
+            .bind(self.password_hash)
+            .bind(self.role)
+            .bind(self.username)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /*
+        Deletes the user with the same name along with its sessions and API keys.
+        Returns false if there is no such user.
+    */
+    pub async fn _delete(&self, db: &sqlx::Pool<sqlx::Sqlite>, tbl_name: String) -> Result<bool, DnsError> {
+        let result = sqlx::query(&format!("DELETE FROM {} WHERE username = ?;", tbl_name))
+            .bind(&self.username)
+            .execute(db).await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub fn with_username(mut self, username: String) -> Self {
+        self.username = username;
+        self
+    }
+
+    pub fn with_password_hash(mut self, password_hash: String) -> Self {
+        self.password_hash = password_hash;
+        self
+    }
+
+    pub fn with_role(mut self, role: Role) -> Self {
+        self.role = role.as_str().to_string();
+        self
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    pub fn password_hash(&self) -> &str {
+        &self.password_hash
+    }
+
+    pub fn role(&self) -> Result<Role, AuthError> {
+        self.role.parse()
+    }
+
+    pub fn created_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.created_at
+    }
+
+    pub fn updated_at(&self) -> Option<chrono::NaiveDateTime> {
+        self.updated_at
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt::{Display, Formatter};
+
+use super::DnsError;
+
+#[derive(Debug)]
+pub enum AuthError {
+    // user supplied account data (role, username, password) is invalid
+    InvalidAccount(String),
+    // passwords couldn't be hashed or verified
+    Hashing(String),
+    // reading or writing users, sessions or API keys failed
+    Database(DnsError),
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::InvalidAccount(reason) => write!(f, "Invalid account: {}", reason),
+            AuthError::Hashing(reason) => write!(f, "Password hashing failed: {}", reason),
+            AuthError::Database(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl From<DnsError> for AuthError {
+    fn from(err: DnsError) -> Self {
+        AuthError::Database(err)
+    }
+}
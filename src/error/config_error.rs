@@ -0,0 +1,15 @@
+use std::fmt::{Display, Formatter};
+
+/*
+    Configuration file that is unreadable or has invalid values
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfigError {}
@@ -0,0 +1,210 @@
+use std::fmt::{Display, Formatter};
+
+use crate::protocol::packet::flags::ResponseCode;
+
+/*
+    Reasons a message couldn't be read from the wire
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedEnd,
+    InvalidPointer,
+    UnsupportedLabel,
+    InvalidLabel,
+    NameTooLong,
+    RdataLength,
+    InvalidOpt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub offset: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Query(sqlx::Error),
+    Migration(sqlx::migrate::MigrateError),
+}
+
+#[derive(Debug)]
+pub enum UpstreamError {
+    NoServers,
+    Timeout,
+    Io(std::io::Error),
+    InvalidResponse(Box<DnsError>),
+    Rcode(ResponseCode),
+}
+
+#[derive(Debug)]
+pub enum DnsError {
+    // received message is malformed
+    Parse(ParseError),
+    // message or name couldn't be written to the wire
+    Encode(String),
+    Storage(StorageError),
+    Upstream(UpstreamError),
+    // nothing could be found to answer the question with
+    NotFound,
+    NotImplemented(String),
+    // we won't answer the query, be it for lack of capacity or by policy
+    Refused(String),
+    // client speaks an EDNS version we don't support
+    BadVersion(u8),
+    Io(std::io::Error),
+}
+
+impl ParseError {
+    pub fn new(offset: usize, kind: ParseErrorKind) -> Self {
+        ParseError { offset, kind }
+    }
+}
+
+impl DnsError {
+    pub fn parse(offset: usize, kind: ParseErrorKind) -> Self {
+        DnsError::Parse(ParseError::new(offset, kind))
+    }
+
+    pub fn encode(reason: impl Into<String>) -> Self {
+        DnsError::Encode(reason.into())
+    }
+
+    /*
+        Response code to send back to a client whose query failed with this error
+    */
+    pub fn response_code(&self) -> ResponseCode {
+        match self {
+            DnsError::Parse(_) => ResponseCode::FormatError,
+            DnsError::NotFound => ResponseCode::NameError,
+            DnsError::NotImplemented(_) => ResponseCode::NotImplemented,
+            DnsError::Refused(_) => ResponseCode::Refused,
+            DnsError::BadVersion(_) => ResponseCode::BadVersion,
+            DnsError::Encode(_)
+            | DnsError::Storage(_)
+            | DnsError::Upstream(_)
+            | DnsError::Io(_) => ResponseCode::ServerFailure,
+        }
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of message"),
+            ParseErrorKind::InvalidPointer => write!(f, "invalid compression pointer"),
+            ParseErrorKind::UnsupportedLabel => write!(f, "unsupported label type"),
+            ParseErrorKind::InvalidLabel => write!(f, "label is not valid UTF-8"),
+            ParseErrorKind::NameTooLong => write!(f, "domain name exceeds 255 bytes"),
+            ParseErrorKind::RdataLength => write!(f, "RDATA length mismatch"),
+            ParseErrorKind::InvalidOpt => write!(f, "invalid OPT record"),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageError::Query(err) => write!(f, "query failed: {}", err),
+            StorageError::Migration(err) => write!(f, "migration failed: {}", err),
+        }
+    }
+}
+
+impl Display for UpstreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamError::NoServers => write!(f, "no upstream servers configured"),
+            UpstreamError::Timeout => write!(f, "upstream server timed out"),
+            UpstreamError::Io(err) => write!(f, "{}", err),
+            UpstreamError::InvalidResponse(err) => write!(f, "invalid response: {}", err),
+            UpstreamError::Rcode(rcode) => write!(f, "upstream server answered {:?}", rcode),
+        }
+    }
+}
+
+impl Display for DnsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DnsError::Parse(err) => write!(f, "Malformed message: {}", err),
+            DnsError::Encode(reason) => write!(f, "Failed to encode message: {}", reason),
+            DnsError::Storage(err) => write!(f, "Storage error: {}", err),
+            DnsError::Upstream(err) => write!(f, "Upstream error: {}", err),
+            DnsError::NotFound => write!(f, "No questions could be answered"),
+            DnsError::NotImplemented(what) => write!(f, "Not implemented: {}", what),
+            DnsError::Refused(reason) => write!(f, "Refused: {}", reason),
+            DnsError::BadVersion(version) => write!(f, "Unsupported EDNS version {}", version),
+            DnsError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for DnsError {}
+
+impl From<ParseError> for DnsError {
+    fn from(err: ParseError) -> Self {
+        DnsError::Parse(err)
+    }
+}
+
+impl From<StorageError> for DnsError {
+    fn from(err: StorageError) -> Self {
+        DnsError::Storage(err)
+    }
+}
+
+impl From<UpstreamError> for DnsError {
+    fn from(err: UpstreamError) -> Self {
+        DnsError::Upstream(err)
+    }
+}
+
+impl From<sqlx::Error> for DnsError {
+    fn from(err: sqlx::Error) -> Self {
+        DnsError::Storage(StorageError::Query(err))
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for DnsError {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        DnsError::Storage(StorageError::Migration(err))
+    }
+}
+
+impl From<std::io::Error> for DnsError {
+    fn from(err: std::io::Error) -> Self {
+        DnsError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_codes() {
+        let cases = [
+            (DnsError::parse(12, ParseErrorKind::UnexpectedEnd), ResponseCode::FormatError),
+            (DnsError::NotFound, ResponseCode::NameError),
+            (DnsError::Upstream(UpstreamError::Timeout), ResponseCode::ServerFailure),
+            (DnsError::Storage(StorageError::Query(sqlx::Error::RowNotFound)), ResponseCode::ServerFailure),
+            (DnsError::NotImplemented("opcode".to_string()), ResponseCode::NotImplemented),
+            (DnsError::Refused("too many queries in flight".to_string()), ResponseCode::Refused),
+        ];
+
+        for (err, rcode) in cases {
+            assert_eq!(err.response_code(), rcode);
+        }
+
+        assert_eq!(
+            DnsError::parse(12, ParseErrorKind::UnexpectedEnd).to_string(),
+            "Malformed message: unexpected end of message at offset 12"
+        );
+    }
+}
@@ -0,0 +1,11 @@
+mod auth_error;
+mod config_error;
+mod dns_error;
+mod presentation_error;
+mod rule_error;
+
+pub use auth_error::AuthError;
+pub use config_error::ConfigError;
+pub use dns_error::{DnsError, ParseErrorKind, StorageError, UpstreamError};
+pub use presentation_error::PresentationError;
+pub use rule_error::RuleError;
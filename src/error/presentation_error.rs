@@ -0,0 +1,15 @@
+use std::fmt::{Display, Formatter};
+
+/*
+    Record data given in presentation format, e.g. by tinydns-ctl or the admin API, that can't be used
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresentationError(pub String);
+
+impl Display for PresentationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PresentationError {}
@@ -0,0 +1,15 @@
+use std::fmt::{Display, Formatter};
+
+/*
+    Filter rule, schedule or client group definition that can't be used
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleError(pub String);
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid filter rule: {}", self.0)
+    }
+}
+
+impl std::error::Error for RuleError {}
@@ -0,0 +1,97 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::protocol::packet::{flags::ResponseCode, Question, RData, RecordType, ResourceRecord};
+
+/*
+    How queries for blocked domains are answered
+*/
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum BlockMode {
+    // the domain doesn't exist
+    #[default]
+    NxDomain,
+    // the domain exists, but has no records
+    NoData,
+    // we won't answer
+    Refused,
+    // 0.0.0.0 for A and :: for AAAA queries
+    NullIp { ttl: u32 },
+    // custom addresses for A and AAAA queries, NODATA for types without an address
+    Sinkhole {
+        ipv4: Option<Ipv4Addr>,
+        ipv6: Option<Ipv6Addr>,
+        ttl: u32,
+    },
+}
+
+impl BlockMode {
+    pub fn rcode(&self) -> ResponseCode {
+        match self {
+            BlockMode::NxDomain => ResponseCode::NameError,
+            BlockMode::Refused => ResponseCode::Refused,
+            _ => ResponseCode::NoError,
+        }
+    }
+
+    /*
+        The record a blocked question is answered with, if any
+    */
+    pub fn answer(&self, question: &Question) -> Option<ResourceRecord> {
+        let (ipv4, ipv6, ttl) = match self {
+            BlockMode::NullIp { ttl } => {
+                (Some(Ipv4Addr::UNSPECIFIED), Some(Ipv6Addr::UNSPECIFIED), *ttl)
+            }
+            BlockMode::Sinkhole { ipv4, ipv6, ttl } => (*ipv4, *ipv6, *ttl),
+            _ => return None,
+        };
+
+        let rdata = match question.qtype() {
+            RecordType::A => RData::A(ipv4?),
+            RecordType::AAAA => RData::AAAA(ipv6?),
+            _ => return None,
+        };
+
+        Some(
+            ResourceRecord::default()
+                .with_name(question.name())
+                .with_rdata(rdata)
+                .with_rclass(question.qclass())
+                .with_ttl(ttl),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_mode_answer() {
+        let question = |rtype: RecordType| {
+            Question::default()
+                .with_name("ads.example.com".to_string())
+                .with_qtype(rtype.into())
+        };
+
+        assert_eq!(BlockMode::NxDomain.rcode(), ResponseCode::NameError);
+        assert!(BlockMode::NxDomain.answer(&question(RecordType::A)).is_none());
+        assert_eq!(BlockMode::Refused.rcode(), ResponseCode::Refused);
+
+        let null_ip = BlockMode::NullIp { ttl: 10 };
+        assert_eq!(null_ip.rcode(), ResponseCode::NoError);
+        let record = null_ip.answer(&question(RecordType::AAAA)).unwrap();
+        assert_eq!(record.rdata(), RData::AAAA(Ipv6Addr::UNSPECIFIED));
+        assert_eq!(record.ttl(), 10);
+
+        let sinkhole = BlockMode::Sinkhole {
+            ipv4: Some(Ipv4Addr::new(192, 168, 1, 2)),
+            ipv6: None,
+            ttl: 60,
+        };
+        let record = sinkhole.answer(&question(RecordType::A)).unwrap();
+        assert_eq!(record.name(), "ads.example.com");
+        assert_eq!(record.rdata(), RData::A(Ipv4Addr::new(192, 168, 1, 2)));
+        assert!(sinkhole.answer(&question(RecordType::AAAA)).is_none());
+        assert!(sinkhole.answer(&question(RecordType::MX)).is_none());
+    }
+}
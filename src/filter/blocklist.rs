@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use crate::error::DnsError;
+
+use super::{domain_trie::DomainTrie, BlockMode, ListFormat};
+
+/*
+    Name of the list domains added one by one end up in
+*/
+const CUSTOM_LIST: &str = "custom";
+
+#[derive(Debug)]
+struct ListSource {
+    name: String,
+    // overrides the global block mode for domains of this list
+    mode: Option<BlockMode>,
+}
+
+/*
+    Where a block comes from, which decides the Extended DNS Error it's answered with
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockSource {
+    // a domain list, e.g. one of the blocklist files
+    Blocklist,
+    // a block rule, for all clients or a client group
+    Rule,
+}
+
+/*
+    Why a name is blocked and how it should be answered
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockMatch {
+    // the blocked domain covering the name
+    pub rule: String,
+    pub list: String,
+    pub source: BlockSource,
+    pub mode: BlockMode,
+}
+
+/*
+    Domains that must not be resolved, gathered from any number of lists.
+    Blocking a domain blocks all of its subdomains as well.
+*/
+#[derive(Debug)]
+pub struct Blocklist {
+    domains: DomainTrie,
+    lists: Vec<ListSource>,
+    mode: BlockMode,
+}
+
+impl Default for Blocklist {
+    fn default() -> Self {
+        Blocklist {
+            domains: DomainTrie::new(),
+            lists: vec![ListSource {
+                name: CUSTOM_LIST.to_string(),
+                mode: None,
+            }],
+            mode: BlockMode::default(),
+        }
+    }
+}
+
+impl Blocklist {
+    pub fn new() -> Self {
+        Blocklist::default()
+    }
+
+    /*
+        How blocked domains are answered, unless their list says otherwise
+    */
+    pub fn with_mode(mut self, mode: BlockMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_domain(mut self, domain: &str) -> Self {
+        self.add_domain(domain);
+        self
+    }
+
+    pub fn add_domain(&mut self, domain: &str) -> bool {
+        self.domains.insert(domain, 0)
+    }
+
+    /*
+        Adds all domains of a list, returns how many of them weren't blocked yet.
+        Domains that are on several lists are answered according to the first of them.
+    */
+    pub fn load_str(
+        &mut self,
+        name: &str,
+        content: &str,
+        format: ListFormat,
+        mode: Option<BlockMode>,
+    ) -> usize {
+        let tag = self.lists.len();
+        self.lists.push(ListSource {
+            name: name.to_string(),
+            mode,
+        });
+
+        content
+            .lines()
+            .flat_map(|line| format.parse_line(line))
+            .filter(|domain| self.domains.insert(domain, tag))
+            .count()
+    }
+
+    pub fn load_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        format: ListFormat,
+        mode: Option<BlockMode>,
+    ) -> Result<usize, DnsError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        let added = self.load_str(&path.display().to_string(), &content, format, mode);
+        log::info!("Loaded {} blocked domains from {}", added, path.display());
+        Ok(added)
+    }
+
+    pub fn mode(&self) -> &BlockMode {
+        &self.mode
+    }
+
+    /*
+        Returns the rule blocking name, if there is one
+    */
+    pub fn find(&self, name: &str) -> Option<BlockMatch> {
+        let (rule, tag) = self.domains.find(name)?;
+        let list = &self.lists[tag];
+
+        Some(BlockMatch {
+            rule,
+            list: list.name.clone(),
+            source: BlockSource::Blocklist,
+            mode: list.mode.clone().unwrap_or_else(|| self.mode.clone()),
+        })
+    }
+
+    pub fn is_blocked(&self, name: &str) -> bool {
+        self.domains.contains(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_blocklist_modes() {
+        let mut blocklist = Blocklist::new()
+            .with_mode(BlockMode::Refused)
+            .with_domain("custom.example.com");
+
+        let sinkhole = BlockMode::Sinkhole {
+            ipv4: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            ipv6: None,
+            ttl: 30,
+        };
+        let added = blocklist.load_str(
+            "ads",
+            "0.0.0.0 ads.example.com\n0.0.0.0 custom.example.com\n",
+            ListFormat::Hosts,
+            Some(sinkhole.clone()),
+        );
+        assert_eq!(added, 1);
+
+        let matched = blocklist.find("img.ads.example.com").unwrap();
+        assert_eq!(matched.rule, "ads.example.com");
+        assert_eq!(matched.list, "ads");
+        assert_eq!(matched.mode, sinkhole);
+
+        // the domain was on the custom list first, so the global mode applies
+        let matched = blocklist.find("custom.example.com").unwrap();
+        assert_eq!(matched.list, CUSTOM_LIST);
+        assert_eq!(matched.mode, BlockMode::Refused);
+
+        assert!(blocklist.find("example.com").is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+#[derive(Default, Debug)]
+struct TrieNode {
+    children: HashMap<String, TrieNode>,
+    // a rule ends at this label, covering the name and all of its subdomains.
+    // Holds the tag the rule was inserted with.
+    terminal: Option<usize>,
+}
+
+/*
+    Set of domains stored label by label from the root down, so that checking
+    whether a name or any of its parent domains is contained only takes a
+    single walk along the labels of the name
+*/
+#[derive(Default, Debug)]
+pub struct DomainTrie {
+    root: TrieNode,
+    len: usize,
+}
+
+pub fn normalize_domain(domain: &str) -> String {
+    let domain = domain.trim();
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    domain.to_lowercase()
+}
+
+impl DomainTrie {
+    pub fn new() -> Self {
+        DomainTrie::default()
+    }
+
+    /*
+        Adds a domain along with a tag (e.g. the list it came from),
+        returns false if it was already contained
+    */
+    pub fn insert(&mut self, domain: &str, tag: usize) -> bool {
+        let domain = normalize_domain(domain);
+        if domain.is_empty() {
+            return false;
+        }
+
+        let mut node = &mut self.root;
+        for label in domain.rsplit('.') {
+            node = node.children.entry(label.to_string()).or_default();
+        }
+
+        if node.terminal.is_some() {
+            return false;
+        }
+        node.terminal = Some(tag);
+        self.len += 1;
+        true
+    }
+
+    /*
+        Returns the contained domain that covers name, i.e. name itself or its closest
+        parent domain, along with its tag
+    */
+    pub fn find(&self, name: &str) -> Option<(String, usize)> {
+        let name = normalize_domain(name);
+        let labels: Vec<&str> = name.rsplit('.').collect();
+
+        let mut node = &self.root;
+        for (depth, label) in labels.iter().enumerate() {
+            node = node.children.get(*label)?;
+            if let Some(tag) = node.terminal {
+                let matched: Vec<&str> = labels[..=depth].iter().rev().copied().collect();
+                return Some((matched.join("."), tag));
+            }
+        }
+
+        None
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_trie() {
+        let mut trie = DomainTrie::new();
+        assert!(trie.insert("ads.example.com", 0));
+        assert!(trie.insert("Tracker.NET.", 1));
+        assert!(!trie.insert("tracker.net", 2));
+        assert_eq!(trie.len(), 2);
+
+        assert_eq!(trie.find("ads.example.com"), Some(("ads.example.com".to_string(), 0)));
+        assert_eq!(trie.find("x.y.ADS.example.com."), Some(("ads.example.com".to_string(), 0)));
+        assert_eq!(trie.find("cdn.tracker.net"), Some(("tracker.net".to_string(), 1)));
+
+        // parents and siblings of blocked domains aren't blocked
+        assert!(!trie.contains("example.com"));
+        assert!(!trie.contains("images.example.com"));
+        assert!(!trie.contains("badads.example.com"));
+        assert!(!trie.contains("net"));
+    }
+}
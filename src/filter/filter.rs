@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    database::{Database, FilterRuleEntity, ScheduleEntity},
+    error::DnsError,
+};
+
+use super::{BlockMatch, BlockSource, Blocklist, Clock, FilterRule, LocalClock, RuleSet};
+
+/*
+    Outcome of filtering a name, along with the rule responsible for it
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allowed(FilterRule),
+    Blocked(BlockMatch),
+}
+
+/*
+    Decides which names get blocked. Allow rules beat block rules,
+    which in turn are checked before the blocklists.
+*/
+#[derive(Debug)]
+pub struct Filter {
+    // shared with the filters forked off this one
+    blocklist: Arc<Blocklist>,
+    // replaced as a whole whenever the rules change
+    rules: RwLock<RuleSet>,
+    // decides which scheduled rules are in effect
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            blocklist: Arc::default(),
+            rules: RwLock::default(),
+            clock: Arc::new(LocalClock),
+        }
+    }
+}
+
+impl Filter {
+    pub fn new() -> Self {
+        Filter::default()
+    }
+
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = Arc::new(blocklist);
+        self
+    }
+
+    /*
+        Blocklist shared with other filters, e.g. those of client groups with the same lists
+    */
+    pub fn with_shared_blocklist(mut self, blocklist: Arc<Blocklist>) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /*
+        A filter with the same blocklist and clock, but rules of its own
+    */
+    pub fn fork(&self) -> Filter {
+        Filter {
+            blocklist: self.blocklist.clone(),
+            rules: RwLock::default(),
+            clock: self.clock.clone(),
+        }
+    }
+
+    pub fn with_rules(self, rules: RuleSet) -> Self {
+        self.set_rules(rules);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn set_rules(&self, rules: RuleSet) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /*
+        Replaces the rules by the active ones stored in the database for all clients,
+        plus those of a client group if group is given.
+        Rules that fail to compile are skipped. Returns the number of rules loaded.
+    */
+    pub async fn reload_rules(&self, db: &Database, group: Option<&str>) -> Result<usize, DnsError> {
+        let mut schedules = HashMap::new();
+        for entity in ScheduleEntity::_fetch_all(db.get_pool(), db.schedules_tbl()).await? {
+            match entity.to_schedule() {
+                Ok(schedule) => {
+                    schedules.insert(entity.id(), schedule);
+                }
+                Err(err) => log::warn!("Skipping schedule {}: {}", entity.id(), err),
+            }
+        }
+
+        let entities =
+            FilterRuleEntity::_fetch_for_group(db.get_pool(), db.filter_rules_tbl(), group).await?;
+
+        let mut rules = RuleSet::new();
+        for entity in entities {
+            if let Err(err) = entity.to_rule(&schedules).and_then(|rule| rules.add_rule(rule)) {
+                log::warn!("Skipping filter rule {}: {}", entity.id(), err);
+            }
+        }
+
+        let loaded = rules.len();
+        self.set_rules(rules);
+        match group {
+            Some(group) => log::info!("Loaded {} filter rules for group {}", loaded, group),
+            None => log::info!("Loaded {} filter rules", loaded),
+        }
+        Ok(loaded)
+    }
+
+    /*
+        Returns whether name is explicitly allowed or blocked, or None if no rule applies.
+        Scheduled rules only count while their window is open.
+    */
+    pub fn check(&self, name: &str) -> Option<Verdict> {
+        let now = self.clock.now();
+        let rules = self.rules.read().unwrap();
+        if let Some(rule) = rules.find_allow(name, now) {
+            return Some(Verdict::Allowed(rule.clone()));
+        }
+
+        if let Some(rule) = rules.find_block(name, now) {
+            return Some(Verdict::Blocked(BlockMatch {
+                rule: rule.pattern.clone(),
+                list: format!("rule {}", rule.id),
+                source: BlockSource::Rule,
+                mode: self.blocklist.mode().clone(),
+            }));
+        }
+
+        self.blocklist.find(name).map(Verdict::Blocked)
+    }
+
+    pub fn blocklist(&self) -> &Blocklist {
+        &self.blocklist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+    use crate::filter::{RuleAction, RuleKind, Schedule};
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct FixedClock(Mutex<NaiveDateTime>);
+
+    impl FixedClock {
+        fn set(&self, day: u32, hour: u32) {
+            *self.0.lock().unwrap() = NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap();
+        }
+    }
+
+    impl Clock for FixedClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_filter_precedence() {
+        let db = Database::init_mem().await.unwrap();
+        for (action, kind, pattern) in [
+            (RuleAction::Allow, RuleKind::Exact, "cdn.ads.example.com"),
+            (RuleAction::Block, RuleKind::Wildcard, "*.tracker.example"),
+            (RuleAction::Block, RuleKind::Regex, "(broken"),
+        ] {
+            FilterRuleEntity::default()
+                .with_action(action)
+                .with_kind(kind)
+                .with_pattern(pattern.to_string())
+                ._insert(db.get_pool(), db.filter_rules_tbl())
+                .await
+                .unwrap();
+        }
+
+        let filter = Filter::new().with_blocklist(Blocklist::new().with_domain("ads.example.com"));
+        // the broken regex is skipped
+        assert_eq!(filter.reload_rules(&db, None).await.unwrap(), 2);
+
+        // allow beats block
+        match filter.check("img.cdn.ads.example.com") {
+            Some(Verdict::Allowed(rule)) => assert_eq!(rule.pattern, "cdn.ads.example.com"),
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+
+        match filter.check("www.ads.example.com") {
+            Some(Verdict::Blocked(matched)) => assert_eq!(matched.rule, "ads.example.com"),
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+
+        match filter.check("eu.tracker.example") {
+            Some(Verdict::Blocked(matched)) => assert_eq!(matched.list, "rule 2"),
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+
+        assert!(filter.check("example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_rules() {
+        let db = Database::init_mem().await.unwrap();
+        let school_nights = Schedule::new(
+            "school nights",
+            vec![Weekday::Sun, Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu],
+            NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+        );
+        let schedule_id = ScheduleEntity::default()
+            .with_schedule(&school_nights)
+            ._insert(db.get_pool(), db.schedules_tbl())
+            .await
+            .unwrap();
+
+        FilterRuleEntity::default()
+            .with_action(RuleAction::Block)
+            .with_kind(RuleKind::Exact)
+            .with_pattern("social.example".to_string())
+            .with_client_group("kids".to_string())
+            .with_schedule_id(schedule_id)
+            ._insert(db.get_pool(), db.filter_rules_tbl())
+            .await
+            .unwrap();
+
+        let clock = Arc::new(FixedClock(Mutex::new(NaiveDateTime::MIN)));
+        let filter = Filter::new().with_clock(clock.clone());
+        // rules of other groups are left out
+        assert_eq!(filter.reload_rules(&db, None).await.unwrap(), 0);
+        assert_eq!(filter.reload_rules(&db, Some("kids")).await.unwrap(), 1);
+
+        // 2026-10-18 is a sunday
+        clock.set(18, 22);
+        match filter.check("www.social.example") {
+            Some(Verdict::Blocked(matched)) => assert_eq!(matched.list, "rule 1"),
+            verdict => panic!("unexpected verdict {:?}", verdict),
+        }
+        clock.set(19, 6);
+        assert!(filter.check("social.example").is_some());
+
+        clock.set(19, 12);
+        assert!(filter.check("social.example").is_none());
+        // friday night is not a school night
+        clock.set(23, 22);
+        assert!(filter.check("social.example").is_none());
+    }
+}
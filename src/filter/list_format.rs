@@ -0,0 +1,176 @@
+use std::net::IpAddr;
+
+use serde::Deserialize;
+
+use super::domain_trie::normalize_domain;
+
+/*
+    Hosts file entries that point a machine to itself, rather than blocking anything
+*/
+const HOSTS_RESERVED_NAMES: [&str; 8] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+    "ip6-loopback",
+    "ip6-allnodes",
+    "ip6-allrouters",
+];
+
+/*
+    Formats blocklists are commonly distributed in
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    // "0.0.0.0 domain", as found in /etc/hosts
+    Hosts,
+    // one domain per line
+    #[serde(alias = "domains")]
+    Plain,
+    // "||domain^" network rules of Adblock-style filter lists
+    Adblock,
+    // detect the format line by line
+    Auto,
+}
+
+impl std::str::FromStr for ListFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hosts" => Ok(ListFormat::Hosts),
+            "plain" | "domains" => Ok(ListFormat::Plain),
+            "adblock" => Ok(ListFormat::Adblock),
+            "auto" => Ok(ListFormat::Auto),
+            _ => Err(format!("Unknown list format {}", s)),
+        }
+    }
+}
+
+pub fn is_valid_domain(domain: &str) -> bool {
+    if domain.is_empty() || domain.len() > 253 || domain.parse::<IpAddr>().is_ok() {
+        return false;
+    }
+
+    domain.split('.').all(|label| {
+        !label.is_empty()
+            && label.len() <= 63
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+impl ListFormat {
+    /*
+        Extracts the blocked domains from a single line of a list.
+        Comments, headers and rules that can't be expressed in DNS yield nothing.
+    */
+    pub fn parse_line(self, line: &str) -> Vec<String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Vec::new();
+        }
+
+        let format = match self {
+            ListFormat::Auto => Self::detect(line),
+            format => format,
+        };
+
+        let domains = match format {
+            ListFormat::Hosts => Self::parse_hosts(line),
+            ListFormat::Plain => Self::parse_plain(line),
+            ListFormat::Adblock => Self::parse_adblock(line),
+            ListFormat::Auto => unreachable!("format was detected above"),
+        };
+
+        domains
+            .into_iter()
+            .map(normalize_domain)
+            .filter(|domain| is_valid_domain(domain))
+            .collect()
+    }
+
+    fn detect(line: &str) -> ListFormat {
+        if line.starts_with("||") || line.starts_with('!') || line.starts_with('[') {
+            return ListFormat::Adblock;
+        }
+
+        match line.split_whitespace().next() {
+            Some(first) if first.parse::<IpAddr>().is_ok() => ListFormat::Hosts,
+            _ => ListFormat::Plain,
+        }
+    }
+
+    fn strip_comment(line: &str) -> &str {
+        line.split('#').next().unwrap_or_default()
+    }
+
+    fn parse_hosts(line: &str) -> Vec<&str> {
+        let mut tokens = Self::strip_comment(line).split_whitespace();
+        match tokens.next() {
+            Some(address) if address.parse::<IpAddr>().is_ok() => tokens
+                .filter(|name| !HOSTS_RESERVED_NAMES.contains(&name.to_lowercase().as_str()))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn parse_plain(line: &str) -> Vec<&str> {
+        Self::strip_comment(line).split_whitespace().take(1).collect()
+    }
+
+    fn parse_adblock(line: &str) -> Vec<&str> {
+        // comments, headers and exceptions (exceptions belong on the allowlist)
+        let Some(rule) = line.strip_prefix("||") else {
+            return Vec::new();
+        };
+        let Some((domain, options)) = rule.split_once('^') else {
+            return Vec::new();
+        };
+
+        // options restricting rules to certain requests have no meaning for DNS
+        match options {
+            "" | "$important" => vec![domain],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let hosts = ListFormat::Hosts;
+        assert_eq!(hosts.parse_line("0.0.0.0 ads.example.com"), vec!["ads.example.com"]);
+        assert_eq!(
+            hosts.parse_line("127.0.0.1 a.example.com B.example.com # trackers"),
+            vec!["a.example.com", "b.example.com"]
+        );
+        assert!(hosts.parse_line("127.0.0.1 localhost").is_empty());
+        assert!(hosts.parse_line("::1 ip6-localhost").is_empty());
+        assert!(hosts.parse_line("# 0.0.0.0 commented.example.com").is_empty());
+
+        let plain = ListFormat::Plain;
+        assert_eq!(plain.parse_line("tracker.net. # comment"), vec!["tracker.net"]);
+        assert!(plain.parse_line("not/a/domain").is_empty());
+
+        let adblock = ListFormat::Adblock;
+        assert_eq!(adblock.parse_line("||ads.example.com^"), vec!["ads.example.com"]);
+        assert_eq!(adblock.parse_line("||ads.example.com^$important"), vec!["ads.example.com"]);
+        assert!(adblock.parse_line("||ads.example.com^$third-party").is_empty());
+        assert!(adblock.parse_line("@@||good.example.com^").is_empty());
+        assert!(adblock.parse_line("! Title: some list").is_empty());
+        assert!(adblock.parse_line("[Adblock Plus 2.0]").is_empty());
+        assert!(adblock.parse_line("||example.com/ads/*").is_empty());
+
+        let auto = ListFormat::Auto;
+        assert_eq!(auto.parse_line("0.0.0.0 a.example.com"), vec!["a.example.com"]);
+        assert_eq!(auto.parse_line("||b.example.com^"), vec!["b.example.com"]);
+        assert_eq!(auto.parse_line("c.example.com"), vec!["c.example.com"]);
+    }
+}
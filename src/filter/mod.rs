@@ -0,0 +1,15 @@
+mod block_mode;
+mod blocklist;
+mod domain_trie;
+mod filter;
+mod list_format;
+mod rules;
+mod schedule;
+
+pub use block_mode::BlockMode;
+pub use blocklist::{BlockMatch, BlockSource, Blocklist};
+pub use domain_trie::normalize_domain;
+pub use filter::{Filter, Verdict};
+pub use list_format::{is_valid_domain, ListFormat};
+pub use rules::{FilterRule, RuleAction, RuleKind, RuleSet};
+pub use schedule::{Clock, LocalClock, Schedule};
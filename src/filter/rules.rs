@@ -0,0 +1,303 @@
+use std::fmt::{Display, Formatter};
+
+use chrono::NaiveDateTime;
+use regex::{Regex, RegexBuilder};
+
+use crate::error::RuleError;
+
+use super::{
+    domain_trie::{normalize_domain, DomainTrie},
+    Schedule,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleAction {
+    Allow,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuleKind {
+    // the domain and all of its subdomains
+    Exact,
+    // '*' matches any number of characters, '?' exactly one
+    Wildcard,
+    Regex,
+}
+
+impl RuleAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleAction::Allow => "allow",
+            RuleAction::Block => "block",
+        }
+    }
+}
+
+impl RuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleKind::Exact => "exact",
+            RuleKind::Wildcard => "wildcard",
+            RuleKind::Regex => "regex",
+        }
+    }
+}
+
+impl std::str::FromStr for RuleAction {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(RuleAction::Allow),
+            "block" => Ok(RuleAction::Block),
+            _ => Err(RuleError(format!("unknown action {}", s))),
+        }
+    }
+}
+
+impl std::str::FromStr for RuleKind {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(RuleKind::Exact),
+            "wildcard" => Ok(RuleKind::Wildcard),
+            "regex" => Ok(RuleKind::Regex),
+            _ => Err(RuleError(format!("unknown rule kind {}", s))),
+        }
+    }
+}
+
+/*
+    A single allow or block rule as configured by the admin
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterRule {
+    pub id: u64,
+    pub action: RuleAction,
+    pub kind: RuleKind,
+    pub pattern: String,
+    // the rule is only in effect during the schedule's window
+    pub schedule: Option<Schedule>,
+}
+
+impl Display for FilterRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} (rule {}",
+            self.action.as_str(),
+            self.kind.as_str(),
+            self.pattern,
+            self.id
+        )?;
+        match &self.schedule {
+            Some(schedule) => write!(f, ", schedule {})", schedule.name),
+            None => write!(f, ")"),
+        }
+    }
+}
+
+/*
+    Turns a wildcard pattern into an anchored, case insensitive regex
+*/
+fn compile_wildcard(pattern: &str) -> Result<Regex, RuleError> {
+    let mut regex = String::from("^");
+    for c in normalize_domain(pattern).chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    compile_regex(&regex)
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex, RuleError> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|err| RuleError(err.to_string()))
+}
+
+#[derive(Debug)]
+enum Matcher {
+    // the domain and all of its subdomains
+    Domain(String),
+    Pattern(Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, name: &str) -> bool {
+        match self {
+            Matcher::Domain(domain) => {
+                name == domain || name.ends_with(&format!(".{}", domain))
+            }
+            Matcher::Pattern(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/*
+    Rules of a single action. Exact ones live in a trie, patterns as compiled regexes.
+    Scheduled rules are few and checked one by one, as they may be out of effect.
+*/
+#[derive(Default, Debug)]
+struct CompiledRules {
+    domains: DomainTrie,
+    patterns: Vec<(Regex, usize)>,
+    scheduled: Vec<(Matcher, usize)>,
+}
+
+impl CompiledRules {
+    fn add(&mut self, matcher: Matcher, idx: usize, scheduled: bool) {
+        match matcher {
+            _ if scheduled => self.scheduled.push((matcher, idx)),
+            Matcher::Domain(domain) => {
+                self.domains.insert(&domain, idx);
+            }
+            Matcher::Pattern(regex) => self.patterns.push((regex, idx)),
+        }
+    }
+
+    // returns the index of the first matching rule in effect
+    fn find(&self, name: &str, rules: &[FilterRule], now: NaiveDateTime) -> Option<usize> {
+        if let Some((_, idx)) = self.domains.find(name) {
+            return Some(idx);
+        }
+
+        let name = normalize_domain(name);
+        self.patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(&name))
+            .map(|(_, idx)| *idx)
+            .or_else(|| {
+                self.scheduled
+                    .iter()
+                    .filter(|(_, idx)| {
+                        rules[*idx]
+                            .schedule
+                            .as_ref()
+                            .is_some_and(|schedule| schedule.is_active_at(now))
+                    })
+                    .find(|(matcher, _)| matcher.is_match(&name))
+                    .map(|(_, idx)| *idx)
+            })
+    }
+}
+
+/*
+    Allow and block rules, where allow rules always take precedence
+*/
+#[derive(Default, Debug)]
+pub struct RuleSet {
+    rules: Vec<FilterRule>,
+    allow: CompiledRules,
+    block: CompiledRules,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    pub fn with_rule(mut self, rule: FilterRule) -> Result<Self, RuleError> {
+        self.add_rule(rule)?;
+        Ok(self)
+    }
+
+    pub fn add_rule(&mut self, rule: FilterRule) -> Result<(), RuleError> {
+        let idx = self.rules.len();
+        let compiled = match rule.action {
+            RuleAction::Allow => &mut self.allow,
+            RuleAction::Block => &mut self.block,
+        };
+
+        let matcher = match rule.kind {
+            RuleKind::Exact => {
+                let domain = normalize_domain(&rule.pattern);
+                if !super::list_format::is_valid_domain(&domain) {
+                    return Err(RuleError(format!("invalid domain {}", rule.pattern)));
+                }
+                Matcher::Domain(domain)
+            }
+            RuleKind::Wildcard => Matcher::Pattern(compile_wildcard(&rule.pattern)?),
+            RuleKind::Regex => Matcher::Pattern(compile_regex(&rule.pattern)?),
+        };
+        compiled.add(matcher, idx, rule.schedule.is_some());
+
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /*
+        First allow rule matching name that is in effect at the given (local) time
+    */
+    pub fn find_allow(&self, name: &str, now: NaiveDateTime) -> Option<&FilterRule> {
+        self.allow.find(name, &self.rules, now).map(|idx| &self.rules[idx])
+    }
+
+    /*
+        First block rule matching name that is in effect at the given (local) time
+    */
+    pub fn find_block(&self, name: &str, now: NaiveDateTime) -> Option<&FilterRule> {
+        self.block.find(name, &self.rules, now).map(|idx| &self.rules[idx])
+    }
+
+    pub fn rules(&self) -> &[FilterRule] {
+        &self.rules
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: u64, action: RuleAction, kind: RuleKind, pattern: &str) -> FilterRule {
+        FilterRule {
+            id,
+            action,
+            kind,
+            pattern: pattern.to_string(),
+            schedule: None,
+        }
+    }
+
+    #[test]
+    fn test_rule_set() {
+        let now = chrono::Local::now().naive_local();
+        let rules = RuleSet::new()
+            .with_rule(rule(1, RuleAction::Block, RuleKind::Wildcard, "*.tracker.example"))
+            .unwrap()
+            .with_rule(rule(2, RuleAction::Block, RuleKind::Regex, r"^ads?\d*\."))
+            .unwrap()
+            .with_rule(rule(3, RuleAction::Allow, RuleKind::Exact, "login.tracker.example"))
+            .unwrap();
+
+        assert_eq!(rules.find_block("eu.Tracker.example.", now).unwrap().id, 1);
+        // the wildcard requires a subdomain
+        assert!(rules.find_block("tracker.example", now).is_none());
+        assert_eq!(rules.find_block("ads3.example.com", now).unwrap().id, 2);
+        assert!(rules.find_block("badads.example.com", now).is_none());
+
+        let allowed = rules.find_allow("sso.login.tracker.example", now).unwrap();
+        assert_eq!(allowed.to_string(), "allow exact login.tracker.example (rule 3)");
+
+        assert!(RuleSet::new()
+            .with_rule(rule(4, RuleAction::Block, RuleKind::Regex, "(unclosed"))
+            .is_err());
+        assert!(RuleSet::new()
+            .with_rule(rule(5, RuleAction::Block, RuleKind::Exact, "not a domain"))
+            .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::error::RuleError;
+
+/*
+    Source of the current local time, replaceable for tests
+*/
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    fn now(&self) -> NaiveDateTime;
+}
+
+#[derive(Debug, Default)]
+pub struct LocalClock;
+
+impl Clock for LocalClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Local::now().naive_local()
+    }
+}
+
+/*
+    Recurring weekly time window. A window ending before it starts runs past
+    midnight and belongs to the day it starts on, so "sun-thu 21:00-07:00"
+    covers sunday night until monday morning, but not friday morning.
+    Windows starting and ending at the same time last all day.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub name: String,
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    pub fn new(name: &str, days: Vec<Weekday>, start: NaiveTime, end: NaiveTime) -> Self {
+        Schedule {
+            name: name.to_string(),
+            days,
+            start,
+            end,
+        }
+    }
+
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        let day = now.weekday();
+        let time = now.time();
+
+        if self.start == self.end {
+            self.days.contains(&day)
+        } else if self.start < self.end {
+            self.days.contains(&day) && self.start <= time && time < self.end
+        } else {
+            (self.days.contains(&day) && time >= self.start)
+                || (self.days.contains(&day.pred()) && time < self.end)
+        }
+    }
+
+    /*
+        Parses comma separated weekdays, e.g. "sun,mon,tue"
+    */
+    pub fn parse_days(days: &str) -> Result<Vec<Weekday>, RuleError> {
+        days.split(',')
+            .map(str::trim)
+            .filter(|day| !day.is_empty())
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| RuleError(format!("invalid weekday {}", day)))
+            })
+            .collect()
+    }
+
+    pub fn days_to_string(days: &[Weekday]) -> String {
+        days.iter()
+            .map(|day| day.to_string().to_lowercase())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_schedule_window() {
+        let school_nights = Schedule::new(
+            "school nights",
+            Schedule::parse_days("sun, mon,tue,wed,thu").unwrap(),
+            NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(7, 0, 0).unwrap(),
+        );
+        // 2026-10-18 is a sunday
+        let at = |day: u32, hour: u32| {
+            NaiveDate::from_ymd_opt(2026, 10, day)
+                .unwrap()
+                .and_hms_opt(hour, 30, 0)
+                .unwrap()
+        };
+
+        assert!(school_nights.is_active_at(at(18, 22)));
+        assert!(school_nights.is_active_at(at(19, 6)));
+        assert!(!school_nights.is_active_at(at(19, 7)));
+        assert!(!school_nights.is_active_at(at(19, 20)));
+        // thursday night until friday morning, but not friday night
+        assert!(school_nights.is_active_at(at(23, 3)));
+        assert!(!school_nights.is_active_at(at(23, 22)));
+        assert!(!school_nights.is_active_at(at(18, 3)));
+
+        let weekend = Schedule::new(
+            "weekend",
+            vec![Weekday::Sat, Weekday::Sun],
+            NaiveTime::MIN,
+            NaiveTime::MIN,
+        );
+        assert!(weekend.is_active_at(at(18, 12)));
+        assert!(!weekend.is_active_at(at(19, 12)));
+
+        assert_eq!(Schedule::days_to_string(&weekend.days), "sat,sun");
+        assert!(Schedule::parse_days("mon,someday").is_err());
+    }
+}
@@ -0,0 +1,16 @@
+#![allow(clippy::module_inception)]
+
+pub mod api;
+pub mod auth;
+pub mod config;
+pub mod dashboard;
+pub mod database;
+pub mod error;
+pub mod filter;
+pub mod metrics;
+pub mod nameserver;
+pub mod protocol;
+pub mod querylog;
+pub mod resolver;
+pub mod server;
+pub mod stats;
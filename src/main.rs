@@ -1,36 +1,84 @@
-use database::RecordEntity;
-use nameserver::Nameserver;
-use protocol::{packet::RecordType, util};
+use std::{path::PathBuf, process::ExitCode, sync::Arc};
 
-mod nameserver;
-mod protocol;
-mod resolver;
-mod server;
-mod database;
+use clap::Parser;
+use tinydns::{
+    api, auth,
+    config::{Config, DEFAULT_CONFIG_PATH},
+    database,
+    error::AuthError,
+    metrics, server,
+};
+
+/*
+    Password of the user admin, created if the database has no users yet. It's read from
+    the environment so that it never shows up in logs or the process list.
+*/
+const ADMIN_PASSWORD_VAR: &str = "TINYDNS_ADMIN_PASSWORD";
+
+#[derive(Parser)]
+#[command(name = "tinydns", version, about = "Hybrid DNS server with domain blocking")]
+struct Cli {
+    /// TOML configuration file
+    #[arg(short, long, default_value = DEFAULT_CONFIG_PATH)]
+    config: PathBuf,
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load(&cli.config)?;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // set up logging
-    log4rs::init_file("config/log4rs.yml", Default::default())?;
-    let db = database::Database::init("sqlite.db").await?;
+    config.init_logging()?;
+    let db = Arc::new(database::Database::init(config.database_path()).await?);
 
-    // create local nameserver
-    let nameserver = Nameserver::new(&db);
+    let admin_enabled = config.admin_enabled();
+    // listeners, resolver, nameserver, blocklists, query log and metrics as configured
+    let config = Arc::new(config.server_config(db.clone())?);
 
-    // create resolver
-    let dns_resolver =
-        resolver::Resolver::default();//.with_fallback_server(("8.8.8.8".to_string(), 53));
+    // load allow and block rules, upstream servers and client groups
+    config.reload(&db).await?;
 
-    let config = server::ServerConfig::default()
-        .with_resolver(dns_resolver)
-        .with_nameserver(nameserver);
+    if admin_enabled {
+        let password = std::env::var(ADMIN_PASSWORD_VAR).ok();
+        match auth::ensure_admin(&db, password.as_deref()).await {
+            Ok(true) => log::warn!("Created the user admin with the password from {}", ADMIN_PASSWORD_VAR),
+            Ok(false) => {}
+            Err(AuthError::InvalidAccount(reason)) => {
+                return Err(format!("{}, set it in {}", reason, ADMIN_PASSWORD_VAR).into());
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
 
-    log::info!("Starting to serve UDP");
-    if let Err(err) = server::serve::serve_udp(&config).await {
+    log::info!("Starting to serve UDP and TCP");
+    if let Err(err) = tokio::try_join!(
+        server::serve::serve_udp(config.clone()),
+        server::serve::serve_tcp(config.clone()),
+        metrics::serve_metrics(config.clone()),
+        async {
+            if admin_enabled {
+                api::serve_api(config.clone(), db.clone()).await
+            } else {
+                Ok(())
+            }
+        },
+        config.watch(&db)
+    ) {
         log::error!("Server failed due to an unhandled exception: {}", err);
-    } else {
-        log::info!("Server exited naturally")
+        return Err(err.into());
     }
 
+    log::info!("Server exited naturally");
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // logging might not be set up yet, so errors go to stderr
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
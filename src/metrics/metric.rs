@@ -0,0 +1,203 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Mutex,
+    },
+};
+
+/*
+    Writes "{name="value",...}", or nothing if there are no labels
+*/
+fn write_labels(out: &mut String, names: &[&str], values: &[String], extra: Option<(&str, &str)>) {
+    let labels: Vec<_> = names
+        .iter()
+        .copied()
+        .zip(values.iter().map(String::as_str))
+        .chain(extra)
+        .collect();
+    if labels.is_empty() {
+        return;
+    }
+
+    out.push('{');
+    for (i, (name, value)) in labels.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = write!(out, "{}=\"{}\"", name, value);
+    }
+    out.push('}');
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/*
+    Writes a gauge whose value is computed when rendering
+*/
+pub fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    write_header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn to_values(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+/*
+    Counters sharing a name, one per combination of label values
+*/
+#[derive(Debug)]
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    values: Mutex<BTreeMap<Vec<String>, u64>>,
+}
+
+impl CounterVec {
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        CounterVec {
+            name,
+            help,
+            labels,
+            values: Mutex::default(),
+        }
+    }
+
+    pub fn inc(&self, values: &[&str]) {
+        *self.values.lock().unwrap().entry(to_values(values)).or_default() += 1;
+    }
+
+    pub fn get(&self, values: &[&str]) -> u64 {
+        self.values.lock().unwrap().get(&to_values(values)).copied().unwrap_or_default()
+    }
+
+    pub fn render(&self, out: &mut String) {
+        write_header(out, self.name, self.help, "counter");
+        for (values, count) in self.values.lock().unwrap().iter() {
+            out.push_str(self.name);
+            write_labels(out, self.labels, values, None);
+            let _ = writeln!(out, " {}", count);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.value.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    pub fn render(&self, out: &mut String) {
+        write_gauge(out, self.name, self.help, self.get() as f64);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct HistogramValue {
+    // observations per bucket, not yet cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/*
+    Histograms sharing a name and buckets, one per combination of label values
+*/
+#[derive(Debug)]
+pub struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    // upper bounds, ascending
+    buckets: &'static [f64],
+    values: Mutex<BTreeMap<Vec<String>, HistogramValue>>,
+}
+
+impl HistogramVec {
+    pub fn new(
+        name: &'static str,
+        help: &'static str,
+        labels: &'static [&'static str],
+        buckets: &'static [f64],
+    ) -> Self {
+        HistogramVec {
+            name,
+            help,
+            labels,
+            buckets,
+            values: Mutex::default(),
+        }
+    }
+
+    pub fn observe(&self, values: &[&str], value: f64) {
+        let mut histograms = self.values.lock().unwrap();
+        let histogram = histograms.entry(to_values(values)).or_insert_with(|| HistogramValue {
+            counts: vec![0; self.buckets.len()],
+            sum: 0.0,
+            count: 0,
+        });
+
+        if let Some(bucket) = self.buckets.iter().position(|bound| value <= *bound) {
+            histogram.counts[bucket] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    pub fn render(&self, out: &mut String) {
+        write_header(out, self.name, self.help, "histogram");
+        for (values, histogram) in self.values.lock().unwrap().iter() {
+            let mut cumulative = 0;
+            for (bound, count) in self.buckets.iter().zip(&histogram.counts) {
+                cumulative += count;
+                let _ = write!(out, "{}_bucket", self.name);
+                write_labels(out, self.labels, values, Some(("le", &bound.to_string())));
+                let _ = writeln!(out, " {}", cumulative);
+            }
+
+            let _ = write!(out, "{}_bucket", self.name);
+            write_labels(out, self.labels, values, Some(("le", "+Inf")));
+            let _ = writeln!(out, " {}", histogram.count);
+
+            let _ = write!(out, "{}_sum", self.name);
+            write_labels(out, self.labels, values, None);
+            let _ = writeln!(out, " {}", histogram.sum);
+
+            let _ = write!(out, "{}_count", self.name);
+            write_labels(out, self.labels, values, None);
+            let _ = writeln!(out, " {}", histogram.count);
+        }
+    }
+}
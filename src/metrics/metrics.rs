@@ -0,0 +1,202 @@
+use std::time::Duration;
+
+use crate::protocol::{
+    answer::AnswerSource,
+    packet::{flags::ResponseCode, type_mnemonic, RecordType},
+};
+
+use super::metric::{write_gauge, CounterVec, Gauge, HistogramVec};
+
+/*
+    Upper bounds of the latency histograms, in seconds
+*/
+const LATENCY_BUCKETS: [f64; 13] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/*
+    Label value for a query type, e.g. "AAAA", or "TYPE65534" for unknown ones (RFC 3597, Section 5)
+*/
+fn qtype_label(qtype: u16) -> String {
+    match RecordType::from(qtype) {
+        RecordType::BROADCAST => "ANY".to_string(),
+        _ => type_mnemonic(qtype),
+    }
+}
+
+/*
+    Counters and histograms of the server, rendered in the Prometheus text exposition format
+*/
+#[derive(Debug)]
+pub struct Metrics {
+    queries: CounterVec,
+    query_duration: HistogramVec,
+    upstream_duration: HistogramVec,
+    upstream_errors: CounterVec,
+    cache_lookups: CounterVec,
+    in_flight: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            queries: CounterVec::new(
+                "tinydns_queries_total",
+                "Queries answered, by query type, response code and source of the answer.",
+                &["qtype", "rcode", "source"],
+            ),
+            query_duration: HistogramVec::new(
+                "tinydns_query_duration_seconds",
+                "Time taken to answer queries, by source of the answer.",
+                &["source"],
+                &LATENCY_BUCKETS,
+            ),
+            upstream_duration: HistogramVec::new(
+                "tinydns_upstream_duration_seconds",
+                "Time taken by fallback servers to answer, by server.",
+                &["server"],
+                &LATENCY_BUCKETS,
+            ),
+            upstream_errors: CounterVec::new(
+                "tinydns_upstream_errors_total",
+                "Queries to fallback servers that failed or timed out, by server.",
+                &["server"],
+            ),
+            cache_lookups: CounterVec::new(
+                "tinydns_cache_lookups_total",
+                "Response cache lookups, by result.",
+                &["result"],
+            ),
+            in_flight: Gauge::new(
+                "tinydns_in_flight_queries",
+                "Queries currently being answered.",
+            ),
+        }
+    }
+}
+
+/*
+    Counts a query as in flight until dropped
+*/
+pub struct InFlightGuard<'a>(&'a Gauge);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    pub fn start_query(&self) -> InFlightGuard<'_> {
+        self.in_flight.inc();
+        InFlightGuard(&self.in_flight)
+    }
+
+    /*
+        Records an answered query. A source of None means the query failed.
+    */
+    pub fn record_query(
+        &self,
+        qtype: u16,
+        rcode: ResponseCode,
+        source: Option<AnswerSource>,
+        elapsed: Duration,
+    ) {
+        let source = source.map_or("failed", |source| source.as_str());
+        self.queries.inc(&[&qtype_label(qtype), rcode.mnemonic(), source]);
+        self.query_duration.observe(&[source], elapsed.as_secs_f64());
+    }
+
+    pub fn record_upstream(&self, server: &(String, u16), elapsed: Duration) {
+        let server = format!("{}:{}", server.0, server.1);
+        self.upstream_duration.observe(&[&server], elapsed.as_secs_f64());
+    }
+
+    pub fn record_upstream_error(&self, server: &(String, u16)) {
+        self.upstream_errors.inc(&[&format!("{}:{}", server.0, server.1)]);
+    }
+
+    pub fn record_cache_lookup(&self, hit: bool) {
+        self.cache_lookups.inc(&[if hit { "hit" } else { "miss" }]);
+    }
+
+    /*
+        Share of cache lookups that were hits, from 0 to 1
+    */
+    pub fn cache_hit_ratio(&self) -> f64 {
+        let hits = self.cache_lookups.get(&["hit"]);
+        let lookups = hits + self.cache_lookups.get(&["miss"]);
+        if lookups == 0 {
+            return 0.0;
+        }
+        hits as f64 / lookups as f64
+    }
+
+    pub fn in_flight(&self) -> i64 {
+        self.in_flight.get()
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.queries.render(&mut out);
+        self.query_duration.render(&mut out);
+        self.upstream_duration.render(&mut out);
+        self.upstream_errors.render(&mut out);
+        self.cache_lookups.render(&mut out);
+        write_gauge(
+            &mut out,
+            "tinydns_cache_hit_ratio",
+            "Share of response cache lookups that were hits.",
+            self.cache_hit_ratio(),
+        );
+        self.in_flight.render(&mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        {
+            let _query = metrics.start_query();
+            assert_eq!(metrics.in_flight(), 1);
+            metrics.record_query(1, ResponseCode::NoError, Some(AnswerSource::Cache), Duration::from_micros(800));
+            metrics.record_query(65534, ResponseCode::NameError, None, Duration::from_millis(30));
+        }
+        assert_eq!(metrics.in_flight(), 0);
+
+        let upstream = ("192.0.2.1".to_string(), 53);
+        metrics.record_upstream(&upstream, Duration::from_millis(20));
+        metrics.record_upstream_error(&upstream);
+        for hit in [true, true, true, false] {
+            metrics.record_cache_lookup(hit);
+        }
+
+        let text = metrics.render();
+        for line in [
+            "# TYPE tinydns_queries_total counter",
+            r#"tinydns_queries_total{qtype="A",rcode="NOERROR",source="cache"} 1"#,
+            r#"tinydns_queries_total{qtype="TYPE65534",rcode="NXDOMAIN",source="failed"} 1"#,
+            "# TYPE tinydns_query_duration_seconds histogram",
+            r#"tinydns_query_duration_seconds_bucket{source="cache",le="0.0005"} 0"#,
+            r#"tinydns_query_duration_seconds_bucket{source="cache",le="0.001"} 1"#,
+            r#"tinydns_query_duration_seconds_bucket{source="cache",le="+Inf"} 1"#,
+            r#"tinydns_query_duration_seconds_count{source="failed"} 1"#,
+            r#"tinydns_upstream_duration_seconds_bucket{server="192.0.2.1:53",le="0.025"} 1"#,
+            r#"tinydns_upstream_errors_total{server="192.0.2.1:53"} 1"#,
+            r#"tinydns_cache_lookups_total{result="hit"} 3"#,
+            "tinydns_cache_hit_ratio 0.75",
+            "tinydns_in_flight_queries 0",
+        ] {
+            assert!(text.lines().any(|rendered| rendered == line), "missing {}", line);
+        }
+    }
+}
@@ -0,0 +1,6 @@
+mod metric;
+mod metrics;
+mod serve;
+
+pub use metrics::Metrics;
+pub use serve::serve_metrics;
@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+
+use crate::{error::DnsError, server::ServerConfig};
+
+use super::Metrics;
+
+async fn render_metrics(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        metrics.render(),
+    )
+}
+
+fn router(metrics: Arc<Metrics>) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .with_state(metrics)
+}
+
+/*
+    Serves the metrics on /metrics over HTTP. Returns right away if metrics aren't enabled.
+*/
+pub async fn serve_metrics(config: Arc<ServerConfig>) -> Result<(), DnsError> {
+    let Some(metrics) = config.metrics().cloned() else {
+        return Ok(());
+    };
+
+    let listener =
+        tokio::net::TcpListener::bind(format!("{}:{}", config.listen_addr(), config.metrics_port()))
+            .await?;
+    axum::serve(listener, router(metrics)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serve_metrics() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.record_cache_lookup(true);
+        // any free port, bound before the client connects
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let client = async {
+            let mut stream = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+            stream
+                .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .await
+                .unwrap();
+
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        tokio::select! {
+            response = client => {
+                assert!(response.starts_with("HTTP/1.1 200 OK"));
+                assert!(response.contains("tinydns_cache_hit_ratio 1"));
+            },
+            result = axum::serve(listener, router(metrics)) => panic!("metrics server exited: {:?}", result.err()),
+        }
+    }
+}
@@ -1,3 +1,3 @@
-mod nameserver;
-
-pub use nameserver::Nameserver;
+mod nameserver;
+
+pub use nameserver::{Nameserver, RRsetOrder};
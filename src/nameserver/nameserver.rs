@@ -1,60 +1,339 @@
-use crate::{database::{Database, RecordEntity, RecordQuery}, protocol::{answer::AnswerEntry, packet::Question}};
-
-pub struct Nameserver<'a> {
-    db: &'a Database
-}
-
-impl<'a> Nameserver<'a> {
-    pub fn new(db: &'a Database) -> Self {
-        Nameserver {
-            db
-        }
-    }
-
-    /*
-        Tries to answer DNS question locally
-    */
-    pub async fn try_answer(&self, question: Question) -> Option<AnswerEntry> {
-        let query = RecordQuery::default()
-            .with_domain_name(question.name())
-            .with_record_type(question.qtype());
-
-        log::trace!("trying to answer question");
-        // TODO?: Support qclasses other than IN and ANY 
-        if question.qclass() != 1 && question.qclass() != 255 {
-            log::trace!("refused to answer question, as qclass ne 1 and qclass ne 255");
-            return None;
-        }
-
-        let res  = Some(AnswerEntry{
-            authoritive: true,
-            resource: Some(
-                self.query_record(&query)
-                    .await?
-                    .serialize()
-                    .with_name(question.name())
-                ),
-            ..Default::default()
-        });
-
-        log::trace!("providing answer: {:?}", res);
-
-        return res;
-    }
-
-    /*
-        Query a record
-    */
-    pub async fn query_record(&self, record_query: &RecordQuery) -> Option<RecordEntity> {
-        Some(record_query._fetch_one(self.db.get_pool(), self.db.config_dns_tbl()).await?)
-    }
-
-    /*
-
-    */
-    pub async fn insert_record(&self, record: RecordEntity) -> Result<(), Box<dyn std::error::Error>> {
-        record._insert(self.db.get_pool(), self.db.config_dns_tbl()).await
-    }
-
-
-}
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use crate::{database::{Database, RecordEntity, RecordQuery}, error::DnsError, protocol::{answer::{self, AnswerEntry, AnswerKind}, packet::{Question, RecordType, ResourceRecord}, util}};
+
+/*
+    Order in which the records of an RRset are returned
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RRsetOrder {
+    // in the order they were inserted
+    Fixed,
+    // rotated by one record per response
+    RoundRobin,
+    // randomly shuffled per response
+    Shuffle,
+}
+
+#[derive(Clone)]
+pub struct Nameserver {
+    db: Arc<Database>,
+    rrset_order: RRsetOrder,
+    rotation: Arc<AtomicUsize>,
+}
+
+impl Nameserver {
+    pub fn new(db: Arc<Database>) -> Self {
+        Nameserver {
+            db,
+            rrset_order: RRsetOrder::Fixed,
+            rotation: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn with_rrset_order(mut self, order: RRsetOrder) -> Self {
+        self.rrset_order = order;
+        self
+    }
+
+    fn order_rrset(&self, records: &mut [ResourceRecord]) {
+        if records.len() < 2 {
+            return;
+        }
+
+        match self.rrset_order {
+            RRsetOrder::Fixed => {}
+            RRsetOrder::RoundRobin => {
+                let rotation = self.rotation.fetch_add(1, Ordering::Relaxed);
+                records.rotate_left(rotation % records.len());
+            }
+            RRsetOrder::Shuffle => records.shuffle(&mut rand::thread_rng()),
+        }
+    }
+
+    /*
+        Tries to answer DNS question locally
+    */
+    pub async fn try_answer(&self, question: Question) -> Result<Option<AnswerEntry>, DnsError> {
+        let mut query = RecordQuery::default().with_domain_name(question.name());
+        // ANY matches records of every type
+        if question.qtype() != RecordType::BROADCAST {
+            query = query.with_record_type(question.qtype());
+        }
+
+        log::trace!("trying to answer question");
+        // TODO?: Support qclasses other than IN and ANY 
+        if question.qclass() != 1 && question.qclass() != 255 {
+            return Err(DnsError::Refused(format!("qclass {} is not served", question.qclass())));
+        }
+
+        let mut records = self.query_records(&query).await?;
+        if records.is_empty() && question.qtype() != RecordType::CNAME {
+            // an alias answers questions of any type for its name
+            let query = RecordQuery::default()
+                .with_domain_name(question.name())
+                .with_record_type(RecordType::CNAME);
+            records = self.query_records(&query).await?;
+        }
+
+        if records.is_empty() {
+            return self.try_answer_negative(&question).await;
+        }
+
+        let mut resources = records
+            .into_iter()
+            .map(|record| Ok(record.serialize()?.with_name(question.name())))
+            .collect::<Result<Vec<_>, DnsError>>()?;
+        self.order_rrset(&mut resources);
+
+        let res  = Some(AnswerEntry{
+            authoritive: true,
+            resources,
+            ..Default::default()
+        });
+
+        log::trace!("providing answer: {:?}", res);
+
+        Ok(res)
+    }
+
+    /*
+        Tells apart names that exist without records of the requested type (NODATA)
+        from names that don't exist within one of our zones (NXDOMAIN). A name without records
+        of its own still exists if there are names below it (RFC 8020). Either answer carries
+        the zone's SOA, with the negative TTL, in the authority section (RFC 2308, Section 2).
+        Names we know nothing about are left to the resolver.
+    */
+    async fn try_answer_negative(&self, question: &Question) -> Result<Option<AnswerEntry>, DnsError> {
+        let name_exists = self
+            .query_record(&RecordQuery::default().with_domain_name(question.name()).with_descendants())
+            .await?
+            .is_some();
+        let soa = self.find_soa(question.name()).await?;
+
+        if !name_exists && soa.is_none() {
+            return Ok(None);
+        }
+
+        let res = Some(AnswerEntry {
+            kind: if name_exists { AnswerKind::NoData } else { AnswerKind::NxDomain },
+            authoritive: true,
+            authority: soa,
+            ..Default::default()
+        });
+
+        log::trace!("providing negative answer: {:?}", res);
+
+        Ok(res)
+    }
+
+    /*
+        Finds the SOA record of the closest zone enclosing name
+    */
+    async fn find_soa(&self, name: String) -> Result<Option<ResourceRecord>, DnsError> {
+        let mut zone = name;
+        while !zone.is_empty() && zone != "." {
+            let query = RecordQuery::default()
+                .with_domain_name(zone.clone())
+                .with_record_type(RecordType::SOA);
+
+            if let Some(record) = self.query_record(&query).await? {
+                let soa = record.serialize()?.with_name(zone);
+                let ttl = answer::negative_ttl(&soa).unwrap_or(soa.ttl());
+                return Ok(Some(soa.with_ttl(ttl)));
+            }
+
+            zone = util::get_upzone(zone);
+        }
+
+        Ok(None)
+    }
+
+    /*
+        Query a record
+    */
+    pub async fn query_record(&self, record_query: &RecordQuery) -> Result<Option<RecordEntity>, DnsError> {
+        record_query._fetch_one(self.db.get_pool(), self.db.config_dns_tbl()).await
+    }
+
+    /*
+        Query all records matching the query
+    */
+    pub async fn query_records(&self, record_query: &RecordQuery) -> Result<Vec<RecordEntity>, DnsError> {
+        record_query._fetch_all(self.db.get_pool(), self.db.config_dns_tbl()).await
+    }
+
+    /*
+        Insert a record
+    */
+    pub async fn insert_record(&self, record: RecordEntity) -> Result<u64, DnsError> {
+        record._insert(self.db.get_pool(), self.db.config_dns_tbl()).await
+    }
+
+    /*
+        Overwrite the record with the same id, returns false if it doesn't exist
+    */
+    pub async fn update_record(&self, record: RecordEntity) -> Result<bool, DnsError> {
+        record._update(self.db.get_pool(), self.db.config_dns_tbl()).await
+    }
+
+    /*
+        Delete a record by id, returns false if it doesn't exist
+    */
+    pub async fn delete_record(&self, id: u64) -> Result<bool, DnsError> {
+        RecordEntity::default()
+            .with_id(id)
+            ._delete(self.db.get_pool(), self.db.config_dns_tbl())
+            .await
+    }
+
+    /*
+        (De)activate a record by id. Inactive records are kept, but never served.
+    */
+    pub async fn set_active(&self, id: u64, is_active: bool) -> Result<bool, DnsError> {
+        RecordEntity::default()
+            .with_id(id)
+            ._set_active(self.db.get_pool(), self.db.config_dns_tbl(), is_active)
+            .await
+    }
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::protocol::packet::{flags::ResponseCode, RData, RecordType};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_answer_rrset() {
+        let db = Arc::new(Database::init_mem().await.unwrap());
+        let nameserver = Nameserver::new(db).with_rrset_order(RRsetOrder::RoundRobin);
+
+        for octet in 1..=3 {
+            let record = RecordEntity::default()
+                .with_domain_name("dns.is.tiny".to_string())
+                .with_rdata(RData::A(Ipv4Addr::new(10, 0, 0, octet)))
+                .unwrap();
+            nameserver.insert_record(record).await.unwrap();
+        }
+
+        let question = Question::default()
+            .with_name("dns.is.tiny".to_string())
+            .with_qtype(RecordType::A.into());
+
+        let first_octets = |answer: AnswerEntry| {
+            answer
+                .resources
+                .iter()
+                .map(|record| match record.rdata() {
+                    RData::A(addr) => addr.octets()[3],
+                    rdata => panic!("unexpected rdata {}", rdata),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // the whole RRset is returned, rotated by one record per response
+        let answer = nameserver.try_answer(question.clone()).await.unwrap().unwrap();
+        assert_eq!(first_octets(answer), vec![1, 2, 3]);
+        let answer = nameserver.try_answer(question.clone()).await.unwrap().unwrap();
+        assert_eq!(first_octets(answer), vec![2, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_answer_negative() {
+        let db = Arc::new(Database::init_mem().await.unwrap());
+        let nameserver = Nameserver::new(db);
+
+        let soa = RecordEntity::default()
+            .with_domain_name("is.tiny".to_string())
+            .with_rdata(RData::SOA {
+                mname: "ns.is.tiny".to_string(),
+                rname: "admin.is.tiny".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 300,
+            })
+            .unwrap();
+        nameserver.insert_record(soa).await.unwrap();
+
+        let record = RecordEntity::default()
+            .with_domain_name("dns.is.tiny".to_string())
+            .with_rdata(RData::A(Ipv4Addr::new(10, 0, 0, 1)))
+            .unwrap();
+        nameserver.insert_record(record).await.unwrap();
+
+        let question = |name: &str, rtype: RecordType| {
+            Question::default()
+                .with_name(name.to_string())
+                .with_qtype(rtype.into())
+        };
+
+        let answer = nameserver
+            .try_answer(question("dns.is.tiny", RecordType::AAAA))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.kind, AnswerKind::NoData);
+        assert!(answer.resources.is_empty());
+        let authority = answer.authority.unwrap();
+        assert_eq!(authority.name(), "is.tiny");
+        assert_eq!(authority.ttl(), 300);
+
+        let answer = nameserver
+            .try_answer(question("missing.is.tiny", RecordType::A))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.kind, AnswerKind::NxDomain);
+        assert!(answer.authority.is_some());
+
+        // names with only names below them are empty non-terminals, not NXDOMAIN
+        let record = RecordEntity::default()
+            .with_domain_name("host.sub.is.tiny".to_string())
+            .with_rdata(RData::A(Ipv4Addr::new(10, 0, 0, 2)))
+            .unwrap();
+        nameserver.insert_record(record).await.unwrap();
+
+        let answer = nameserver
+            .try_answer(question("sub.is.tiny", RecordType::A))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.kind, AnswerKind::NoData);
+        assert!(answer.resources.is_empty());
+        assert!(answer.authority.is_some());
+
+        // a shared suffix without the dot doesn't make a name exist
+        let answer = nameserver
+            .try_answer(question("ub.is.tiny", RecordType::A))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(answer.kind, AnswerKind::NxDomain);
+
+        // names outside of our zones are left to the resolver
+        assert!(nameserver
+            .try_answer(question("dns.is.huge", RecordType::A))
+            .await
+            .unwrap()
+            .is_none());
+
+        // only the IN class (and ANY) is served
+        let chaos = question("dns.is.tiny", RecordType::A).with_qclass(3);
+        let err = nameserver.try_answer(chaos).await.unwrap_err();
+        assert_eq!(err.response_code(), ResponseCode::Refused);
+    }
+}
@@ -1,9 +1,63 @@
-use super::packet::ResourceRecord;
-
-#[derive(Default, Debug)]
-pub struct AnswerEntry {
-    pub resource: Option<ResourceRecord>,
-    pub authoritive: bool,
-    pub authority: Option<ResourceRecord>,
-    pub additional: Vec<ResourceRecord>,
-}
+use super::packet::{RData, ResourceRecord};
+
+/*
+    Whether an answer carries records, or states that there are none (RFC 2308)
+*/
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum AnswerKind {
+    #[default]
+    Positive,
+    // the name exists, but has no records of the requested type
+    NoData,
+    // the name doesn't exist at all
+    NxDomain,
+}
+
+/*
+    Where an answer came from, ordered by how far we had to go for it
+*/
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnswerSource {
+    // our own nameserver
+    #[default]
+    Local,
+    Cache,
+    Upstream,
+    // the filter answered in place of anyone else
+    Blocked,
+}
+
+impl AnswerSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnswerSource::Local => "local",
+            AnswerSource::Cache => "cache",
+            AnswerSource::Upstream => "upstream",
+            AnswerSource::Blocked => "blocked",
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AnswerEntry {
+    // all records of the answered RRset
+    pub resources: Vec<ResourceRecord>,
+    pub kind: AnswerKind,
+    pub authoritive: bool,
+    pub authority: Option<ResourceRecord>,
+    pub additional: Vec<ResourceRecord>,
+    pub source: AnswerSource,
+    // the upstream response was truncated, the client has to ask again over TCP
+    pub truncated: bool,
+}
+
+/*
+    How long a negative answer may be cached: the smaller of the SOA's
+    own TTL and its MINIMUM field (RFC 2308, Section 5)
+*/
+pub fn negative_ttl(soa: &ResourceRecord) -> Option<u32> {
+    match soa.rdata() {
+        RData::SOA { minimum, .. } => Some(soa.ttl().min(minimum)),
+        _ => None,
+    }
+}
@@ -1,3 +1,3 @@
-pub mod answer;
-pub mod packet;
-pub mod util;
+pub mod answer;
+pub mod packet;
+pub mod util;
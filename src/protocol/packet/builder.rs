@@ -1,81 +1,103 @@
-use log::log;
-
-use super::{
-    flags::HeaderFlags, header::PacketHeader, packet::Packet, question::Question,
-    resource_record::ResourceRecord,
-};
-
-pub struct PacketBuilder {
-    header: PacketHeader,
-    questions: Vec<Question>,
-    answers: Vec<ResourceRecord>,
-    authorities: Vec<ResourceRecord>,
-    additionals: Vec<ResourceRecord>,
-}
-
-impl PacketBuilder {
-    pub fn new() -> Self {
-        PacketBuilder {
-            header: PacketHeader::default(),
-            questions: Vec::new(),
-            answers: Vec::new(),
-            authorities: Vec::new(),
-            additionals: Vec::new(),
-        }
-    }
-
-    pub fn from_packet(packet: Packet) -> Self {
-        PacketBuilder {
-            header: packet.header,
-            questions: packet.questions,
-            answers: packet.answers,
-            authorities: packet.authorities,
-            additionals: packet.additionals,
-        }
-    }
-
-    pub fn with_id(mut self, id: u16) -> Self {
-        self.header.id = id;
-        self
-    }
-
-    pub fn with_flags(mut self, flags: HeaderFlags) -> Self {
-        self.header.flags = flags.serialize();
-        self
-    }
-
-    pub fn with_qentries(mut self, qentries: Vec<Question>) -> Self {
-        self.questions.extend(qentries);
-        self
-    }
-
-    pub fn with_aentries(mut self, aentries: Vec<ResourceRecord>) -> Self {
-        self.answers.extend(aentries);
-        self
-    }
-
-    pub fn with_authentries(mut self, authentries: Vec<ResourceRecord>) -> Self {
-        self.answers.extend(authentries);
-        self
-    }
-
-    pub fn with_addentries(mut self, addentries: Vec<ResourceRecord>) -> Self {
-        self.answers.extend(addentries);
-        self
-    }
-
-    pub fn build(mut self) -> Packet {
-        self.header.qdcount = self.questions.len() as u16;
-        self.header.ancount = self.answers.len() as u16;
-        self.header.nscount = self.authorities.len() as u16;
-        self.header.arcount = self.additionals.len() as u16;
-        
-        Packet {
-            header: self.header,
-            questions: self.questions,
-            answers: self.answers,
-            authorities: self.authorities,
-            additionals: self.additionals,
-        }
-    }
-}
+use super::{
+    edns::Edns, flags::HeaderFlags, header::PacketHeader, packet::Packet, question::Question,
+    resource_record::ResourceRecord,
+};
+
+#[derive(Default)]
+pub struct PacketBuilder {
+    header: PacketHeader,
+    questions: Vec<Question>,
+    answers: Vec<ResourceRecord>,
+    authorities: Vec<ResourceRecord>,
+    additionals: Vec<ResourceRecord>,
+    edns: Option<Edns>,
+    extended_rcode: u8,
+}
+
+impl PacketBuilder {
+    pub fn new() -> Self {
+        PacketBuilder {
+            header: PacketHeader::default(),
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authorities: Vec::new(),
+            additionals: Vec::new(),
+            edns: None,
+            extended_rcode: 0,
+        }
+    }
+
+    pub fn from_packet(packet: Packet) -> Self {
+        PacketBuilder {
+            header: packet.header,
+            questions: packet.questions,
+            answers: packet.answers,
+            authorities: packet.authorities,
+            additionals: packet.additionals,
+            extended_rcode: packet.edns.as_ref().map_or(0, |edns| edns.extended_rcode),
+            edns: packet.edns,
+        }
+    }
+
+    pub fn with_id(mut self, id: u16) -> Self {
+        self.header.id = id;
+        self
+    }
+
+    pub fn with_flags(mut self, flags: HeaderFlags) -> Self {
+        self.header.flags = flags.serialize();
+        // upper bits of extended response codes go into the OPT record
+        self.extended_rcode = (flags.2 as u16 >> 4) as u8;
+        self
+    }
+
+    pub fn with_qentries(mut self, qentries: Vec<Question>) -> Self {
+        self.questions.extend(qentries);
+        self
+    }
+
+    pub fn with_aentries(mut self, aentries: Vec<ResourceRecord>) -> Self {
+        self.answers.extend(aentries);
+        self
+    }
+
+    pub fn with_authentries(mut self, authentries: Vec<ResourceRecord>) -> Self {
+        self.authorities.extend(authentries);
+        self
+    }
+
+    pub fn with_addentries(mut self, addentries: Vec<ResourceRecord>) -> Self {
+        self.additionals.extend(addentries);
+        self
+    }
+
+    pub fn with_edns(mut self, edns: Edns) -> Self {
+        self.edns = Some(edns);
+        self
+    }
+
+    pub fn without_edns(mut self) -> Self {
+        self.edns = None;
+        self
+    }
+
+    pub fn build(mut self) -> Packet {
+        if let Some(edns) = self.edns.as_mut() {
+            edns.extended_rcode = self.extended_rcode;
+        }
+
+        self.header.qdcount = self.questions.len() as u16;
+        self.header.ancount = self.answers.len() as u16;
+        self.header.nscount = self.authorities.len() as u16;
+        self.header.arcount = self.additionals.len() as u16 + self.edns.is_some() as u16;
+        
+        Packet {
+            header: self.header,
+            questions: self.questions,
+            answers: self.answers,
+            authorities: self.authorities,
+            additionals: self.additionals,
+            edns: self.edns,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::DnsError,
+    protocol::util::{MAX_LABEL_LENGTH, MAX_NAME_LENGTH},
+};
+
+/*
+    Highest message offset a compression pointer can refer to (14 bits)
+*/
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+/*
+    Keeps track of the names already written to a message, so that repeated
+    suffixes can be replaced by pointers (RFC 1035, Section 4.1.4).
+*/
+#[derive(Default)]
+pub struct NameCompressor {
+    offsets: HashMap<String, u16>,
+}
+
+impl NameCompressor {
+    pub fn new() -> Self {
+        NameCompressor::default()
+    }
+
+    /*
+        Appends name to buf, replacing the longest suffix that is already part of
+        the message by a pointer. buf must contain the message from its first byte on,
+        as pointers are offsets relative to the start of the message.
+    */
+    pub fn encode_name(
+        &mut self,
+        buf: &mut Vec<u8>,
+        name: &str,
+    ) -> Result<(), DnsError> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        let name = name.strip_suffix('.').unwrap_or(name);
+
+        if name.is_empty() {
+            buf.push(0);
+            return Ok(());
+        }
+
+        if name.len() + 2 > MAX_NAME_LENGTH {
+            return Err(DnsError::encode(format!("Domain name exceeds {} bytes", MAX_NAME_LENGTH)));
+        }
+
+        let labels: Vec<&str> = name.split('.').collect();
+        for (idx, label) in labels.iter().enumerate() {
+            if label.is_empty() {
+                return Err(DnsError::encode(format!("Invalid domain name {}", name)));
+            }
+            if label.len() > MAX_LABEL_LENGTH {
+                return Err(DnsError::encode(format!("Label exceeds {} bytes", MAX_LABEL_LENGTH)));
+            }
+
+            let suffix = labels[idx..].join(".");
+            if let Some(offset) = self.offsets.get(&suffix) {
+                buf.extend((0xC000 | offset).to_be_bytes());
+                return Ok(());
+            }
+
+            if buf.len() <= MAX_POINTER_OFFSET {
+                self.offsets.insert(suffix, buf.len() as u16);
+            }
+
+            buf.push(label.len() as u8);
+            buf.extend(label.as_bytes());
+        }
+
+        buf.push(0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_name() {
+        let mut compressor = NameCompressor::new();
+        // pretend there's a header in front of the names
+        let mut buf = vec![0; 12];
+
+        compressor.encode_name(&mut buf, "example.com").unwrap();
+        assert_eq!(
+            buf[12..],
+            [7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
+        );
+
+        // full match is replaced by a single pointer
+        let len = buf.len();
+        compressor.encode_name(&mut buf, "example.com.").unwrap();
+        assert_eq!(buf[len..], [0xC0, 12]);
+
+        // new label followed by a pointer to the known suffix
+        let len = buf.len();
+        compressor.encode_name(&mut buf, "www.example.com").unwrap();
+        assert_eq!(buf[len..], [3, 119, 119, 119, 0xC0, 12]);
+
+        // the new name is known as well now
+        let len = buf.len();
+        compressor.encode_name(&mut buf, "www.example.com").unwrap();
+        assert_eq!(buf[len..], [0xC0, len as u8 - 6]);
+
+        let len = buf.len();
+        compressor.encode_name(&mut buf, ".").unwrap();
+        assert_eq!(buf[len..], [0]);
+
+        assert!(compressor.encode_name(&mut buf, "example..com").is_err());
+    }
+}
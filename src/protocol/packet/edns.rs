@@ -0,0 +1,202 @@
+use crate::{
+    error::{DnsError, ParseErrorKind},
+    protocol::util,
+};
+
+use super::{RData, RecordType, ResourceRecord};
+
+/*
+    Payload size we advertise by default, small enough to avoid IP fragmentation
+    on virtually every path (DNS Flag Day 2020)
+*/
+pub const EDNS_DEFAULT_PAYLOAD_SIZE: u16 = 1232;
+
+const DNSSEC_OK: u32 = 1 << 15;
+
+/*
+    Option code of Extended DNS Errors (RFC 8914)
+*/
+pub const EDE_OPTION_CODE: u16 = 15;
+
+/*
+    Extended DNS Error info codes for answers we deliberately changed (RFC 8914, Section 4)
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u16)]
+pub enum ExtendedError {
+    // blocked due to a policy of the server's operator
+    Blocked = 15,
+    Censored = 16,
+    // blocked as requested by the client (or its admin)
+    Filtered = 17,
+    Prohibited = 18,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+/*
+    Contents of the OPT pseudo-record (RFC 6891, Section 6.1)
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    // upper 8 bits of the 12 bit response code
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+    pub options: Vec<EdnsOption>,
+}
+
+impl Default for Edns {
+    fn default() -> Self {
+        Edns {
+            udp_payload_size: EDNS_DEFAULT_PAYLOAD_SIZE,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: Vec::new(),
+        }
+    }
+}
+
+impl Edns {
+    pub fn new(udp_payload_size: u16) -> Self {
+        Edns {
+            udp_payload_size,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_dnssec_ok(mut self, dnssec_ok: bool) -> Self {
+        self.dnssec_ok = dnssec_ok;
+        self
+    }
+
+    pub fn with_option(mut self, code: u16, data: Vec<u8>) -> Self {
+        self.options.push(EdnsOption { code, data });
+        self
+    }
+
+    /*
+        Attaches an Extended DNS Error along with a human readable explanation
+    */
+    pub fn with_extended_error(self, error: ExtendedError, text: &str) -> Self {
+        let mut data = (error as u16).to_be_bytes().to_vec();
+        data.extend(text.as_bytes());
+        self.with_option(EDE_OPTION_CODE, data)
+    }
+
+    /*
+        Info code and extra text of the first Extended DNS Error, if any
+    */
+    pub fn extended_error(&self) -> Option<(u16, String)> {
+        let option = self
+            .options
+            .iter()
+            .find(|option| option.code == EDE_OPTION_CODE && option.data.len() >= 2)?;
+        Some((
+            u16::from_be_bytes([option.data[0], option.data[1]]),
+            String::from_utf8_lossy(&option.data[2..]).to_string(),
+        ))
+    }
+
+    /*
+        Largest response the sender of this OPT record accepts over UDP.
+        Values below 512 are treated as 512 (RFC 6891, Section 6.2.3)
+    */
+    pub fn max_udp_size(&self) -> usize {
+        (self.udp_payload_size as usize).max(super::UDP_MAX_SIZE)
+    }
+
+    pub fn is_opt(record: &ResourceRecord) -> bool {
+        u16::from(record.rtype()) == u16::from(RecordType::OPT)
+    }
+
+    pub fn from_record(record: &ResourceRecord, offset: usize) -> Result<Edns, DnsError> {
+        // OPT records are always owned by the root domain
+        if !record.name().is_empty() {
+            return Err(DnsError::parse(offset, ParseErrorKind::InvalidOpt));
+        }
+
+        let data = record.rdata().to_wire()?;
+        let mut options = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let code = util::read_u16(&data, &mut position)
+                .map_err(|_| DnsError::parse(offset, ParseErrorKind::InvalidOpt))?;
+            let len = util::read_u16(&data, &mut position)
+                .map_err(|_| DnsError::parse(offset, ParseErrorKind::InvalidOpt))?;
+            let option = util::read_bytes(&data, &mut position, len as usize)
+                .map_err(|_| DnsError::parse(offset, ParseErrorKind::InvalidOpt))?;
+            options.push(EdnsOption {
+                code,
+                data: option.to_vec(),
+            });
+        }
+
+        let ttl = record.ttl();
+        Ok(Edns {
+            udp_payload_size: record.rclass(),
+            extended_rcode: (ttl >> 24) as u8,
+            version: (ttl >> 16) as u8,
+            dnssec_ok: ttl & DNSSEC_OK != 0,
+            options,
+        })
+    }
+
+    pub fn to_record(&self) -> ResourceRecord {
+        let mut data = Vec::new();
+        for option in &self.options {
+            data.extend(option.code.to_be_bytes());
+            data.extend((option.data.len() as u16).to_be_bytes());
+            data.extend(&option.data);
+        }
+
+        let mut ttl = ((self.extended_rcode as u32) << 24) | ((self.version as u32) << 16);
+        if self.dnssec_ok {
+            ttl |= DNSSEC_OK;
+        }
+
+        ResourceRecord::default()
+            .with_rdata(RData::Unknown {
+                rtype: RecordType::OPT.into(),
+                data,
+            })
+            .with_rclass(self.udp_payload_size)
+            .with_ttl(ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edns_record_roundtrip() {
+        let edns = Edns::new(4096)
+            .with_dnssec_ok(true)
+            .with_option(10, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let record = edns.to_record();
+        assert!(Edns::is_opt(&record));
+        assert_eq!(record.rclass(), 4096);
+        assert_eq!(record.ttl(), DNSSEC_OK);
+        assert_eq!(Edns::from_record(&record, 0).unwrap(), edns);
+
+        let record = edns.to_record().with_name("example.com".to_string());
+        assert!(Edns::from_record(&record, 0).is_err());
+
+        // option length pointing past the end of the RDATA
+        let record = ResourceRecord::default().with_rdata(RData::Unknown {
+            rtype: RecordType::OPT.into(),
+            data: vec![0, 10, 0, 8, 1],
+        });
+        assert!(Edns::from_record(&record, 0).is_err());
+
+        assert_eq!(Edns::new(100).max_udp_size(), 512);
+    }
+}
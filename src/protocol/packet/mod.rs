@@ -1,15 +1,20 @@
-mod builder;
-pub mod flags;
-mod header;
-mod packet;
-mod question;
-mod record_type;
-mod resource_record;
-
-pub use builder::PacketBuilder;
-pub use packet::Packet;
-pub use question::Question;
-pub use record_type::RecordType;
-pub use resource_record::ResourceRecord;
-
-pub use header::PacketHeader;
+mod builder;
+mod compression;
+mod edns;
+pub mod flags;
+mod header;
+mod packet;
+mod question;
+mod rdata;
+mod record_type;
+mod resource_record;
+
+pub use builder::PacketBuilder;
+pub use edns::{Edns, ExtendedError, EDNS_DEFAULT_PAYLOAD_SIZE};
+pub use packet::{Packet, UDP_MAX_SIZE};
+pub use question::Question;
+pub use rdata::{parse_type_mnemonic, type_mnemonic, RData};
+pub use record_type::RecordType;
+pub use resource_record::ResourceRecord;
+
+pub use header::PacketHeader;
@@ -1,116 +1,316 @@
-use super::{header::PacketHeader, question::Question, resource_record::ResourceRecord};
-
-#[derive(Debug, Clone, PartialEq)]
-pub struct Packet {
-    pub header: PacketHeader,
-    pub questions: Vec<Question>,
-    pub answers: Vec<ResourceRecord>,
-    pub authorities: Vec<ResourceRecord>,
-    pub additionals: Vec<ResourceRecord>,
-}
-
-impl Packet {
-    pub fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut buffer = Vec::new();
-        buffer.extend(self.header.serialize()?);
-        for question in &self.questions {
-            buffer.extend(question.serialize()?);
-        }
-        for answer in &self.answers {
-            buffer.extend(answer.serialize()?);
-        }
-        for authority in &self.authorities {
-            buffer.extend(authority.serialize()?);
-        }
-        for additional in &self.additionals {
-            buffer.extend(additional.serialize()?);
-        }
-        Ok(buffer)
-    }
-
-    pub fn deserialize(buffer: &[u8]) -> Result<Packet, Box<dyn std::error::Error>> {
-        let mut offset = 0;
-        let header = PacketHeader::deserialize(&buffer[offset..])?;
-        offset += std::mem::size_of::<PacketHeader>();
-
-        let mut questions = Vec::new();
-        for _ in 0..header.qdcount {
-            let question = Question::deserialize(&buffer, &mut offset)?;
-            questions.push(question);
-        }
-
-        let mut answers = Vec::new();
-        for _ in 0..header.ancount {
-            let answer = ResourceRecord::deserialize(&buffer, &mut offset)?;
-            answers.push(answer);
-        }
-
-        let mut authorities = Vec::new();
-        for _ in 0..header.nscount {
-            let authority = ResourceRecord::deserialize(&buffer, &mut offset)?;
-            authorities.push(authority);
-        }
-
-        let mut additionals = Vec::new();
-        for _ in 0..header.arcount {
-            let additional = ResourceRecord::deserialize(&buffer, &mut offset)?;
-            additionals.push(additional);
-        }
-
-        Ok(Packet {
-            header,
-            questions,
-            answers,
-            authorities,
-            additionals,
-        })
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::protocol::packet::{record_type::RecordType, resource_record};
-
-    use super::*;
-
-    #[test]
-    fn test_packet_serialize_deserialize() {
-        let header = PacketHeader {
-            id: 0x1234,
-            flags: 0x0100,
-            qdcount: 1,
-            ancount: 1,
-            nscount: 1,
-            arcount: 1,
-        };
-
-        let question = Question::default()
-            .with_name("example.com".to_string())
-            .with_qtype(RecordType::A as u16)
-            .with_qclass(1);
-
-        let resource_record = resource_record::ResourceRecord::default()
-            .with_name("example.com".to_string())
-            .with_rtype(RecordType::A)
-            .with_rclass(1)
-            .with_ttl(3600)
-            .with_rdata(vec![192, 0, 2, 1]);
-
-        let packet = Packet {
-            header,
-            questions: vec![question],
-            answers: vec![resource_record.clone()],
-            authorities: vec![resource_record.clone()],
-            additionals: vec![resource_record.clone()],
-        };
-
-        let serialized = packet.serialize().expect("Failed to serialize packet");
-        let deserialized = Packet::deserialize(&serialized).expect("Failed to deserialize packet");
-
-        assert_eq!(packet.header, deserialized.header);
-        assert_eq!(packet.questions, deserialized.questions);
-        assert_eq!(packet.answers, deserialized.answers);
-        assert_eq!(packet.authorities, deserialized.authorities);
-        assert_eq!(packet.additionals, deserialized.additionals);
-    }
-}
+use crate::error::{DnsError, ParseErrorKind};
+
+use super::{
+    compression::NameCompressor,
+    edns::Edns,
+    flags::{Flags, HeaderFlags, ResponseCode},
+    header::PacketHeader,
+    question::Question,
+    resource_record::ResourceRecord,
+};
+
+/*
+    Maximum size of a DNS message sent over UDP without EDNS (RFC 1035, Section 4.2.1)
+*/
+pub const UDP_MAX_SIZE: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Packet {
+    pub header: PacketHeader,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+    // OPT pseudo-record, kept apart from the other additional records
+    pub edns: Option<Edns>,
+}
+
+impl Packet {
+    pub fn serialize(&self) -> Result<Vec<u8>, DnsError> {
+        let mut compressor = NameCompressor::new();
+        let mut buffer = Vec::new();
+        buffer.extend(self.header.serialize()?);
+        for question in &self.questions {
+            question.serialize_into(&mut buffer, &mut compressor)?;
+        }
+        for answer in &self.answers {
+            answer.serialize_into(&mut buffer, &mut compressor)?;
+        }
+        for authority in &self.authorities {
+            authority.serialize_into(&mut buffer, &mut compressor)?;
+        }
+        for additional in &self.additionals {
+            additional.serialize_into(&mut buffer, &mut compressor)?;
+        }
+        if let Some(edns) = &self.edns {
+            edns.to_record().serialize_into(&mut buffer, &mut compressor)?;
+        }
+        Ok(buffer)
+    }
+
+    /*
+        Full response code, including the upper bits carried by the OPT record
+    */
+    pub fn rcode(&self) -> ResponseCode {
+        let rcode = HeaderFlags::from(self.header.flags).2 as u16 & 0b1111;
+        let extended_rcode = self.edns.as_ref().map_or(0, |edns| edns.extended_rcode as u16);
+        ResponseCode::from_u16((extended_rcode << 4) | rcode)
+    }
+
+    /*
+        Serializes the packet into at most max_size bytes. The question section is always
+        kept, resource records that don't fit are dropped. If answer or authority records
+        had to be dropped, the TC flag is set so the client can retry over TCP (RFC 2181, Section 9).
+        The OPT record is always kept as well (RFC 6891, Section 7).
+    */
+    pub fn serialize_truncated(&self, max_size: usize) -> Result<Vec<u8>, DnsError> {
+        let buffer = self.serialize()?;
+        if buffer.len() <= max_size {
+            return Ok(buffer);
+        }
+
+        // the OPT record is owned by the root domain, so it's never compressed
+        let opt = match &self.edns {
+            Some(edns) => edns.to_record().serialize()?,
+            None => Vec::new(),
+        };
+        let max_size = max_size.saturating_sub(opt.len());
+
+        let mut compressor = NameCompressor::new();
+        let mut header = self.header.clone();
+        let mut buffer = header.serialize()?;
+        for question in &self.questions {
+            question.serialize_into(&mut buffer, &mut compressor)?;
+        }
+
+        let mut truncated = false;
+        let mut counts = [0u16; 3];
+        let sections = [&self.answers, &self.authorities, &self.additionals];
+        'sections: for (section, records) in sections.iter().enumerate() {
+            for record in records.iter() {
+                let length = buffer.len();
+                record.serialize_into(&mut buffer, &mut compressor)?;
+                if buffer.len() > max_size {
+                    // names the compressor learned from this record are never referenced,
+                    // as nothing gets written after it
+                    buffer.truncate(length);
+                    // dropping additional records doesn't require the client to retry
+                    truncated = section < 2;
+                    break 'sections;
+                }
+                counts[section] += 1;
+            }
+        }
+
+        buffer.extend(&opt);
+
+        header.ancount = counts[0];
+        header.nscount = counts[1];
+        header.arcount = counts[2] + self.edns.is_some() as u16;
+        if truncated {
+            header.flags |= Flags::TC as u16;
+        }
+
+        let header = header.serialize()?;
+        buffer[..header.len()].copy_from_slice(&header);
+        Ok(buffer)
+    }
+
+    pub fn deserialize(buffer: &[u8]) -> Result<Packet, DnsError> {
+        let mut offset = 0;
+        let header = PacketHeader::deserialize(&buffer[offset..])?;
+        offset += std::mem::size_of::<PacketHeader>();
+
+        let mut questions = Vec::new();
+        for _ in 0..header.qdcount {
+            let question = Question::deserialize(buffer, &mut offset)?;
+            questions.push(question);
+        }
+
+        let mut answers = Vec::new();
+        for _ in 0..header.ancount {
+            let answer = ResourceRecord::deserialize(buffer, &mut offset)?;
+            answers.push(answer);
+        }
+
+        let mut authorities = Vec::new();
+        for _ in 0..header.nscount {
+            let authority = ResourceRecord::deserialize(buffer, &mut offset)?;
+            authorities.push(authority);
+        }
+
+        let mut additionals = Vec::new();
+        let mut edns = None;
+        for _ in 0..header.arcount {
+            let record_offset = offset;
+            let additional = ResourceRecord::deserialize(buffer, &mut offset)?;
+            if !Edns::is_opt(&additional) {
+                additionals.push(additional);
+                continue;
+            }
+
+            // more than one OPT record is a format error (RFC 6891, Section 6.1.1)
+            if edns.is_some() {
+                return Err(DnsError::parse(record_offset, ParseErrorKind::InvalidOpt));
+            }
+            edns = Some(Edns::from_record(&additional, record_offset)?);
+        }
+
+        Ok(Packet {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+            edns,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::protocol::packet::{record_type::RecordType, resource_record, PacketBuilder, RData};
+
+    use super::*;
+
+    #[test]
+    fn test_packet_serialize_deserialize() {
+        let header = PacketHeader {
+            id: 0x1234,
+            flags: 0x0100,
+            qdcount: 1,
+            ancount: 1,
+            nscount: 1,
+            arcount: 1,
+        };
+
+        let question = Question::default()
+            .with_name("example.com".to_string())
+            .with_qtype(RecordType::A as u16)
+            .with_qclass(1);
+
+        let resource_record = resource_record::ResourceRecord::default()
+            .with_name("example.com".to_string())
+            .with_rclass(1)
+            .with_ttl(3600)
+            .with_rdata(RData::A(Ipv4Addr::new(192, 0, 2, 1)));
+
+        let packet = Packet {
+            header,
+            questions: vec![question],
+            answers: vec![resource_record.clone()],
+            authorities: vec![resource_record.clone()],
+            additionals: vec![resource_record.clone()],
+            edns: None,
+        };
+
+        let serialized = packet.serialize().expect("Failed to serialize packet");
+        let deserialized = Packet::deserialize(&serialized).expect("Failed to deserialize packet");
+
+        assert_eq!(packet.header, deserialized.header);
+        assert_eq!(packet.questions, deserialized.questions);
+        assert_eq!(packet.answers, deserialized.answers);
+        assert_eq!(packet.authorities, deserialized.authorities);
+        assert_eq!(packet.additionals, deserialized.additionals);
+    }
+
+    #[test]
+    fn test_packet_serialize_truncated() {
+        let question = Question::default()
+            .with_name("example.com".to_string())
+            .with_qtype(RecordType::TXT as u16)
+            .with_qclass(1);
+
+        let resource_record = resource_record::ResourceRecord::default()
+            .with_name("example.com".to_string())
+            .with_rclass(1)
+            .with_ttl(3600)
+            .with_rdata(RData::TXT(vec![vec![100; 100]]));
+
+        let packet = PacketBuilder::new()
+            .with_id(0x1234)
+            .with_qentries(vec![question])
+            .with_aentries(vec![resource_record.clone(); 10])
+            .build();
+
+        // small packets are left untouched
+        let serialized = packet.serialize_truncated(UDP_MAX_SIZE * 10).unwrap();
+        assert_eq!(serialized, packet.serialize().unwrap());
+
+        let serialized = packet.serialize_truncated(UDP_MAX_SIZE).unwrap();
+        assert!(serialized.len() <= UDP_MAX_SIZE);
+
+        let deserialized = Packet::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized.questions, packet.questions);
+        assert_eq!(deserialized.answers.len(), 4);
+        assert_eq!(deserialized.header.ancount, 4);
+        assert_ne!(deserialized.header.flags & Flags::TC as u16, 0);
+
+        // dropping additional records alone doesn't set TC
+        let packet = PacketBuilder::new()
+            .with_id(0x1234)
+            .with_addentries(vec![resource_record; 10])
+            .build();
+
+        let deserialized = Packet::deserialize(&packet.serialize_truncated(UDP_MAX_SIZE).unwrap()).unwrap();
+        assert_eq!(deserialized.additionals.len(), 4);
+        assert_eq!(deserialized.header.flags & Flags::TC as u16, 0);
+    }
+
+    #[test]
+    fn test_packet_compression() {
+        let question = Question::default()
+            .with_name("example.com".to_string())
+            .with_qtype(RecordType::MX as u16)
+            .with_qclass(1);
+
+        let record = |name: &str, rdata: &str| {
+            resource_record::ResourceRecord::default()
+                .with_name(name.to_string())
+                .with_rclass(1)
+                .with_ttl(3600)
+                .with_rdata(rdata.parse().unwrap())
+        };
+
+        let packet = PacketBuilder::new()
+            .with_id(0x1234)
+            .with_qentries(vec![question])
+            .with_aentries(vec![
+                record("example.com", "MX 10 mail.example.com."),
+                record("www.example.com", "CNAME example.com."),
+            ])
+            .with_authentries(vec![record(
+                "example.com",
+                "SOA ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 300",
+            )])
+            .with_addentries(vec![record("mail.example.com", "A 192.0.2.1")])
+            .build();
+
+        let uncompressed_size = packet.header.serialize().unwrap().len()
+            + packet.questions.iter().map(|q| q.serialize().unwrap().len()).sum::<usize>()
+            + packet
+                .answers
+                .iter()
+                .chain(&packet.authorities)
+                .chain(&packet.additionals)
+                .map(|r| r.serialize().unwrap().len())
+                .sum::<usize>();
+
+        let serialized = packet.serialize().unwrap();
+        assert!(serialized.len() < uncompressed_size);
+
+        // the answer's owner name is a pointer to the question name
+        let answer_offset = 12 + 17;
+        assert_eq!(serialized[answer_offset..answer_offset + 2], [0xC0, 12]);
+
+        let deserialized = Packet::deserialize(&serialized).unwrap();
+        assert_eq!(packet, deserialized);
+
+        // cutting the message off anywhere must fail gracefully
+        for len in 0..serialized.len() {
+            assert!(Packet::deserialize(&serialized[..len]).is_err());
+        }
+    }
+}
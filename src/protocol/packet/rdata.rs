@@ -0,0 +1,630 @@
+use std::{
+    fmt::{Display, Formatter},
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::{
+    error::{DnsError, ParseErrorKind, PresentationError},
+    protocol::util,
+};
+
+use super::{compression::NameCompressor, RecordType};
+
+/*
+    Typed RDATA of the record types we know how to handle. Everything
+    else is passed through as-is (RFC 3597).
+*/
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum RData {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    NS(String),
+    PTR(String),
+    MX {
+        preference: u16,
+        exchange: String,
+    },
+    TXT(Vec<Vec<u8>>),
+    SOA {
+        mname: String,
+        rname: String,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+    },
+    SRV {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    CAA {
+        flags: u8,
+        tag: String,
+        value: Vec<u8>,
+    },
+    Unknown {
+        rtype: u16,
+        data: Vec<u8>,
+    },
+}
+
+impl Default for RData {
+    fn default() -> Self {
+        RData::Unknown {
+            rtype: RecordType::NULL.into(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl RData {
+    pub fn rtype(&self) -> u16 {
+        match self {
+            RData::A(_) => RecordType::A.into(),
+            RData::AAAA(_) => RecordType::AAAA.into(),
+            RData::CNAME(_) => RecordType::CNAME.into(),
+            RData::NS(_) => RecordType::NS.into(),
+            RData::PTR(_) => RecordType::PTR.into(),
+            RData::MX { .. } => RecordType::MX.into(),
+            RData::TXT(_) => RecordType::TXT.into(),
+            RData::SOA { .. } => RecordType::SOA.into(),
+            RData::SRV { .. } => RecordType::SRV.into(),
+            RData::CAA { .. } => RecordType::CAA.into(),
+            RData::Unknown { rtype, .. } => *rtype,
+        }
+    }
+
+    /*
+        Decodes rdlength bytes of RDATA at offset. buffer has to be the whole
+        message, as names inside the RDATA may point anywhere in front of it.
+    */
+    pub fn decode(
+        rtype: u16,
+        buffer: &[u8],
+        offset: &mut usize,
+        rdlength: usize,
+    ) -> Result<RData, DnsError> {
+        let start = *offset;
+        // make sure nothing past the RDATA is read
+        let end = start
+            .checked_add(rdlength)
+            .filter(|end| *end <= buffer.len())
+            .ok_or_else(|| DnsError::parse(start, ParseErrorKind::UnexpectedEnd))?;
+        let message = &buffer[..end];
+
+        let rdata = match RecordType::from(rtype) {
+            _ if RecordType::from(rtype) as u16 != rtype => RData::Unknown {
+                rtype,
+                data: util::read_bytes(message, offset, rdlength)?.to_vec(),
+            },
+            RecordType::A => {
+                let bytes = util::read_bytes(message, offset, 4)?;
+                RData::A(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]))
+            }
+            RecordType::AAAA => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(util::read_bytes(message, offset, 16)?);
+                RData::AAAA(Ipv6Addr::from(octets))
+            }
+            RecordType::CNAME => RData::CNAME(util::decode_domain(message, offset)?),
+            RecordType::NS => RData::NS(util::decode_domain(message, offset)?),
+            RecordType::PTR => RData::PTR(util::decode_domain(message, offset)?),
+            RecordType::MX => RData::MX {
+                preference: util::read_u16(message, offset)?,
+                exchange: util::decode_domain(message, offset)?,
+            },
+            RecordType::TXT => {
+                let mut strings = Vec::new();
+                while *offset < end {
+                    let len = util::read_bytes(message, offset, 1)?[0] as usize;
+                    strings.push(util::read_bytes(message, offset, len)?.to_vec());
+                }
+                RData::TXT(strings)
+            }
+            RecordType::SOA => RData::SOA {
+                mname: util::decode_domain(message, offset)?,
+                rname: util::decode_domain(message, offset)?,
+                serial: util::read_u32(message, offset)?,
+                refresh: util::read_u32(message, offset)?,
+                retry: util::read_u32(message, offset)?,
+                expire: util::read_u32(message, offset)?,
+                minimum: util::read_u32(message, offset)?,
+            },
+            RecordType::SRV => RData::SRV {
+                priority: util::read_u16(message, offset)?,
+                weight: util::read_u16(message, offset)?,
+                port: util::read_u16(message, offset)?,
+                target: util::decode_domain(message, offset)?,
+            },
+            RecordType::CAA => {
+                let flags = util::read_bytes(message, offset, 1)?[0];
+                let tag_len = util::read_bytes(message, offset, 1)?[0] as usize;
+                let tag = std::str::from_utf8(util::read_bytes(message, offset, tag_len)?)
+                    .map_err(|_| DnsError::parse(start, ParseErrorKind::RdataLength))?
+                    .to_string();
+                let value = util::read_bytes(message, offset, end - *offset)?.to_vec();
+                RData::CAA { flags, tag, value }
+            }
+            _ => RData::Unknown {
+                rtype,
+                data: util::read_bytes(message, offset, rdlength)?.to_vec(),
+            },
+        };
+
+        if *offset != end {
+            return Err(DnsError::parse(start, ParseErrorKind::RdataLength));
+        }
+
+        Ok(rdata)
+    }
+
+    /*
+        Decodes RDATA stored on its own, e.g. in the database
+    */
+    pub fn from_wire(rtype: u16, data: &[u8]) -> Result<RData, DnsError> {
+        RData::decode(rtype, data, &mut 0, data.len())
+    }
+
+    /*
+        Appends the RDATA to a message. Names are only compressed for the types
+        defined in RFC 1035 (RFC 3597, Section 4), and only if a compressor is given.
+    */
+    pub fn encode_into(
+        &self,
+        buf: &mut Vec<u8>,
+        mut compressor: Option<&mut NameCompressor>,
+    ) -> Result<(), DnsError> {
+        let mut encode_name = |buf: &mut Vec<u8>, name: &str| match compressor {
+            Some(ref mut compressor) => compressor.encode_name(buf, name),
+            None => {
+                buf.extend(util::encode_domain(name.to_string())?);
+                Ok(())
+            }
+        };
+
+        match self {
+            RData::A(addr) => buf.extend(addr.octets()),
+            RData::AAAA(addr) => buf.extend(addr.octets()),
+            RData::CNAME(name) | RData::NS(name) | RData::PTR(name) => encode_name(buf, name)?,
+            RData::MX {
+                preference,
+                exchange,
+            } => {
+                buf.extend(preference.to_be_bytes());
+                encode_name(buf, exchange)?;
+            }
+            RData::TXT(strings) => {
+                for string in strings {
+                    let len: u8 = string
+                        .len()
+                        .try_into()
+                        .map_err(|_| DnsError::encode("TXT string exceeds 255 bytes"))?;
+                    buf.push(len);
+                    buf.extend(string);
+                }
+            }
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => {
+                encode_name(buf, mname)?;
+                encode_name(buf, rname)?;
+                for value in [serial, refresh, retry, expire, minimum] {
+                    buf.extend(value.to_be_bytes());
+                }
+            }
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => {
+                buf.extend(priority.to_be_bytes());
+                buf.extend(weight.to_be_bytes());
+                buf.extend(port.to_be_bytes());
+                // must not be compressed (RFC 2782)
+                buf.extend(util::encode_domain(target.to_string())?);
+            }
+            RData::CAA { flags, tag, value } => {
+                let tag_len: u8 = tag
+                    .len()
+                    .try_into()
+                    .map_err(|_| DnsError::encode("CAA tag exceeds 255 bytes"))?;
+                buf.push(*flags);
+                buf.push(tag_len);
+                buf.extend(tag.as_bytes());
+                buf.extend(value);
+            }
+            RData::Unknown { data, .. } => buf.extend(data),
+        }
+
+        Ok(())
+    }
+
+    /*
+        Encodes the RDATA without any compression, e.g. to store it in the database
+    */
+    pub fn to_wire(&self) -> Result<Vec<u8>, DnsError> {
+        let mut buf = Vec::new();
+        self.encode_into(&mut buf, None)?;
+        Ok(buf)
+    }
+
+    /*
+        Parses the presentation format of the RDATA of a given type, e.g. "10 mail.example.com."
+    */
+    pub fn parse(rtype: u16, rdata: &str) -> Result<RData, PresentationError> {
+        let invalid = || PresentationError(format!("Invalid {} data: {}", type_mnemonic(rtype), rdata));
+
+        // generic encoding works for every type (RFC 3597, Section 5)
+        if let Some(generic) = rdata.trim_start().strip_prefix("\\#") {
+            let mut fields = generic.split_whitespace();
+            let len: usize = fields
+                .next()
+                .and_then(|len| len.parse().ok())
+                .ok_or_else(invalid)?;
+            let hex: String = fields.collect();
+            let data = decode_hex(&hex).ok_or_else(invalid)?;
+            if data.len() != len {
+                return Err(invalid());
+            }
+            return RData::from_wire(rtype, &data).map_err(|_| invalid());
+        }
+
+        let tokens = tokenize(rdata)?;
+        let field = |idx: usize| -> Result<&str, PresentationError> {
+            token_str(tokens.get(idx).ok_or_else(invalid)?)
+        };
+        let number = |idx: usize| -> Result<u32, PresentationError> { field(idx)?.parse().map_err(|_| invalid()) };
+        let short = |idx: usize| -> Result<u16, PresentationError> { field(idx)?.parse().map_err(|_| invalid()) };
+        let name = |idx: usize| -> Result<String, PresentationError> { parse_name(field(idx)?) };
+
+        let expected_fields = match RecordType::from(rtype) {
+            _ if RecordType::from(rtype) as u16 != rtype => None,
+            RecordType::A | RecordType::AAAA | RecordType::CNAME | RecordType::NS | RecordType::PTR => Some(1),
+            RecordType::MX => Some(2),
+            RecordType::SOA => Some(7),
+            RecordType::SRV => Some(4),
+            RecordType::CAA => Some(3),
+            RecordType::TXT => Some(tokens.len().max(1)),
+            _ => None,
+        };
+
+        // types without a presentation format of their own only support the generic encoding
+        if expected_fields != Some(tokens.len()) {
+            return Err(invalid());
+        }
+
+        Ok(match RecordType::from(rtype) {
+            RecordType::A => RData::A(field(0)?.parse().map_err(|_| invalid())?),
+            RecordType::AAAA => RData::AAAA(field(0)?.parse().map_err(|_| invalid())?),
+            RecordType::CNAME => RData::CNAME(name(0)?),
+            RecordType::NS => RData::NS(name(0)?),
+            RecordType::PTR => RData::PTR(name(0)?),
+            RecordType::MX => RData::MX {
+                preference: short(0)?,
+                exchange: name(1)?,
+            },
+            RecordType::TXT => RData::TXT(tokens),
+            RecordType::SOA => RData::SOA {
+                mname: name(0)?,
+                rname: name(1)?,
+                serial: number(2)?,
+                refresh: number(3)?,
+                retry: number(4)?,
+                expire: number(5)?,
+                minimum: number(6)?,
+            },
+            RecordType::SRV => RData::SRV {
+                priority: short(0)?,
+                weight: short(1)?,
+                port: short(2)?,
+                target: name(3)?,
+            },
+            RecordType::CAA => RData::CAA {
+                flags: field(0)?.parse().map_err(|_| invalid())?,
+                tag: field(1)?.to_string(),
+                value: tokens[2].clone(),
+            },
+            _ => return Err(invalid()),
+        })
+    }
+
+    /*
+        Presentation format of the RDATA alone, without the type mnemonic
+    */
+    pub fn data_to_string(&self) -> String {
+        match self {
+            RData::A(addr) => addr.to_string(),
+            RData::AAAA(addr) => addr.to_string(),
+            RData::CNAME(name) | RData::NS(name) | RData::PTR(name) => fqdn(name),
+            RData::MX {
+                preference,
+                exchange,
+            } => format!("{} {}", preference, fqdn(exchange)),
+            RData::TXT(strings) => strings
+                .iter()
+                .map(|string| quote(string))
+                .collect::<Vec<_>>()
+                .join(" "),
+            RData::SOA {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            } => format!(
+                "{} {} {} {} {} {} {}",
+                fqdn(mname),
+                fqdn(rname),
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum
+            ),
+            RData::SRV {
+                priority,
+                weight,
+                port,
+                target,
+            } => format!("{} {} {} {}", priority, weight, port, fqdn(target)),
+            RData::CAA { flags, tag, value } => format!("{} {} {}", flags, tag, quote(value)),
+            RData::Unknown { data, .. } => {
+                let hex: String = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("\\# {} {}", data.len(), hex).trim_end().to_string()
+            }
+        }
+    }
+}
+
+/*
+    Presentation format including the type, e.g. "MX 10 mail.example.com."
+*/
+impl Display for RData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", type_mnemonic(self.rtype()), self.data_to_string())
+    }
+}
+
+impl FromStr for RData {
+    type Err = PresentationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mnemonic, rdata) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        RData::parse(parse_type_mnemonic(mnemonic)?, rdata)
+    }
+}
+
+/*
+    Mnemonic of a record type, or the generic TYPEnnn notation (RFC 3597, Section 5)
+*/
+pub fn type_mnemonic(rtype: u16) -> String {
+    let record_type = RecordType::from(rtype);
+    if record_type.clone() as u16 == rtype {
+        format!("{:?}", record_type)
+    } else {
+        format!("TYPE{}", rtype)
+    }
+}
+
+pub fn parse_type_mnemonic(mnemonic: &str) -> Result<u16, PresentationError> {
+    if let Some(number) = mnemonic.to_uppercase().strip_prefix("TYPE") {
+        if let Ok(rtype) = number.parse() {
+            return Ok(rtype);
+        }
+    }
+
+    RecordType::from_mnemonic(mnemonic)
+        .map(u16::from)
+        .ok_or_else(|| PresentationError(format!("Unknown record type {}", mnemonic)))
+}
+
+fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+fn parse_name(name: &str) -> Result<String, PresentationError> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    // validate the name the same way it's going to be encoded
+    util::encode_domain(name.to_string())
+        .map_err(|err| PresentationError(format!("Invalid name {}: {}", name, err)))?;
+    Ok(name.to_string())
+}
+
+fn token_str(token: &[u8]) -> Result<&str, PresentationError> {
+    std::str::from_utf8(token).map_err(|_| PresentationError("Invalid UTF-8 in field".to_string()))
+}
+
+fn quote(string: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for byte in string {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(*byte as char);
+            }
+            0x20..=0x7E => quoted.push(*byte as char),
+            _ => quoted.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/*
+    Splits presentation format into fields, honouring quotes
+    and backslash escapes (RFC 1035, Section 5.1)
+*/
+fn tokenize(input: &str) -> Result<Vec<Vec<u8>>, PresentationError> {
+    let mut tokens = Vec::new();
+    let mut bytes = input.bytes().peekable();
+
+    while let Some(&byte) = bytes.peek() {
+        if byte.is_ascii_whitespace() {
+            bytes.next();
+            continue;
+        }
+
+        let quoted = byte == b'"';
+        if quoted {
+            bytes.next();
+        }
+
+        let mut token = Vec::new();
+        let mut closed = !quoted;
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'"' if quoted => {
+                    closed = true;
+                    break;
+                }
+                b'\\' => {
+                    let escaped = bytes
+                        .next()
+                        .ok_or_else(|| PresentationError("Dangling escape".to_string()))?;
+                    if escaped.is_ascii_digit() {
+                        let digits = [escaped, bytes.next().unwrap_or(0), bytes.next().unwrap_or(0)];
+                        let value = std::str::from_utf8(&digits)
+                            .ok()
+                            .and_then(|digits| digits.parse::<u8>().ok())
+                            .ok_or_else(|| PresentationError("Invalid escape".to_string()))?;
+                        token.push(value);
+                    } else {
+                        token.push(escaped);
+                    }
+                }
+                _ if !quoted && byte.is_ascii_whitespace() => break,
+                _ => token.push(byte),
+            }
+        }
+
+        if !closed {
+            return Err(PresentationError("Unterminated quote".to_string()));
+        }
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rdata_wire_roundtrip() {
+        let cases = [
+            RData::A(Ipv4Addr::new(192, 0, 2, 1)),
+            RData::AAAA("2001:db8::1".parse().unwrap()),
+            RData::CNAME("dns.is.tidy".to_string()),
+            RData::MX {
+                preference: 10,
+                exchange: "mail.example.com".to_string(),
+            },
+            RData::TXT(vec![b"v=spf1 -all".to_vec(), Vec::new()]),
+            RData::SOA {
+                mname: "ns1.example.com".to_string(),
+                rname: "hostmaster.example.com".to_string(),
+                serial: 2025022501,
+                refresh: 7200,
+                retry: 3600,
+                expire: 1209600,
+                minimum: 300,
+            },
+            RData::SRV {
+                priority: 0,
+                weight: 5,
+                port: 5060,
+                target: "sip.example.com".to_string(),
+            },
+            RData::CAA {
+                flags: 0,
+                tag: "issue".to_string(),
+                value: b"letsencrypt.org".to_vec(),
+            },
+            RData::Unknown {
+                rtype: 65,
+                data: vec![0, 1, 0],
+            },
+        ];
+
+        for rdata in cases {
+            let wire = rdata.to_wire().unwrap();
+            assert_eq!(RData::from_wire(rdata.rtype(), &wire).unwrap(), rdata);
+        }
+
+        // A record with the wrong length
+        assert!(RData::from_wire(RecordType::A.into(), &[192, 0, 2]).is_err());
+        assert!(RData::from_wire(RecordType::A.into(), &[192, 0, 2, 1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_rdata_presentation() {
+        let cases = [
+            "A 192.0.2.1",
+            "AAAA 2001:db8::1",
+            "CNAME dns.is.tidy.",
+            "MX 10 mail.example.com.",
+            "TXT \"v=spf1 -all\" \"say \\\"hi\\\"\"",
+            "SOA ns1.example.com. hostmaster.example.com. 1 7200 3600 1209600 300",
+            "SRV 0 5 5060 sip.example.com.",
+            "CAA 0 issue \"letsencrypt.org\"",
+            "HTTPS \\# 3 000100",
+            "TYPE999 \\# 2 abcd",
+            "TYPE999 \\# 0",
+        ];
+
+        for case in cases {
+            let rdata: RData = case.parse().unwrap();
+            assert_eq!(rdata.to_string(), case);
+        }
+
+        assert_eq!(
+            "mx 10 mail.example.com".parse::<RData>().unwrap(),
+            RData::MX {
+                preference: 10,
+                exchange: "mail.example.com".to_string()
+            }
+        );
+        assert_eq!(
+            "TXT unquoted".parse::<RData>().unwrap(),
+            RData::TXT(vec![b"unquoted".to_vec()])
+        );
+        assert_eq!(
+            "A \\# 4 c0000201".parse::<RData>().unwrap(),
+            RData::A(Ipv4Addr::new(192, 0, 2, 1))
+        );
+
+        assert!("A 192.0.2".parse::<RData>().is_err());
+        assert!("MX mail.example.com.".parse::<RData>().is_err());
+        assert!("CNAME a..b".parse::<RData>().is_err());
+        assert!("TXT \"unterminated".parse::<RData>().is_err());
+        assert!("BOGUS 1".parse::<RData>().is_err());
+    }
+}
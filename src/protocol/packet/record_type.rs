@@ -1,62 +1,102 @@
-#[derive(Debug, Clone, PartialEq)]
-#[repr(u16)]
-pub enum RecordType {
-    A = 1,
-    NS,
-    MD,
-    MF,
-    CNAME,
-    SOA,
-    MB,
-    MG,
-    MR,
-    NULL,
-    WKS,
-    PTR,
-    HINFO,
-    MINFO,
-    MX,
-    TXT,
-    AAAA = 28,
-    HTTPS = 65,
-    AXFR = 252,
-    MAILB,
-    MAILA,
-    BROADCAST,
-}
-
-impl From<u16> for RecordType {
-    fn from(value: u16) -> Self {
-        match value {
-            1 => RecordType::A,
-            2 => RecordType::NS,
-            3 => RecordType::MD,
-            4 => RecordType::MF,
-            5 => RecordType::CNAME,
-            6 => RecordType::SOA,
-            7 => RecordType::MB,
-            8 => RecordType::MG,
-            9 => RecordType::MR,
-            10 => RecordType::NULL,
-            11 => RecordType::WKS,
-            12 => RecordType::PTR,
-            13 => RecordType::HINFO,
-            14 => RecordType::MINFO,
-            15 => RecordType::MX,
-            16 => RecordType::TXT,
-            28 => RecordType::AAAA,
-            65 => RecordType::HTTPS,
-            252 => RecordType::AXFR,
-            253 => RecordType::MAILB,
-            254 => RecordType::MAILA,
-            255 => RecordType::BROADCAST,
-            _ => RecordType::NULL,
-        }
-    }
-}
-
-impl Into<u16> for RecordType {
-    fn into(self) -> u16 {
-        self as u16
-    }
-}
+#[derive(Debug, Clone, PartialEq)]
+#[repr(u16)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum RecordType {
+    A = 1,
+    NS,
+    MD,
+    MF,
+    CNAME,
+    SOA,
+    MB,
+    MG,
+    MR,
+    NULL,
+    WKS,
+    PTR,
+    HINFO,
+    MINFO,
+    MX,
+    TXT,
+    AAAA = 28,
+    SRV = 33,
+    OPT = 41,
+    HTTPS = 65,
+    AXFR = 252,
+    MAILB,
+    MAILA,
+    BROADCAST,
+    CAA = 257,
+}
+
+impl From<u16> for RecordType {
+    fn from(value: u16) -> Self {
+        match value {
+            1 => RecordType::A,
+            2 => RecordType::NS,
+            3 => RecordType::MD,
+            4 => RecordType::MF,
+            5 => RecordType::CNAME,
+            6 => RecordType::SOA,
+            7 => RecordType::MB,
+            8 => RecordType::MG,
+            9 => RecordType::MR,
+            10 => RecordType::NULL,
+            11 => RecordType::WKS,
+            12 => RecordType::PTR,
+            13 => RecordType::HINFO,
+            14 => RecordType::MINFO,
+            15 => RecordType::MX,
+            16 => RecordType::TXT,
+            28 => RecordType::AAAA,
+            33 => RecordType::SRV,
+            41 => RecordType::OPT,
+            65 => RecordType::HTTPS,
+            252 => RecordType::AXFR,
+            253 => RecordType::MAILB,
+            254 => RecordType::MAILA,
+            255 => RecordType::BROADCAST,
+            257 => RecordType::CAA,
+            _ => RecordType::NULL,
+        }
+    }
+}
+
+impl RecordType {
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Self> {
+        match mnemonic.to_uppercase().as_str() {
+            "A" => Some(RecordType::A),
+            "NS" => Some(RecordType::NS),
+            "MD" => Some(RecordType::MD),
+            "MF" => Some(RecordType::MF),
+            "CNAME" => Some(RecordType::CNAME),
+            "SOA" => Some(RecordType::SOA),
+            "MB" => Some(RecordType::MB),
+            "MG" => Some(RecordType::MG),
+            "MR" => Some(RecordType::MR),
+            "NULL" => Some(RecordType::NULL),
+            "WKS" => Some(RecordType::WKS),
+            "PTR" => Some(RecordType::PTR),
+            "HINFO" => Some(RecordType::HINFO),
+            "MINFO" => Some(RecordType::MINFO),
+            "MX" => Some(RecordType::MX),
+            "TXT" => Some(RecordType::TXT),
+            "AAAA" => Some(RecordType::AAAA),
+            "SRV" => Some(RecordType::SRV),
+            "OPT" => Some(RecordType::OPT),
+            "HTTPS" => Some(RecordType::HTTPS),
+            "AXFR" => Some(RecordType::AXFR),
+            "MAILB" => Some(RecordType::MAILB),
+            "MAILA" => Some(RecordType::MAILA),
+            "ANY" | "BROADCAST" => Some(RecordType::BROADCAST),
+            "CAA" => Some(RecordType::CAA),
+            _ => None,
+        }
+    }
+}
+
+impl From<RecordType> for u16 {
+    fn from(value: RecordType) -> Self {
+        value as u16
+    }
+}
@@ -1,175 +1,153 @@
-use crate::protocol::util;
-
-use super::RecordType;
-
-#[derive(PartialEq, Clone)]
-pub struct ResourceRecord {
-    name: String,
-    rtype: u16,
-    rclass: u16,
-    ttl: u32,
-    rdlength: u16,
-    rdata: Vec<u8>,
-    size: usize,
-}
-
-impl Default for ResourceRecord {
-    fn default() -> Self {
-        Self {
-            name: String::new(),
-            rtype: 1,
-            rclass: 1,
-            ttl: 0,
-            rdlength: 0,
-            rdata: Vec::new(),
-            size: 12,
-        }
-    }
-}
-
-impl std::fmt::Debug for ResourceRecord {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("ResourceRecord")
-            .field("name", &self.name)
-            .field("rtype", &RecordType::from(self.rtype))
-            .field("rclass", &self.rclass)
-            .field("ttl", &self.ttl)
-            .field("rdlength", &self.rdlength)
-            .finish()
-    }
-}
-
-impl ResourceRecord {
-    pub fn with_name(mut self, name: String) -> Self {
-        self.name = name;
-        self.size = 12 + self.name.len();
-        self
-    }
-
-    pub fn with_rtype(mut self, rtype: RecordType) -> Self {
-        self.rtype = rtype.into();
-        self
-    }
-
-    pub fn with_rclass(mut self, rclass: u16) -> Self {
-        self.rclass = rclass;
-        self
-    }
-
-    pub fn with_ttl(mut self, ttl: u32) -> Self {
-        self.ttl = ttl;
-        self
-    }
-
-    pub fn with_rdata(mut self, rdata: Vec<u8>) -> Self {
-        self.rdlength = rdata.len() as u16;
-        self.rdata = rdata;
-        self.size = 12 + self.name.len() + self.rdata.len();
-        self
-    }
-
-    pub fn serialize(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut buf = Vec::new();
-        buf.extend(util::encode_domain(self.name.clone())?);
-        buf.extend(&self.rtype.to_be_bytes());
-        buf.extend(&self.rclass.to_be_bytes());
-        buf.extend(&self.ttl.to_be_bytes());
-        buf.extend(&self.rdlength.to_be_bytes());
-        buf.extend(&self.rdata);
-        Ok(buf)
-    }
-
-    pub fn deserialize(
-        buffer: &[u8],
-        offset: &mut usize,
-    ) -> Result<ResourceRecord, Box<dyn std::error::Error>> {
-        let mut name = String::new();
-
-        if buffer[*offset] & 0xc0 == 0xc0 {
-            let pointer = u16::from_be_bytes([buffer[*offset], buffer[*offset + 1]]) & 0x3FFF;
-            let mut loop_offset = pointer as usize;
-
-            loop {
-                let len = buffer[loop_offset] as usize;
-                if len == 0 {
-                    break;
-                }
-                if !name.is_empty() {
-                    name.push('.');
-                }
-                name.push_str(std::str::from_utf8(
-                    &buffer[loop_offset + 1..loop_offset + 1 + len],
-                )?);
-                loop_offset += len + 1;
-            }
-
-            *offset += 2;
-        } else {
-            loop {
-                let len = buffer[*offset] as usize;
-                if len == 0 {
-                    *offset += 1;
-                    break;
-                }
-                if !name.is_empty() {
-                    name.push('.');
-                }
-                name.push_str(std::str::from_utf8(
-                    &buffer[*offset + 1..*offset + 1 + len],
-                )?);
-                *offset += len + 1;
-            }
-        }
-        let rtype = u16::from_be_bytes([buffer[*offset], buffer[*offset + 1]]);
-        *offset += 2;
-        let rclass = u16::from_be_bytes([buffer[*offset], buffer[*offset + 1]]);
-        *offset += 2;
-        let ttl = u32::from_be_bytes([
-            buffer[*offset],
-            buffer[*offset + 1],
-            buffer[*offset + 2],
-            buffer[*offset + 3],
-        ]);
-        *offset += 4;
-        let rdlength = u16::from_be_bytes([buffer[*offset], buffer[*offset + 1]]);
-        *offset += 2;
-        let rdata = buffer[*offset..*offset + rdlength as usize].to_vec();
-        *offset += rdlength as usize;
-
-        let size = 12 + name.len() + rdata.len();
-
-        Ok(ResourceRecord {
-            name,
-            rtype,
-            rclass,
-            ttl,
-            rdlength,
-            rdata,
-            size,
-        })
-    }
-
-    pub fn size(&self) -> usize {
-        self.size
-    }
-
-    pub fn name(&self) -> String {
-        self.name.clone()
-    }
-
-    pub fn rtype(&self) -> RecordType {
-        self.rtype.into()
-    }
-
-    pub fn rclass(&self) -> u16 {
-        self.rclass
-    }
-
-    pub fn ttl(&self) -> u32 {
-        self.ttl
-    }
-
-    pub fn rdata(&self) -> Vec<u8> {
-        self.rdata.clone()
-    }
-}
+use crate::{error::DnsError, protocol::util};
+
+use super::{compression::NameCompressor, RData, RecordType};
+
+#[derive(PartialEq, Clone)]
+pub struct ResourceRecord {
+    name: String,
+    rtype: u16,
+    rclass: u16,
+    ttl: u32,
+    rdata: RData,
+}
+
+impl Default for ResourceRecord {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            rtype: 1,
+            rclass: 1,
+            ttl: 0,
+            rdata: RData::default(),
+        }
+    }
+}
+
+impl std::fmt::Debug for ResourceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResourceRecord")
+            .field("name", &self.name)
+            .field("rtype", &RecordType::from(self.rtype))
+            .field("rclass", &self.rclass)
+            .field("ttl", &self.ttl)
+            .field("rdata", &format_args!("{}", self.rdata.data_to_string()))
+            .finish()
+    }
+}
+
+impl ResourceRecord {
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    pub fn with_rtype(mut self, rtype: RecordType) -> Self {
+        self.rtype = rtype.into();
+        self
+    }
+
+    pub fn with_rclass(mut self, rclass: u16) -> Self {
+        self.rclass = rclass;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /*
+        Sets the RDATA along with the record type it belongs to
+    */
+    pub fn with_rdata(mut self, rdata: RData) -> Self {
+        self.rtype = rdata.rtype();
+        self.rdata = rdata;
+        self
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, DnsError> {
+        let rdata = self.rdata.to_wire()?;
+        let rdlength: u16 = rdata
+            .len()
+            .try_into()
+            .map_err(|_| DnsError::encode("RDATA exceeds 65535 bytes"))?;
+
+        let mut buf = Vec::new();
+        buf.extend(util::encode_domain(self.name.clone())?);
+        buf.extend(&self.rtype.to_be_bytes());
+        buf.extend(&self.rclass.to_be_bytes());
+        buf.extend(&self.ttl.to_be_bytes());
+        buf.extend(&rdlength.to_be_bytes());
+        buf.extend(&rdata);
+        Ok(buf)
+    }
+
+    /*
+        Appends the record to a message, compressing its owner name
+        as well as the domain names inside its RDATA
+    */
+    pub fn serialize_into(
+        &self,
+        buf: &mut Vec<u8>,
+        compressor: &mut NameCompressor,
+    ) -> Result<(), DnsError> {
+        compressor.encode_name(buf, &self.name)?;
+        buf.extend(&self.rtype.to_be_bytes());
+        buf.extend(&self.rclass.to_be_bytes());
+        buf.extend(&self.ttl.to_be_bytes());
+
+        let rdlength_offset = buf.len();
+        buf.extend([0, 0]);
+        self.rdata.encode_into(buf, Some(compressor))?;
+
+        let rdlength: u16 = (buf.len() - rdlength_offset - 2)
+            .try_into()
+            .map_err(|_| DnsError::encode("RDATA exceeds 65535 bytes"))?;
+        buf[rdlength_offset..rdlength_offset + 2].copy_from_slice(&rdlength.to_be_bytes());
+        Ok(())
+    }
+
+    pub fn deserialize(
+        buffer: &[u8],
+        offset: &mut usize,
+    ) -> Result<ResourceRecord, DnsError> {
+        let name = util::decode_domain(buffer, offset)?;
+        let rtype = util::read_u16(buffer, offset)?;
+        let rclass = util::read_u16(buffer, offset)?;
+        let ttl = util::read_u32(buffer, offset)?;
+        let rdlength = util::read_u16(buffer, offset)? as usize;
+        let rdata = RData::decode(rtype, buffer, offset, rdlength)?;
+
+        Ok(ResourceRecord {
+            name,
+            rtype,
+            rclass,
+            ttl,
+            rdata,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        12 + self.name.len() + self.rdata.to_wire().map(|rdata| rdata.len()).unwrap_or(0)
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn rtype(&self) -> RecordType {
+        self.rtype.into()
+    }
+
+    pub fn rclass(&self) -> u16 {
+        self.rclass
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn rdata(&self) -> RData {
+        self.rdata.clone()
+    }
+}
@@ -1,75 +1,244 @@
-pub fn encode_domain(mut name: String) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    if let Some(pstrip) = name.strip_prefix(".") {
-        name = pstrip.to_string();
-    }
-
-    if let Some(sstrip) = name.strip_suffix(".") {
-        name = sstrip.to_string();
-    }
-
-    if name.len() == 0 {
-        return Ok(vec![0]);
-    }
-
-    let mut buf = Vec::new();
-    for part in name.split('.') {
-        if part.len() == 0 {
-            return Err("Invalid domain name".into());
-        }
-        buf.push(part.len() as u8);
-        buf.extend(part.as_bytes());
-    }
-    buf.push(0);
-    Ok(buf)
-}
-
-pub fn get_upzone(name: String) -> String {
-    name.split(".").skip(1).collect::<Vec<&str>>().join(".")
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_encode_domain() {
-        let encoded = encode_domain("example.com".to_string());
-        assert!(encoded.is_ok());
-        assert_eq!(
-            encoded.unwrap(),
-            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
-        );
-
-        let encoded = encode_domain("example.com.".to_string());
-        assert!(encoded.is_ok());
-        assert_eq!(
-            encoded.unwrap(),
-            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
-        );
-
-        let encoded = encode_domain(".example.com".to_string());
-        assert!(encoded.is_ok());
-        assert_eq!(
-            encoded.unwrap(),
-            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
-        );
-
-        let encoded = encode_domain("example..com".to_string());
-        assert!(encoded.is_err());
-
-        let encoded = encode_domain(".".to_string());
-        assert!(encoded.is_ok());
-        assert_eq!(encoded.unwrap(), vec![0]);
-    }
-
-    fn test_get_upzone() {
-        debug_assert_eq!(
-            get_upzone("sub.example.com".to_string()),
-            "example.com".to_string()
-        );
-
-        debug_assert_eq!(get_upzone("example.com".to_string()), "com".to_string());
-
-        debug_assert_eq!(get_upzone("com".to_string()), ".".to_string());
-    }
-}
+use crate::error::{DnsError, ParseErrorKind};
+
+/*
+    Maximum length of a single label and of a whole name in wire format (RFC 1035, Section 2.3.4)
+*/
+pub const MAX_LABEL_LENGTH: usize = 63;
+pub const MAX_NAME_LENGTH: usize = 255;
+
+pub fn encode_domain(mut name: String) -> Result<Vec<u8>, DnsError> {
+    if let Some(pstrip) = name.strip_prefix(".") {
+        name = pstrip.to_string();
+    }
+
+    if let Some(sstrip) = name.strip_suffix(".") {
+        name = sstrip.to_string();
+    }
+
+    if name.is_empty() {
+        return Ok(vec![0]);
+    }
+
+    let mut buf = Vec::new();
+    for part in name.split('.') {
+        if part.is_empty() {
+            return Err(DnsError::encode(format!("Invalid domain name {}", name)));
+        }
+        if part.len() > MAX_LABEL_LENGTH {
+            return Err(DnsError::encode(format!("Label exceeds {} bytes", MAX_LABEL_LENGTH)));
+        }
+        buf.push(part.len() as u8);
+        buf.extend(part.as_bytes());
+    }
+    buf.push(0);
+
+    if buf.len() > MAX_NAME_LENGTH {
+        return Err(DnsError::encode(format!("Domain name exceeds {} bytes", MAX_NAME_LENGTH)));
+    }
+    Ok(buf)
+}
+
+/*
+    Reads len bytes at offset, advancing it
+*/
+pub fn read_bytes<'a>(
+    buffer: &'a [u8],
+    offset: &mut usize,
+    len: usize,
+) -> Result<&'a [u8], DnsError> {
+    let bytes = offset
+        .checked_add(len)
+        .and_then(|end| buffer.get(*offset..end))
+        .ok_or_else(|| DnsError::parse(*offset, ParseErrorKind::UnexpectedEnd))?;
+    *offset += len;
+    Ok(bytes)
+}
+
+pub fn read_u16(buffer: &[u8], offset: &mut usize) -> Result<u16, DnsError> {
+    let bytes = read_bytes(buffer, offset, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn read_u32(buffer: &[u8], offset: &mut usize) -> Result<u32, DnsError> {
+    let bytes = read_bytes(buffer, offset, 4)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/*
+    Decodes a (possibly compressed) domain name from a message. offset is advanced past
+    the name as it is stored at that position, i.e. up to and including the first pointer.
+    Labels and pointers may be mixed arbitrarily, e.g. "www" followed by a pointer to "example.com".
+*/
+pub fn decode_domain(buffer: &[u8], offset: &mut usize) -> Result<String, DnsError> {
+    let start = *offset;
+    let mut labels: Vec<&str> = Vec::new();
+    // wire length of the name, including the terminating zero label
+    let mut name_length = 1;
+    let mut position = *offset;
+    let mut segment_start = *offset;
+    let mut jumped = false;
+
+    loop {
+        let label_offset = position;
+        let len = read_bytes(buffer, &mut position, 1)?[0] as usize;
+
+        match len & 0xC0 {
+            0xC0 => {
+                let pointer = ((len & 0x3F) << 8) | read_bytes(buffer, &mut position, 1)?[0] as usize;
+                // only allow pointers in front of the labels read so far, so we can't be sent in circles
+                if pointer >= segment_start {
+                    return Err(DnsError::parse(label_offset, ParseErrorKind::InvalidPointer));
+                }
+
+                if !jumped {
+                    *offset = position;
+                    jumped = true;
+                }
+                position = pointer;
+                segment_start = pointer;
+            }
+            0x00 if len == 0 => {
+                if !jumped {
+                    *offset = position;
+                }
+                break;
+            }
+            0x00 => {
+                name_length += len + 1;
+                if name_length > MAX_NAME_LENGTH {
+                    return Err(DnsError::parse(start, ParseErrorKind::NameTooLong));
+                }
+
+                let label = read_bytes(buffer, &mut position, len)?;
+                labels.push(
+                    std::str::from_utf8(label)
+                        .map_err(|_| DnsError::parse(label_offset, ParseErrorKind::InvalidLabel))?,
+                );
+            }
+            // 0x40 and 0x80 are reserved (RFC 6891, Section 5)
+            _ => return Err(DnsError::parse(label_offset, ParseErrorKind::UnsupportedLabel)),
+        }
+    }
+
+    Ok(labels.join("."))
+}
+
+pub fn get_upzone(name: String) -> String {
+    let upzone = name.split(".").skip(1).collect::<Vec<&str>>().join(".");
+    if upzone.is_empty() {
+        ".".to_string()
+    } else {
+        upzone
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_domain() {
+        let encoded = encode_domain("example.com".to_string());
+        assert!(encoded.is_ok());
+        assert_eq!(
+            encoded.unwrap(),
+            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
+        );
+
+        let encoded = encode_domain("example.com.".to_string());
+        assert!(encoded.is_ok());
+        assert_eq!(
+            encoded.unwrap(),
+            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
+        );
+
+        let encoded = encode_domain(".example.com".to_string());
+        assert!(encoded.is_ok());
+        assert_eq!(
+            encoded.unwrap(),
+            vec![7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0]
+        );
+
+        let encoded = encode_domain("example..com".to_string());
+        assert!(encoded.is_err());
+
+        let encoded = encode_domain(".".to_string());
+        assert!(encoded.is_ok());
+        assert_eq!(encoded.unwrap(), vec![0]);
+
+        let encoded = encode_domain(format!("{}.com", "a".repeat(64)));
+        assert!(encoded.is_err());
+
+        let encoded = encode_domain(vec!["a".repeat(63); 4].join("."));
+        assert!(encoded.is_err());
+    }
+
+    #[test]
+    fn test_decode_domain() {
+        // example.com, followed by www + pointer to example.com
+        let buffer = [
+            7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 3, 119, 119, 119, 0xC0, 0,
+        ];
+
+        let mut offset = 0;
+        assert_eq!(decode_domain(&buffer, &mut offset).unwrap(), "example.com");
+        assert_eq!(offset, 13);
+
+        assert_eq!(decode_domain(&buffer, &mut offset).unwrap(), "www.example.com");
+        assert_eq!(offset, buffer.len());
+
+        // pointer to itself
+        let mut offset = 0;
+        assert!(decode_domain(&[0xC0, 0], &mut offset).is_err());
+
+        // pointers sending us in circles: 0 -> label "a" -> pointer to 0
+        let mut offset = 4;
+        assert!(decode_domain(&[1, 97, 0xC0, 0, 0xC0, 0], &mut offset).is_err());
+
+        // truncated label and truncated pointer
+        let mut offset = 0;
+        assert!(decode_domain(&[7, 101, 120, 97], &mut offset).is_err());
+        let mut offset = 0;
+        assert!(decode_domain(&[3, 119, 119, 119, 0xC0], &mut offset).is_err());
+        let mut offset = 0;
+        assert!(decode_domain(&[], &mut offset).is_err());
+
+        // reserved label types
+        let mut offset = 0;
+        assert!(decode_domain(&[0x40, 0], &mut offset).is_err());
+        let mut offset = 0;
+        assert!(decode_domain(&[0x80, 0], &mut offset).is_err());
+
+        // names longer than 255 bytes, spread across pointers
+        let mut buffer = Vec::new();
+        for _ in 0..5 {
+            buffer.extend([63; 64]);
+        }
+        buffer.push(0);
+        let mut offset = 0;
+        assert!(decode_domain(&buffer, &mut offset).is_err());
+
+        let mut buffer = vec![63; 64];
+        buffer.extend([63; 64]);
+        buffer.push(0);
+        // two labels + pointer to 0, then one label + pointer to the former
+        buffer.extend([63; 64]);
+        buffer.extend([0xC0, 0]);
+        buffer.extend([63; 64]);
+        buffer.extend([0xC0, 129]);
+        let mut offset = buffer.len() - 66;
+        assert!(decode_domain(&buffer, &mut offset).is_err());
+    }
+
+    #[test]
+    fn test_get_upzone() {
+        debug_assert_eq!(
+            get_upzone("sub.example.com".to_string()),
+            "example.com".to_string()
+        );
+
+        debug_assert_eq!(get_upzone("example.com".to_string()), "com".to_string());
+
+        debug_assert_eq!(get_upzone("com".to_string()), ".".to_string());
+    }
+}
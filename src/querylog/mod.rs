@@ -0,0 +1,3 @@
+mod querylog;
+
+pub use querylog::{QueryLog, QueryLogConfig};
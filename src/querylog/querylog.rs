@@ -0,0 +1,255 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::database::{Database, QueryLogEntity};
+
+/*
+    How the query log is written and how much of it is kept
+*/
+#[derive(Debug, Clone)]
+pub struct QueryLogConfig {
+    // entries written at once
+    batch_size: usize,
+    // longest time an entry waits for its batch to fill up
+    flush_interval: Duration,
+    // entries waiting to be written, further ones are dropped
+    queue_size: usize,
+    max_age: Duration,
+    max_rows: u64,
+    prune_interval: Duration,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        QueryLogConfig {
+            batch_size: 256,
+            flush_interval: Duration::from_secs(1),
+            queue_size: 8192,
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            max_rows: 1_000_000,
+            prune_interval: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl QueryLogConfig {
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    pub fn with_flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    pub fn with_queue_size(mut self, size: usize) -> Self {
+        self.queue_size = size.max(1);
+        self
+    }
+
+    /*
+        Entries older than this are pruned
+    */
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /*
+        Only the newest max_rows entries are kept
+    */
+    pub fn with_max_rows(mut self, max_rows: u64) -> Self {
+        self.max_rows = max_rows;
+        self
+    }
+
+    pub fn with_prune_interval(mut self, interval: Duration) -> Self {
+        self.prune_interval = interval;
+        self
+    }
+}
+
+enum Message {
+    Entry(QueryLogEntity),
+    // write out the current batch, then notify the sender
+    Flush(oneshot::Sender<()>),
+}
+
+/*
+    Handle to the task writing handled queries to the database. Recording an entry never
+    waits for the database; while the writer falls behind, entries are dropped instead.
+*/
+#[derive(Debug, Clone)]
+pub struct QueryLog {
+    sender: mpsc::Sender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl QueryLog {
+    /*
+        Spawns the writer task, which runs until every handle is dropped
+    */
+    pub fn start(db: Arc<Database>, config: QueryLogConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.queue_size);
+        let dropped = Arc::new(AtomicU64::new(0));
+        tokio::spawn(write_entries(db, config, receiver, dropped.clone()));
+
+        QueryLog { sender, dropped }
+    }
+
+    pub fn record(&self, entry: QueryLogEntity) {
+        if self.sender.try_send(Message::Entry(entry)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /*
+        Waits until everything recorded so far has been written
+    */
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.sender.send(Message::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+}
+
+async fn write_batch(db: &Database, batch: &mut Vec<QueryLogEntity>, dropped: &AtomicU64) {
+    let dropped = dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        log::warn!("Query log fell behind, dropped {} entries", dropped);
+    }
+
+    if batch.is_empty() {
+        return;
+    }
+
+    if let Err(err) = QueryLogEntity::_insert_batch(batch, db.get_pool(), db.query_log_tbl()).await {
+        log::error!("Failed to write {} query log entries: {}", batch.len(), err);
+    }
+    batch.clear();
+}
+
+async fn prune(db: &Database, config: &QueryLogConfig) {
+    // a max_age reaching back before any representable date keeps entries regardless of age
+    let older_than = chrono::Duration::from_std(config.max_age)
+        .ok()
+        .and_then(|max_age| chrono::Utc::now().naive_utc().checked_sub_signed(max_age));
+
+    match QueryLogEntity::_prune(db.get_pool(), db.query_log_tbl(), older_than, config.max_rows).await {
+        Ok(0) => {}
+        Ok(pruned) => log::debug!("Pruned {} query log entries", pruned),
+        Err(err) => log::error!("Failed to prune the query log: {}", err),
+    }
+}
+
+async fn write_entries(
+    db: Arc<Database>,
+    config: QueryLogConfig,
+    mut receiver: mpsc::Receiver<Message>,
+    dropped: Arc<AtomicU64>,
+) {
+    // prune before handling any entries, an immediate first tick of the timer
+    // would fire at whatever point select! gets around to it
+    prune(&db, &config).await;
+
+    let mut batch = Vec::with_capacity(config.batch_size);
+    let mut flush_timer = tokio::time::interval(config.flush_interval);
+    let mut prune_timer = tokio::time::interval_at(
+        tokio::time::Instant::now() + config.prune_interval,
+        config.prune_interval,
+    );
+
+    loop {
+        tokio::select! {
+            message = receiver.recv() => match message {
+                Some(Message::Entry(entry)) => {
+                    batch.push(entry);
+                    if batch.len() >= config.batch_size {
+                        write_batch(&db, &mut batch, &dropped).await;
+                    }
+                }
+                Some(Message::Flush(done)) => {
+                    write_batch(&db, &mut batch, &dropped).await;
+                    let _ = done.send(());
+                }
+                None => {
+                    write_batch(&db, &mut batch, &dropped).await;
+                    return;
+                }
+            },
+            _ = flush_timer.tick() => write_batch(&db, &mut batch, &dropped).await,
+            _ = prune_timer.tick() => prune(&db, &config).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::answer::AnswerSource;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_query_log() {
+        let db = Arc::new(Database::init_mem().await.unwrap());
+        let config = QueryLogConfig::default()
+            .with_max_rows(3)
+            .with_max_age(Duration::from_secs(60 * 60));
+        let query_log = QueryLog::start(db.clone(), config.clone());
+
+        for (i, source) in [AnswerSource::Local, AnswerSource::Cache, AnswerSource::Blocked]
+            .into_iter()
+            .enumerate()
+        {
+            query_log.record(QueryLogEntity::new(
+                "10.0.0.1".parse().unwrap(),
+                format!("{}.example.com", i),
+                1,
+                0,
+                Some(source),
+                Duration::from_micros(150),
+            ));
+        }
+        query_log.flush().await;
+
+        let entries = QueryLogEntity::_fetch_recent(db.get_pool(), db.query_log_tbl(), 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].qname(), "2.example.com");
+        assert_eq!(entries[0].answered_from(), Some("blocked"));
+        assert_eq!(entries[0].latency(), Duration::from_micros(150));
+
+        // one entry too many, and one that is too old
+        let old = chrono::Utc::now().naive_utc() - chrono::Duration::hours(2);
+        let entries = [
+            QueryLogEntity::new("10.0.0.2".parse().unwrap(), "old.example.com".to_string(), 1, 0, None, Duration::ZERO)
+                .with_timestamp(old),
+            QueryLogEntity::new("10.0.0.2".parse().unwrap(), "new.example.com".to_string(), 1, 2, None, Duration::ZERO),
+        ];
+        QueryLogEntity::_insert_batch(&entries, db.get_pool(), db.query_log_tbl()).await.unwrap();
+        prune(&db, &config).await;
+
+        let entries = QueryLogEntity::_fetch_recent(db.get_pool(), db.query_log_tbl(), 10)
+            .await
+            .unwrap();
+        let names: Vec<_> = entries.iter().map(|entry| entry.qname()).collect();
+        assert_eq!(names, vec!["new.example.com", "2.example.com", "1.example.com"]);
+
+        // an age limit beyond any representable date only leaves the row limit
+        prune(&db, &config.clone().with_max_age(Duration::MAX).with_max_rows(2)).await;
+        let entries = QueryLogEntity::_fetch_recent(db.get_pool(), db.query_log_tbl(), 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+}
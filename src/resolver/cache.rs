@@ -0,0 +1,433 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::protocol::{
+    answer::{self, AnswerEntry, AnswerKind, AnswerSource},
+    packet::{Question, RData, RecordType, ResourceRecord},
+};
+
+/*
+    Upper bound for how long an RRset is kept, regardless of its TTL
+*/
+const MAX_CACHE_TTL: u32 = 86400;
+
+/*
+    Upper bound for how long a negative answer is kept (RFC 2308, Section 5)
+*/
+const MAX_NEGATIVE_CACHE_TTL: u32 = 10800;
+
+/*
+    Maximum number of CNAMEs followed when answering from the cache
+*/
+const MAX_CNAME_CHAIN: usize = 8;
+
+/*
+    Type (reserved, never used by actual records) that NXDOMAIN answers are cached under,
+    as they hold for every type of the name (RFC 2308, Section 5)
+*/
+const NXDOMAIN_TYPE: u16 = 0;
+
+// owner name (lowercase), type, class
+type CacheKey = (String, u16, u16);
+
+struct CacheEntry {
+    // for negative answers, this is just the zone's SOA record
+    records: Vec<ResourceRecord>,
+    kind: AnswerKind,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    // last use -> key, the first entry is the least recently used one
+    usage: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.usage.remove(&entry.last_used);
+        }
+    }
+
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.usage.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.usage.insert(tick, key.clone());
+        }
+    }
+
+    /*
+        Returns the RRset with TTLs lowered by the time it spent in the cache
+    */
+    fn get(&mut self, key: &CacheKey, now: Instant) -> Option<(AnswerKind, Vec<ResourceRecord>)> {
+        let expires_at = self.entries.get(key)?.expires_at;
+        if expires_at <= now {
+            self.remove(key);
+            return None;
+        }
+
+        self.touch(key);
+        let ttl = (expires_at - now).as_secs() as u32;
+        let entry = &self.entries[key];
+        Some((
+            entry.kind,
+            entry
+                .records
+                .iter()
+                .map(|record| record.clone().with_ttl(ttl))
+                .collect(),
+        ))
+    }
+
+    fn store(
+        &mut self,
+        key: CacheKey,
+        kind: AnswerKind,
+        records: Vec<ResourceRecord>,
+        expires_at: Instant,
+        capacity: usize,
+    ) {
+        self.remove(&key);
+        while self.entries.len() >= capacity {
+            let Some((_, oldest)) = self.usage.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+
+        self.entries.insert(
+            key.clone(),
+            CacheEntry {
+                records,
+                kind,
+                expires_at,
+                last_used: 0,
+            },
+        );
+        self.touch(&key);
+    }
+}
+
+/*
+    In-memory cache for RRsets received from fallback servers, keyed by (name, type, class).
+    Holds at most capacity RRsets, evicting the least recently used one when full.
+*/
+pub struct ResponseCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        ResponseCache::new(10000)
+    }
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        ResponseCache {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    fn key(name: &str, rtype: u16, rclass: u16) -> CacheKey {
+        let name = name.strip_suffix('.').unwrap_or(name);
+        (name.to_lowercase(), rtype, rclass)
+    }
+
+    /*
+        Stores the RRsets answering the question, i.e. those of the queried name and of the
+        CNAME chain starting there. Records of any other name are dropped, an upstream server
+        mustn't get to plant answers for names it wasn't asked about.
+        An RRset lives as long as the smallest TTL within it.
+    */
+    pub fn insert(&self, question: &Question, records: &[ResourceRecord]) {
+        self.insert_at(&Self::answer_chain(question, records), Instant::now());
+    }
+
+    fn answer_chain(question: &Question, records: &[ResourceRecord]) -> Vec<ResourceRecord> {
+        let mut chain = Vec::new();
+        let mut visited = Vec::new();
+        let mut name = Self::key(&question.name(), 0, 0).0;
+
+        while visited.len() <= MAX_CNAME_CHAIN && !visited.contains(&name) {
+            let owned: Vec<&ResourceRecord> = records
+                .iter()
+                .filter(|record| {
+                    record.rclass() == question.qclass() && Self::key(&record.name(), 0, 0).0 == name
+                })
+                .collect();
+            chain.extend(owned.iter().map(|record| (*record).clone()));
+
+            let target = owned.iter().find_map(|record| match record.rdata() {
+                RData::CNAME(target) => Some(target),
+                _ => None,
+            });
+            visited.push(name);
+            match target {
+                Some(target) => name = Self::key(&target, 0, 0).0,
+                None => break,
+            }
+        }
+
+        chain
+    }
+
+    fn insert_at(&self, records: &[ResourceRecord], now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut rrsets: HashMap<CacheKey, Vec<ResourceRecord>> = HashMap::new();
+        for record in records {
+            let key = Self::key(&record.name(), record.rdata().rtype(), record.rclass());
+            rrsets.entry(key).or_default().push(record.clone());
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for (key, records) in rrsets {
+            let ttl = records.iter().map(|record| record.ttl()).min().unwrap_or(0);
+            if ttl == 0 {
+                continue;
+            }
+
+            let expires_at = now + Duration::from_secs(ttl.min(MAX_CACHE_TTL) as u64);
+            state.store(key, AnswerKind::Positive, records, expires_at, self.capacity);
+        }
+    }
+
+    /*
+        Remembers that there is no answer to the question, for as long as
+        the SOA from the authority section allows (RFC 2308, Section 5)
+    */
+    pub fn insert_negative(&self, question: &Question, kind: AnswerKind, soa: &ResourceRecord) {
+        self.insert_negative_at(question, kind, soa, Instant::now());
+    }
+
+    fn insert_negative_at(
+        &self,
+        question: &Question,
+        kind: AnswerKind,
+        soa: &ResourceRecord,
+        now: Instant,
+    ) {
+        let Some(ttl) = answer::negative_ttl(soa) else {
+            return;
+        };
+        if self.capacity == 0 || ttl == 0 {
+            return;
+        }
+
+        let rtype = match kind {
+            AnswerKind::NxDomain => NXDOMAIN_TYPE,
+            _ => question.qtype_code(),
+        };
+        let key = Self::key(&question.name(), rtype, question.qclass());
+        let expires_at = now + Duration::from_secs(ttl.min(MAX_NEGATIVE_CACHE_TTL) as u64);
+        self.state
+            .lock()
+            .unwrap()
+            .store(key, kind, vec![soa.clone()], expires_at, self.capacity);
+    }
+
+    /*
+        Answers the question from the cache, following CNAMEs within it
+    */
+    pub fn lookup(&self, question: &Question) -> Option<AnswerEntry> {
+        self.lookup_at(question, Instant::now())
+    }
+
+    fn lookup_at(&self, question: &Question, now: Instant) -> Option<AnswerEntry> {
+        let qtype = question.qtype_code();
+        // an ANY query can't be answered from whatever happens to be cached
+        if qtype == u16::from(RecordType::BROADCAST) {
+            return None;
+        }
+
+        let cname = u16::from(RecordType::CNAME);
+        let mut state = self.state.lock().unwrap();
+        let mut resources = Vec::new();
+        let mut name = question.name();
+
+        for _ in 0..=MAX_CNAME_CHAIN {
+            let negative = state.get(&Self::key(&name, NXDOMAIN_TYPE, question.qclass()), now);
+            if let Some((kind, records)) = state
+                .get(&Self::key(&name, qtype, question.qclass()), now)
+                .or(negative)
+            {
+                if kind != AnswerKind::Positive {
+                    return Some(AnswerEntry {
+                        resources,
+                        kind,
+                        authority: records.into_iter().next(),
+                        source: AnswerSource::Cache,
+                        ..Default::default()
+                    });
+                }
+
+                resources.extend(records);
+                return Some(AnswerEntry {
+                    resources,
+                    source: AnswerSource::Cache,
+                    ..Default::default()
+                });
+            }
+
+            if qtype == cname {
+                return None;
+            }
+
+            let (_, records) = state.get(&Self::key(&name, cname, question.qclass()), now)?;
+            let RData::CNAME(target) = records.first()?.rdata() else {
+                return None;
+            };
+            resources.extend(records);
+            name = target;
+        }
+
+        None
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn a_record(name: &str, ttl: u32) -> ResourceRecord {
+        ResourceRecord::default()
+            .with_name(name.to_string())
+            .with_rdata(RData::A(Ipv4Addr::new(10, 0, 0, 1)))
+            .with_ttl(ttl)
+    }
+
+    #[test]
+    fn test_response_cache() {
+        let cache = ResponseCache::new(2);
+        let now = Instant::now();
+        let question = |name: &str| {
+            Question::default()
+                .with_name(name.to_string())
+                .with_qtype(RecordType::A.into())
+        };
+
+        let cname = ResourceRecord::default()
+            .with_name("www.dns.is.tiny".to_string())
+            .with_rdata(RData::CNAME("dns.is.tiny".to_string()))
+            .with_ttl(300);
+        cache.insert_at(&[cname, a_record("dns.is.tiny", 60)], now);
+
+        // TTLs count down while the RRset is cached
+        let answer = cache
+            .lookup_at(&question("WWW.dns.is.tiny"), now + Duration::from_secs(20))
+            .unwrap();
+        assert_eq!(answer.resources.len(), 2);
+        assert_eq!(answer.resources[0].ttl(), 280);
+        assert_eq!(answer.resources[1].ttl(), 40);
+
+        // expired RRsets are evicted
+        assert!(cache
+            .lookup_at(&question("dns.is.tiny"), now + Duration::from_secs(60))
+            .is_none());
+        assert_eq!(cache.len(), 1);
+
+        // the least recently used RRset makes room for new ones
+        cache.insert_at(&[a_record("a.is.tiny", 60)], now);
+        cache.insert_at(&[a_record("b.is.tiny", 60)], now);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.lookup_at(&question("www.dns.is.tiny"), now).is_none());
+        assert!(cache.lookup_at(&question("a.is.tiny"), now).is_some());
+
+        // records without a TTL must not be cached at all
+        cache.insert_at(&[a_record("c.is.tiny", 0)], now);
+        assert!(cache.lookup_at(&question("c.is.tiny"), now).is_none());
+    }
+
+    #[test]
+    fn test_insert_answer_chain() {
+        let cache = ResponseCache::new(10);
+        let question = Question::default()
+            .with_name("www.dns.is.tiny".to_string())
+            .with_qtype(RecordType::A.into());
+
+        let cname = ResourceRecord::default()
+            .with_name("WWW.dns.is.tiny.".to_string())
+            .with_rdata(RData::CNAME("dns.is.tiny".to_string()))
+            .with_ttl(300);
+        cache.insert(
+            &question,
+            &[cname, a_record("dns.is.tiny", 60), a_record("bank.example.com", 60)],
+        );
+
+        // the chain starting at the queried name is cached, unrelated names aren't
+        assert_eq!(cache.lookup(&question).unwrap().resources.len(), 2);
+        assert_eq!(cache.len(), 2);
+        let unrelated = Question::default()
+            .with_name("bank.example.com".to_string())
+            .with_qtype(RecordType::A.into());
+        assert!(cache.lookup(&unrelated).is_none());
+    }
+
+    #[test]
+    fn test_negative_cache() {
+        let cache = ResponseCache::new(10);
+        let now = Instant::now();
+        let question = Question::default()
+            .with_name("missing.is.tiny".to_string())
+            .with_qtype(RecordType::A.into());
+
+        let soa = ResourceRecord::default()
+            .with_name("is.tiny".to_string())
+            .with_rdata(RData::SOA {
+                mname: "ns.is.tiny".to_string(),
+                rname: "admin.is.tiny".to_string(),
+                serial: 1,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 30,
+            })
+            .with_ttl(3600);
+        cache.insert_negative_at(&question, AnswerKind::NxDomain, &soa, now);
+
+        // negative answers live for the SOA's minimum TTL
+        let answer = cache
+            .lookup_at(&question, now + Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(answer.kind, AnswerKind::NxDomain);
+        assert!(answer.resources.is_empty());
+        assert_eq!(answer.authority.unwrap().ttl(), 20);
+
+        // a nonexistent name has no records of any type
+        let other_question = question.clone().with_qtype(RecordType::AAAA.into());
+        assert_eq!(cache.lookup_at(&other_question, now).unwrap().kind, AnswerKind::NxDomain);
+
+        assert!(cache
+            .lookup_at(&question, now + Duration::from_secs(30))
+            .is_none());
+    }
+}
@@ -1,3 +1,5 @@
-mod resolver;
-
-pub use resolver::Resolver;
+mod cache;
+mod resolver;
+
+pub use cache::ResponseCache;
+pub use resolver::Resolver;
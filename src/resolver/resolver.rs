@@ -1,113 +1,306 @@
-use crate::{
-    protocol::{
-        answer::AnswerEntry,
-        packet::{
-            flags::{Flags, HeaderFlags, OpCode, ResponseCode},
-            Packet, PacketBuilder, Question,
-        },
-    }, server::ServerConfig
-};
-
-#[derive(Default)]
-pub struct Resolver {
-    fallback_servers: Vec<(String, u16)>,
-}
-
-impl Resolver {
-    pub fn with_fallback_server(mut self, server: (String, u16)) -> Self {
-        self.fallback_servers.push(server);
-        self
-    }
-}
-
-impl Resolver {
-    /*
-        Queries fallback dns for answers
-        TODO: Handle multiple/truncated packets
-    */
-    async fn query_fallback(
-        &self,
-        packet: Packet,
-        fallback: (String, u16),
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let connection = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
-        connection.connect(fallback).await?;
-
-        connection.send(&packet.serialize()?).await?;
-
-        let mut buffer = [0; 512];
-        let size = connection.recv(&mut buffer).await?;
-        let response = &buffer[..size];
-
-        let proxied_packet = Packet::deserialize(response)?;
-        let flags: HeaderFlags = proxied_packet.header.flags.into();
-
-        let packet_builder = PacketBuilder::from_packet(proxied_packet.clone())
-            .with_flags(flags.without_flag(Flags::AA));
-
-        Ok(packet_builder.build().serialize()?)
-    }
-
-    /*
-        Intended for questions that should be delegated to fallback dns
-    */
-    pub async fn resolve_recursive(&self, questions: Vec<Question>) -> Vec<AnswerEntry> {
-        let query_packet = PacketBuilder::new()
-            .with_flags(
-                HeaderFlags::new()
-                    .with_opcode(OpCode::Query)
-                    .with_rcode(ResponseCode::NoError)
-                    .with_flag(Flags::RD),
-            )
-            // TODO: How should this be generated?
-            .with_id(0x0001)
-            .with_qentries(questions)
-            .build();
-
-        for fallback in self.fallback_servers.clone() {
-            let response = self.query_fallback(query_packet.clone(), fallback).await;
-            if !response.is_ok() {
-                continue;
-            }
-
-            // parse received packet
-            let packet = Packet::deserialize(&response.unwrap());
-            if !packet.is_ok() {
-                continue;
-            }
-
-            // it worked! return answers
-            // TODO: improve the way these answers are constructed
-            // (my inner monk won't let me sleep tonight for writing something this ugly/hacky)
-            let packet = packet.unwrap();
-            let mut answers = Vec::new();
-            for answer in packet.answers {
-                let mut answer_entry = AnswerEntry::default();
-                answer_entry.resource = Some(answer);
-                answers.push(answer_entry);
-            }
-
-            for authority in packet.authorities {
-                let mut auth_entry = AnswerEntry::default();
-                auth_entry.authority = Some(authority);
-                answers.push(auth_entry)
-            }
-
-            let mut additionals = AnswerEntry::default();
-            additionals.additional = packet.additionals;
-            answers.push(additionals);
-
-            return answers;
-        }
-
-        Vec::new()
-    }
-
-    /*
-        Tries to retrieve zone authority
-    */
-    pub async fn get_zoneauthority<'a>(&self, zone: String, config: &ServerConfig<'a>) -> AnswerEntry {
-        // TODO: resolve zone authority
-        AnswerEntry::default()
-    }
-}
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::{DnsError, UpstreamError},
+    metrics::Metrics,
+    protocol::{
+        answer::{AnswerEntry, AnswerKind, AnswerSource},
+        packet::{
+            flags::{Flags, HeaderFlags, OpCode, ResponseCode},
+            Edns, Packet, PacketBuilder, Question, RData, EDNS_DEFAULT_PAYLOAD_SIZE,
+        },
+    }, server::ServerConfig
+};
+
+use super::ResponseCache;
+
+/*
+    Time to wait for a fallback server to respond before trying the next one
+*/
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Default)]
+pub struct Resolver {
+    // replaced as a whole whenever the servers change
+    fallback_servers: RwLock<Vec<(String, u16)>>,
+    cache: ResponseCache,
+    metrics: Option<Arc<Metrics>>,
+}
+
+/*
+    Whether a response is about the questions of the query, names compared case-insensitively
+*/
+fn same_questions(asked: &[Question], answered: &[Question]) -> bool {
+    asked.len() == answered.len()
+        && asked.iter().zip(answered).all(|(asked, answered)| {
+            asked.name().trim_end_matches('.').eq_ignore_ascii_case(answered.name().trim_end_matches('.'))
+                && asked.qtype_code() == answered.qtype_code()
+                && asked.qclass() == answered.qclass()
+        })
+}
+
+impl Resolver {
+    pub fn with_fallback_server(mut self, server: (String, u16)) -> Self {
+        self.fallback_servers.get_mut().unwrap().push(server);
+        self
+    }
+
+    pub fn set_fallback_servers(&self, servers: Vec<(String, u16)>) {
+        *self.fallback_servers.write().unwrap() = servers;
+    }
+
+    pub fn fallback_servers(&self) -> Vec<(String, u16)> {
+        self.fallback_servers.read().unwrap().clone()
+    }
+
+    /*
+        Maximum number of RRsets kept in the response cache, zero disables caching
+    */
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache = ResponseCache::new(capacity);
+        self
+    }
+
+    /*
+        Records the latency of fallback servers
+    */
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn cache(&self) -> &ResponseCache {
+        &self.cache
+    }
+}
+
+impl Resolver {
+    /*
+        Queries fallback dns for answers, under a random query id. Datagrams that don't carry
+        that id and the questions asked are ignored, as they may well be spoofed.
+        TODO: Retry truncated responses over TCP
+    */
+    async fn query_fallback(
+        &self,
+        mut packet: Packet,
+        fallback: (String, u16),
+    ) -> Result<Packet, DnsError> {
+        packet.header.id = rand::random();
+
+        let connection = tokio::net::UdpSocket::bind("0.0.0.0:0")
+            .await
+            .map_err(UpstreamError::Io)?;
+        connection.connect(fallback).await.map_err(UpstreamError::Io)?;
+
+        connection.send(&packet.serialize()?).await.map_err(UpstreamError::Io)?;
+
+        let deadline = tokio::time::Instant::now() + FALLBACK_TIMEOUT;
+        let mut buffer = [0; EDNS_DEFAULT_PAYLOAD_SIZE as usize];
+        let proxied_packet = loop {
+            let size = tokio::time::timeout_at(deadline, connection.recv(&mut buffer))
+                .await
+                .map_err(|_| UpstreamError::Timeout)?
+                .map_err(UpstreamError::Io)?;
+            let response = &buffer[..size];
+
+            if size < 2 || u16::from_be_bytes([response[0], response[1]]) != packet.header.id {
+                log::warn!("Ignoring upstream response with an unexpected query id");
+                continue;
+            }
+
+            let response = Packet::deserialize(response)
+                .map_err(|err| UpstreamError::InvalidResponse(Box::new(err)))?;
+            if !same_questions(&packet.questions, &response.questions) {
+                log::warn!("Ignoring upstream response to questions that weren't asked");
+                continue;
+            }
+            break response;
+        };
+        let flags: HeaderFlags = proxied_packet.header.flags.into();
+
+        // the fallback server failing us is no answer the client should get to see
+        if matches!(
+            flags.2,
+            ResponseCode::ServerFailure | ResponseCode::Refused | ResponseCode::NotImplemented
+        ) {
+            return Err(UpstreamError::Rcode(flags.2).into());
+        }
+
+        let packet_builder = PacketBuilder::from_packet(proxied_packet.clone())
+            .with_flags(flags.without_flag(Flags::AA));
+
+        Ok(packet_builder.build())
+    }
+
+    /*
+        Intended for questions that should be delegated to fallback dns
+    */
+    pub async fn resolve_recursive(&self, questions: Vec<Question>) -> Result<Vec<AnswerEntry>, DnsError> {
+        let query_packet = PacketBuilder::new()
+            .with_flags(
+                HeaderFlags::new()
+                    .with_opcode(OpCode::Query)
+                    .with_rcode(ResponseCode::NoError)
+                    .with_flag(Flags::RD),
+            )
+            .with_qentries(questions.clone())
+            // advertise a larger buffer so that upstream answers aren't needlessly truncated
+            .with_edns(Edns::new(EDNS_DEFAULT_PAYLOAD_SIZE))
+            .build();
+
+        let mut last_error = DnsError::Upstream(UpstreamError::NoServers);
+        for fallback in self.fallback_servers() {
+            let started = Instant::now();
+            let result = self.query_fallback(query_packet.clone(), fallback.clone()).await;
+            if let Some(metrics) = &self.metrics {
+                match &result {
+                    Ok(_) => metrics.record_upstream(&fallback, started.elapsed()),
+                    Err(_) => metrics.record_upstream_error(&fallback),
+                }
+            }
+
+            let packet = match result {
+                Ok(packet) => packet,
+                Err(err) => {
+                    log::warn!("Fallback server {}:{} failed: {}", fallback.0, fallback.1, err);
+                    last_error = err;
+                    continue;
+                }
+            };
+
+            let kind = match packet.rcode() {
+                ResponseCode::NameError => AnswerKind::NxDomain,
+                _ if packet.answers.is_empty() => AnswerKind::NoData,
+                _ => AnswerKind::Positive,
+            };
+
+            // a truncated response may lack records of the RRsets it answers with
+            let truncated = HeaderFlags::from(packet.header.flags).1 & Flags::TC as u16 != 0;
+            if !truncated {
+                for question in &questions {
+                    self.cache.insert(question, &packet.answers);
+                }
+            }
+            // a negative answer can only be attributed to a question if there was just one
+            if let [question] = questions.as_slice() {
+                if !truncated && packet.answers.is_empty() && kind != AnswerKind::Positive {
+                    if let Some(soa) = packet
+                        .authorities
+                        .iter()
+                        .find(|record| matches!(record.rdata(), RData::SOA { .. }))
+                    {
+                        self.cache.insert_negative(question, kind, soa);
+                    }
+                }
+            }
+
+            // it worked! return answers
+            // TODO: improve the way these answers are constructed
+            // (my inner monk won't let me sleep tonight for writing something this ugly/hacky)
+            let mut answers = vec![AnswerEntry {
+                resources: packet.answers,
+                kind,
+                source: AnswerSource::Upstream,
+                truncated,
+                ..Default::default()
+            }];
+
+            for authority in packet.authorities {
+                answers.push(AnswerEntry {
+                    authority: Some(authority),
+                    source: AnswerSource::Upstream,
+                    ..Default::default()
+                });
+            }
+
+            answers.push(AnswerEntry {
+                additional: packet.additionals,
+                source: AnswerSource::Upstream,
+                ..Default::default()
+            });
+
+            return Ok(answers);
+        }
+
+        Err(last_error)
+    }
+
+    /*
+        Tries to retrieve zone authority
+    */
+    pub async fn get_zoneauthority(&self, _zone: String, _config: &ServerConfig) -> AnswerEntry {
+        // TODO: resolve zone authority
+        AnswerEntry::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::protocol::packet::{RecordType, ResourceRecord};
+
+    use super::*;
+
+    fn a_record(name: &str) -> ResourceRecord {
+        ResourceRecord::default()
+            .with_name(name.to_string())
+            .with_rdata(RData::A(Ipv4Addr::new(192, 0, 2, 1)))
+            .with_ttl(300)
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_and_truncated_responses() {
+        let upstream = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let resolver = Resolver::default()
+            .with_fallback_server(("127.0.0.1".to_string(), upstream.local_addr().unwrap().port()));
+        let question = |name: &str| {
+            Question::default()
+                .with_name(name.to_string())
+                .with_qtype(RecordType::A.into())
+        };
+
+        let fake_upstream = async {
+            let mut buf = [0u8; 512];
+            for truncated in [false, true] {
+                let (size, client) = upstream.recv_from(&mut buf).await.unwrap();
+                let query = Packet::deserialize(&buf[..size]).unwrap();
+                let response = |id: u16, questions: Vec<Question>, flags: HeaderFlags| {
+                    PacketBuilder::new()
+                        .with_id(id)
+                        .with_flags(flags.with_flag(Flags::QR))
+                        .with_qentries(questions)
+                        .with_aentries(vec![a_record(&query.questions[0].name()), a_record("bank.example.com")])
+                        .build()
+                        .serialize()
+                        .unwrap()
+                };
+
+                // wrong id, then the right id for another question, neither must be accepted
+                let spoofed = response(query.header.id.wrapping_add(1), query.questions.clone(), HeaderFlags::new());
+                upstream.send_to(&spoofed, client).await.unwrap();
+                let spoofed = response(query.header.id, vec![question("bank.example.com")], HeaderFlags::new());
+                upstream.send_to(&spoofed, client).await.unwrap();
+
+                let flags = if truncated { HeaderFlags::new().with_flag(Flags::TC) } else { HeaderFlags::new() };
+                let genuine = response(query.header.id, query.questions.clone(), flags);
+                upstream.send_to(&genuine, client).await.unwrap();
+            }
+        };
+
+        let lookups = async {
+            let answers = resolver.resolve_recursive(vec![question("dns.is.tiny")]).await.unwrap();
+            assert_eq!(answers[0].resources.len(), 2);
+            resolver.resolve_recursive(vec![question("big.is.tiny")]).await.unwrap();
+        };
+        tokio::join!(fake_upstream, lookups);
+
+        // only the queried name made it into the cache, and nothing of the truncated response
+        assert!(resolver.cache().lookup(&question("dns.is.tiny")).is_some());
+        assert!(resolver.cache().lookup(&question("bank.example.com")).is_none());
+        assert!(resolver.cache().lookup(&question("big.is.tiny")).is_none());
+    }
+}
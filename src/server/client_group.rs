@@ -0,0 +1,207 @@
+use std::{net::IpAddr, sync::Arc};
+
+use crate::{error::RuleError, filter::Filter, resolver::Resolver};
+
+/*
+    Identifies the clients belonging to a group
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMatcher {
+    Ip(IpAddr),
+    // network address and prefix length
+    Cidr(IpAddr, u8),
+    // IPv6 addresses whose interface identifier was derived from this MAC (EUI-64, RFC 4291 Appendix A)
+    Mac([u8; 6]),
+}
+
+impl ClientMatcher {
+    pub fn matches(&self, client: IpAddr) -> bool {
+        // clients of dual stack sockets show up as IPv4-mapped IPv6 addresses
+        let client = client.to_canonical();
+        match self {
+            ClientMatcher::Ip(ip) => ip.to_canonical() == client,
+            ClientMatcher::Cidr(network, prefix) => match (network.to_canonical(), client) {
+                (IpAddr::V4(network), IpAddr::V4(client)) => {
+                    let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(client) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(client)) => {
+                    let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(client) & mask
+                }
+                _ => false,
+            },
+            ClientMatcher::Mac(mac) => match client {
+                IpAddr::V6(client) => client.octets()[8..] == eui64_interface_id(mac),
+                IpAddr::V4(_) => false,
+            },
+        }
+    }
+}
+
+/*
+    Interface identifier a host autoconfigures from its MAC: the universal/local bit
+    is flipped and ff:fe is inserted between the two halves
+*/
+fn eui64_interface_id(mac: &[u8; 6]) -> [u8; 8] {
+    [mac[0] ^ 0x02, mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+}
+
+fn parse_mac(s: &str) -> Option<[u8; 6]> {
+    let bytes: Vec<u8> = s
+        .split([':', '-'])
+        .map(|byte| u8::from_str_radix(byte, 16).ok().filter(|_| byte.len() == 2))
+        .collect::<Option<_>>()?;
+    bytes.try_into().ok()
+}
+
+impl std::str::FromStr for ClientMatcher {
+    type Err = RuleError;
+
+    /*
+        Accepts "192.168.1.10", "192.168.1.0/24", "fd00::/8" and "aa:bb:cc:dd:ee:ff"
+    */
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(ip) = s.parse::<IpAddr>() {
+            return Ok(ClientMatcher::Ip(ip));
+        }
+
+        if let Some((network, prefix)) = s.split_once('/') {
+            let network: IpAddr = network
+                .parse()
+                .map_err(|_| RuleError(format!("invalid network {}", s)))?;
+            let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+            return match prefix.parse::<u8>() {
+                Ok(prefix) if prefix <= max_prefix => Ok(ClientMatcher::Cidr(network, prefix)),
+                _ => Err(RuleError(format!("invalid prefix length in {}", s))),
+            };
+        }
+
+        parse_mac(s)
+            .map(ClientMatcher::Mac)
+            .ok_or_else(|| RuleError(format!("invalid client {}", s)))
+    }
+}
+
+/*
+    A set of clients sharing their own filtering policy and upstream resolvers.
+    Whatever a group doesn't configure is taken from the server's defaults.
+*/
+pub struct ClientGroup {
+    name: String,
+    clients: Vec<ClientMatcher>,
+    filter: Option<Filter>,
+    // shared with the group replacing this one on reload, as long as its servers stay the same
+    resolver: Option<Arc<Resolver>>,
+}
+
+impl ClientGroup {
+    pub fn new(name: &str) -> Self {
+        ClientGroup {
+            name: name.to_string(),
+            clients: Vec::new(),
+            filter: None,
+            resolver: None,
+        }
+    }
+
+    pub fn with_client(mut self, client: ClientMatcher) -> Self {
+        self.clients.push(client);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_resolver(mut self, resolver: Arc<Resolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    pub fn contains(&self, client: IpAddr) -> bool {
+        self.clients.iter().any(|matcher| matcher.matches(client))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn clients(&self) -> &[ClientMatcher] {
+        &self.clients
+    }
+
+    pub fn filter(&self) -> Option<&Filter> {
+        self.filter.as_ref()
+    }
+
+    pub fn resolver(&self) -> Option<&Arc<Resolver>> {
+        self.resolver.as_ref()
+    }
+}
+
+/*
+    Filter and resolver a single query is answered with
+*/
+pub struct Policy<'a> {
+    pub client: IpAddr,
+    // None for clients that aren't part of any group
+    pub group: Option<Arc<ClientGroup>>,
+    // used for whatever the group doesn't configure
+    pub default_filter: &'a Filter,
+    pub default_resolver: &'a Resolver,
+}
+
+impl Policy<'_> {
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref().map(ClientGroup::name)
+    }
+
+    pub fn filter(&self) -> &Filter {
+        self.group
+            .as_deref()
+            .and_then(ClientGroup::filter)
+            .unwrap_or(self.default_filter)
+    }
+
+    pub fn resolver(&self) -> &Resolver {
+        self.group
+            .as_deref()
+            .and_then(ClientGroup::resolver)
+            .map(Arc::as_ref)
+            .unwrap_or(self.default_resolver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_matcher() {
+        let ip: ClientMatcher = "192.168.1.10".parse().unwrap();
+        assert!(ip.matches("192.168.1.10".parse().unwrap()));
+        assert!(ip.matches("::ffff:192.168.1.10".parse().unwrap()));
+        assert!(!ip.matches("192.168.1.11".parse().unwrap()));
+
+        let cidr: ClientMatcher = "192.168.1.0/24".parse().unwrap();
+        assert!(cidr.matches("192.168.1.200".parse().unwrap()));
+        assert!(!cidr.matches("192.168.2.1".parse().unwrap()));
+        let everything: ClientMatcher = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.matches("10.0.0.1".parse().unwrap()));
+        let cidr: ClientMatcher = "fd00::/8".parse().unwrap();
+        assert!(cidr.matches("fd12::1".parse().unwrap()));
+        assert!(!cidr.matches("fe80::1".parse().unwrap()));
+
+        let mac: ClientMatcher = "00:1a:2b:3c:4d:5e".parse().unwrap();
+        assert!(mac.matches("fe80::21a:2bff:fe3c:4d5e".parse().unwrap()));
+        assert!(mac.matches("2001:db8::21a:2bff:fe3c:4d5e".parse().unwrap()));
+        assert!(!mac.matches("fe80::1".parse().unwrap()));
+
+        assert!("192.168.1.0/33".parse::<ClientMatcher>().is_err());
+        assert!("00:1a:2b:3c:4d".parse::<ClientMatcher>().is_err());
+        assert!("laptop".parse::<ClientMatcher>().is_err());
+    }
+}
@@ -1,157 +1,301 @@
-use crate::protocol::{
-    answer::AnswerEntry,
-    packet::{
-        flags::{Flags, HeaderFlags, ResponseCode},
-        Packet, PacketBuilder, Question, ResourceRecord,
-    },
-    util,
-};
-
-use super::ServerConfig;
-
-/*
-    Answer single question or return authority for iterative querying
-*/
-pub async fn answer_question<'a>(question: Question, config: &ServerConfig<'a>) -> AnswerEntry {
-    if let Some(nameserver) = config.nameserver() {
-        if let Some(answer) = nameserver.try_answer(question.clone()).await {
-            return answer;
-        }
-    }
-
-    config
-        .resolver()
-        .get_zoneauthority(util::get_upzone(question.name()), config)
-        .await
-}
-
-/*
-    Batch-answer questions. Recursion should be desired.
-*/
-pub async fn answer_batch<'a>(
-    questions: Vec<Question>,
-    config: &ServerConfig<'a>,
-) -> Vec<AnswerEntry> {
-    let mut delegated_questions = Vec::new();
-    let mut answers = Vec::new();
-
-    if let Some(nameserver) = config.nameserver() {
-        // Resolve all locally answerable questions using our nameserver, delegate the rest
-        for question in questions.clone() {
-            if let Some(answer) = nameserver.try_answer(question.clone()).await {
-                answers.push(answer);
-            } else {
-                delegated_questions.push(question);
-            }
-        }
-    } else {
-        // No nameserver configured, delegate ALL questions
-        log::trace!(
-            "Resolving {} question:s recursively",
-            delegated_questions.len()
-        );
-        answers.extend(config.resolver().resolve_recursive(questions).await);
-        return answers;
-    }
-
-    if !delegated_questions.is_empty() {
-        answers.extend(
-            config
-                .resolver()
-                .resolve_recursive(delegated_questions)
-                .await,
-        );
-    }
-
-    log::info!("Resolved {} questions", questions.len());
-    answers
-}
-
-pub async fn handle_packet<'a>(
-    packet: Packet,
-    config: &ServerConfig<'a>,
-) -> Result<Packet, Box<dyn std::error::Error>> {
-    let questions = packet.clone().questions;
-    let mut authoritive = true;
-    let mut authorities: Vec<ResourceRecord> = Vec::new();
-    let mut answer_records: Vec<ResourceRecord> = Vec::new();
-    let mut additional_records: Vec<ResourceRecord> = Vec::new();
-    let recursion_desired =
-        HeaderFlags::from(packet.header.flags).1 & (Flags::RD as u16) == (Flags::RD as u16);
-
-    // TODO: RFC 2308, Section 2.2 Compliance: (Case: No Data / Record Entry doesn't exist) -> Return SOA
-    log::trace!("Handling {} question:s", questions.len());
-    if recursion_desired {
-        let answers = answer_batch(questions.clone(), config).await;
-        for answer in answers {
-            authoritive = authoritive && answer.authoritive;
-
-            // add authorities that can answer the question
-            if let Some(aauth) = answer.authority {
-                if !authorities.contains(&aauth) {
-                    authorities.push(aauth);
-                }
-            }
-
-            // add additional records useful to the client
-            for additional in answer.additional {
-                if !additional_records.contains(&additional) {
-                    additional_records.push(additional);
-                }
-            }
-
-            if let Some(record) = answer.resource {
-                // TODO: should duplicate answer records also be removed?
-                answer_records.push(record);
-            }
-        }
-    } else {
-        // answer on per-question basis
-        for question in questions.clone() {
-            let answer = answer_question(question.clone(), config).await;
-            authoritive = authoritive && answer.authoritive;
-
-            // add authorities that can answer the question
-            if let Some(aauth) = answer.authority {
-                if !authorities.contains(&aauth) {
-                    authorities.push(aauth);
-                }
-            }
-
-            // add additional records useful to the client
-            for additional in answer.additional {
-                if !additional_records.contains(&additional) {
-                    additional_records.push(additional);
-                }
-            }
-
-            if let Some(record) = answer.resource {
-                // TODO: should duplicate answer records also be removed?
-                answer_records.push(record);
-            }
-        }
-    }
-
-    if answer_records.len() > 0 || authorities.len() > 0 {
-        let header_flags = HeaderFlags::new()
-            .with_opcode(HeaderFlags::from(packet.header.flags).0)
-            .with_rcode(ResponseCode::NoError)
-            .with_flag(Flags::QR)
-            .with_flag(Flags::RA);
-
-        Ok(PacketBuilder::new()
-            .with_flags(if authoritive {
-                header_flags.with_flag(Flags::AA)
-            } else {
-                header_flags
-            })
-            .with_id(packet.header.id)
-            .with_qentries(questions)
-            .with_aentries(answer_records)
-            .with_authentries(authorities)
-            .with_addentries(additional_records)
-            .build())
-    } else {
-        Err("No questions could be answered.".into())
-    }
-}
+use crate::{
+    filter::{BlockMatch, BlockMode, BlockSource, Verdict},
+    error::{DnsError, UpstreamError},
+    protocol::{
+        answer::{AnswerEntry, AnswerKind, AnswerSource},
+        packet::{
+            flags::{Flags, HeaderFlags, OpCode, ResponseCode},
+            Edns, ExtendedError, Packet, PacketBuilder, Question, ResourceRecord,
+        },
+        util,
+    },
+};
+
+use super::{Policy, ServerConfig};
+
+/*
+    Answer single question or return authority for iterative querying
+*/
+pub async fn answer_question(
+    question: Question,
+    config: &ServerConfig,
+) -> Result<AnswerEntry, DnsError> {
+    if let Some(nameserver) = config.nameserver() {
+        if let Some(answer) = nameserver.try_answer(question.clone()).await? {
+            return Ok(answer);
+        }
+    }
+
+    Ok(config
+        .resolver()
+        .get_zoneauthority(util::get_upzone(question.name()), config)
+        .await)
+}
+
+/*
+    Delegates questions to the resolver. Having no fallback servers
+    configured just means there's nothing to delegate to.
+*/
+async fn delegate(
+    questions: Vec<Question>,
+    policy: &Policy<'_>,
+) -> Result<Vec<AnswerEntry>, DnsError> {
+    match policy.resolver().resolve_recursive(questions).await {
+        Err(DnsError::Upstream(UpstreamError::NoServers)) => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+/*
+    Batch-answer questions. Recursion should be desired.
+*/
+pub async fn answer_batch(
+    questions: Vec<Question>,
+    config: &ServerConfig,
+    policy: &Policy<'_>,
+) -> Result<Vec<AnswerEntry>, DnsError> {
+    let mut delegated_questions = Vec::new();
+    let mut answers = Vec::new();
+
+    if let Some(nameserver) = config.nameserver() {
+        // Resolve all locally answerable questions using our nameserver, delegate the rest
+        for question in questions.clone() {
+            if let Some(answer) = nameserver.try_answer(question.clone()).await? {
+                answers.push(answer);
+            } else {
+                delegated_questions.push(question);
+            }
+        }
+    } else {
+        // No nameserver configured, delegate ALL questions
+        log::trace!(
+            "Resolving {} question:s recursively",
+            questions.len()
+        );
+        delegated_questions = questions.clone();
+    }
+
+    // answer whatever we can from the cache, delegate the rest
+    let mut uncached_questions = Vec::new();
+    for question in delegated_questions {
+        let cached = policy.resolver().cache().lookup(&question);
+        if let Some(metrics) = config.metrics() {
+            metrics.record_cache_lookup(cached.is_some());
+        }
+        match cached {
+            Some(answer) => answers.push(answer),
+            None => uncached_questions.push(question),
+        }
+    }
+
+    if !uncached_questions.is_empty() {
+        answers.extend(delegate(uncached_questions, policy).await?);
+    }
+
+    log::info!("Resolved {} questions", questions.len());
+    Ok(answers)
+}
+
+/*
+    Response to a query that failed as a whole, carrying nothing but its questions
+*/
+pub fn error_response(packet: &Packet, err: &DnsError) -> PacketBuilder {
+    PacketBuilder::new()
+        .with_flags(
+            HeaderFlags::new()
+                .with_opcode(HeaderFlags::from(packet.header.flags).0)
+                .with_rcode(err.response_code())
+                .with_flag(Flags::QR)
+                .with_flag(Flags::RA),
+        )
+        .with_id(packet.header.id)
+        .with_qentries(packet.questions.clone())
+}
+
+/*
+    Answers a query for a blocked domain according to the block mode of the matching rule.
+    The Extended DNS Error only reaches clients that sent an OPT record themselves. Blocklists
+    are the operator's policy (Blocked), rules are set up for the clients' sake (Filtered).
+*/
+fn block_response(packet: &Packet, matched: &BlockMatch) -> Packet {
+    let (error, text) = match matched.source {
+        BlockSource::Blocklist => (
+            ExtendedError::Blocked,
+            format!("{} is on blocklist {}", matched.rule, matched.list),
+        ),
+        BlockSource::Rule => (
+            ExtendedError::Filtered,
+            format!("{} is blocked by {}", matched.rule, matched.list),
+        ),
+    };
+
+    let response = if matched.mode == BlockMode::Refused {
+        error_response(packet, &DnsError::Refused(text.clone()))
+    } else {
+        let answers = packet
+            .questions
+            .iter()
+            .filter_map(|question| matched.mode.answer(question))
+            .collect();
+
+        PacketBuilder::new()
+            .with_flags(
+                HeaderFlags::new()
+                    .with_opcode(HeaderFlags::from(packet.header.flags).0)
+                    .with_rcode(matched.mode.rcode())
+                    .with_flag(Flags::QR)
+                    .with_flag(Flags::RA),
+            )
+            .with_id(packet.header.id)
+            .with_qentries(packet.questions.clone())
+            .with_aentries(answers)
+    };
+
+    response
+        .with_edns(Edns::default().with_extended_error(error, &text))
+        .build()
+}
+
+/*
+    Answers a query, along with where the answer came from
+*/
+pub async fn handle_packet(
+    packet: Packet,
+    config: &ServerConfig,
+    policy: &Policy<'_>,
+) -> Result<(Packet, AnswerSource), DnsError> {
+    let opcode = HeaderFlags::from(packet.header.flags).0;
+    if !matches!(opcode, OpCode::Query) {
+        return Err(DnsError::NotImplemented(format!("opcode {:?}", opcode)));
+    }
+
+    if let Some(edns) = &packet.edns {
+        // we only speak EDNS(0) (RFC 6891, Section 6.1.3)
+        if edns.version != 0 {
+            return Err(DnsError::BadVersion(edns.version));
+        }
+    }
+
+    // blocked domains never reach the nameserver or the resolver
+    for question in &packet.questions {
+        match policy.filter().check(&question.name()) {
+            Some(Verdict::Blocked(matched)) => {
+                log::info!(
+                    "Blocked query for {} (matched {} on {})",
+                    question.name(),
+                    matched.rule,
+                    matched.list
+                );
+                return Ok((block_response(&packet, &matched), AnswerSource::Blocked));
+            }
+            Some(Verdict::Allowed(rule)) => {
+                log::debug!("Allowed query for {} ({})", question.name(), rule);
+            }
+            None => {}
+        }
+    }
+
+    let questions = packet.clone().questions;
+    let mut authoritive = true;
+    let mut authorities: Vec<ResourceRecord> = Vec::new();
+    let mut answer_records: Vec<ResourceRecord> = Vec::new();
+    let mut additional_records: Vec<ResourceRecord> = Vec::new();
+    // set if there's a definite answer that no (matching) records exist
+    let mut negative = false;
+    let mut name_error = false;
+    // passed on from upstream, so that the client retries over TCP
+    let mut truncated = false;
+    let mut source = AnswerSource::Local;
+    let recursion_desired =
+        HeaderFlags::from(packet.header.flags).1 & (Flags::RD as u16) == (Flags::RD as u16);
+
+    log::trace!("Handling {} question:s", questions.len());
+    if recursion_desired {
+        let answers = answer_batch(questions.clone(), config, policy).await?;
+        for answer in answers {
+            authoritive = authoritive && answer.authoritive;
+            negative = negative || answer.kind != AnswerKind::Positive;
+            name_error = name_error || answer.kind == AnswerKind::NxDomain;
+            truncated = truncated || answer.truncated;
+            source = source.max(answer.source);
+
+            // add authorities that can answer the question
+            if let Some(aauth) = answer.authority {
+                if !authorities.contains(&aauth) {
+                    authorities.push(aauth);
+                }
+            }
+
+            // add additional records useful to the client
+            for additional in answer.additional {
+                if !additional_records.contains(&additional) {
+                    additional_records.push(additional);
+                }
+            }
+
+            // TODO: should duplicate answer records also be removed?
+            answer_records.extend(answer.resources);
+        }
+    } else {
+        // answer on per-question basis
+        for question in questions.clone() {
+            let answer = answer_question(question.clone(), config).await?;
+            authoritive = authoritive && answer.authoritive;
+            negative = negative || answer.kind != AnswerKind::Positive;
+            name_error = name_error || answer.kind == AnswerKind::NxDomain;
+            truncated = truncated || answer.truncated;
+            source = source.max(answer.source);
+
+            // add authorities that can answer the question
+            if let Some(aauth) = answer.authority {
+                if !authorities.contains(&aauth) {
+                    authorities.push(aauth);
+                }
+            }
+
+            // add additional records useful to the client
+            for additional in answer.additional {
+                if !additional_records.contains(&additional) {
+                    additional_records.push(additional);
+                }
+            }
+
+            // TODO: should duplicate answer records also be removed?
+            answer_records.extend(answer.resources);
+        }
+    }
+
+    if !answer_records.is_empty() || !authorities.is_empty() || negative {
+        // a CNAME pointing nowhere is still a name error (RFC 6604, Section 2)
+        let rcode = if name_error {
+            ResponseCode::NameError
+        } else {
+            ResponseCode::NoError
+        };
+        let mut header_flags = HeaderFlags::new()
+            .with_opcode(HeaderFlags::from(packet.header.flags).0)
+            .with_rcode(rcode)
+            .with_flag(Flags::QR)
+            .with_flag(Flags::RA);
+        if truncated {
+            header_flags = header_flags.with_flag(Flags::TC);
+        }
+
+        let response = PacketBuilder::new()
+            .with_flags(if authoritive {
+                header_flags.with_flag(Flags::AA)
+            } else {
+                header_flags
+            })
+            .with_id(packet.header.id)
+            .with_qentries(questions)
+            .with_aentries(answer_records)
+            .with_authentries(authorities)
+            .with_addentries(additional_records)
+            .build();
+
+        Ok((response, source))
+    } else {
+        Err(DnsError::NotFound)
+    }
+}
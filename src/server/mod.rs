@@ -1,5 +1,7 @@
-mod handle_packet;
-pub mod serve;
-mod server_config;
-
-pub use server_config::ServerConfig;
+mod client_group;
+mod handle_packet;
+pub mod serve;
+mod server_config;
+
+pub use client_group::{ClientGroup, ClientMatcher, Policy};
+pub use server_config::{Backpressure, ServerConfig};
@@ -1,153 +1,707 @@
-use std::net::SocketAddr;
-
-use crate::{
-    protocol::packet::{
-        flags::{Flags, HeaderFlags, OpCode, ResponseCode},
-        Packet, PacketBuilder, PacketHeader,
-    },
-    server::handle_packet::handle_packet,
-};
-
-use super::ServerConfig;
-
-pub async fn send_packet(
-    server: &tokio::net::UdpSocket,
-    client: SocketAddr,
-    packet: Packet,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let response = packet.serialize();
-
-    match response {
-        Ok(data) => {
-            if let Err(e) = server.send_to(&data, client).await {
-                log::error!("Failed to send response: {}", e);
-            }
-        }
-        Err(e) => log::error!("Failed to serialize response packet: {}", e),
-    }
-
-    Ok(())
-}
-
-pub async fn serve_udp<'a>(config: &ServerConfig<'a>) -> Result<(), Box<dyn std::error::Error>> {
-    let server =
-        tokio::net::UdpSocket::bind(format!("{}:{}", config.listen_addr(), config.udp_port()))
-            .await?;
-
-    loop {
-        let mut buf: [u8; 512] = [0; 512];
-
-        if let Ok((size, client)) = server.recv_from(&mut buf).await {
-            let data = &buf[..size];
-            log::trace!(
-                "Received {} bytes from {}:{}",
-                size,
-                client.ip(),
-                client.port()
-            );
-
-            // not even a query id was sent that could
-            // be used to return a meaningful error
-            if data.len() < 2 {
-                continue;
-            }
-
-            let packet_deserialized = Packet::deserialize(data);
-
-            // couldn't parse received packet
-            if !packet_deserialized.is_ok() {
-                log::warn!("Received malformed packet. Trying to reconstruct and answer.");
-                if let Ok(header) = PacketHeader::deserialize(data) {
-                    // try and preserve header
-                    let header_flags: HeaderFlags = header.flags.into();
-                    let _ = send_packet(
-                        &server,
-                        client,
-                        PacketBuilder::new()
-                            .with_flags(
-                                HeaderFlags::new()
-                                    .with_opcode(header_flags.0)
-                                    .with_rcode(ResponseCode::FormatError)
-                                    .with_flag(Flags::QR)
-                                    .with_flag(Flags::RA),
-                            )
-                            .with_id(header.id)
-                            .build(),
-                    )
-                    .await;
-                } else {
-                    // fallback to only query ID
-                    let query_id = u16::from_be_bytes([data[0], data[1]]);
-                    let _ = send_packet(
-                        &server,
-                        client,
-                        PacketBuilder::new()
-                            .with_flags(
-                                HeaderFlags::new()
-                                    .with_opcode(OpCode::Query)
-                                    .with_rcode(ResponseCode::FormatError)
-                                    .with_flag(Flags::QR)
-                                    .with_flag(Flags::RA),
-                            )
-                            .with_id(query_id)
-                            .build(),
-                    )
-                    .await;
-                }
-                continue;
-            }
-            let packet_deserialized = packet_deserialized.unwrap();
-
-            match handle_packet(packet_deserialized.clone(), config).await {
-                Ok(response_packet) => {
-                    let _ = send_packet(&server, client, response_packet).await;
-                }
-
-                Err(err) => {
-                    log::trace!("Could not answer a single question (total failure): {}", err);
-                    let _ = send_packet(
-                        &server,
-                        client,
-                        PacketBuilder::new()
-                            .with_flags(
-                                HeaderFlags::new()
-                                    .with_opcode(
-                                        HeaderFlags::from(packet_deserialized.header.flags).0,
-                                    )
-                                    .with_rcode(ResponseCode::NameError)
-                                    .with_flag(Flags::QR)
-                                    .with_flag(Flags::RA),
-                            )
-                            .with_id(packet_deserialized.header.id)
-                            .with_qentries(packet_deserialized.questions)
-                            .build(),
-                    )
-                    .await;
-                }
-            }
-
-            /*if let Ok(response_packet) = handle_packet(packet_deserialized.clone(), config).await {
-                let _ = send_packet(&server, client, response_packet).await;
-            } else {
-                // construct fail-answer if no question could be answered
-                log::trace!("Could not answer a single question (total failure)");
-                let _ = send_packet(
-                    &server,
-                    client,
-                    PacketBuilder::new()
-                        .with_flags(
-                            HeaderFlags::new()
-                                .with_opcode(HeaderFlags::from(packet_deserialized.header.flags).0)
-                                .with_rcode(ResponseCode::NameError)
-                                .with_flag(Flags::QR)
-                                .with_flag(Flags::RA)
-                        )
-                        .with_id(packet_deserialized.header.id)
-                        .with_qentries(packet_deserialized.questions)
-                        .build(),
-                )
-                .await;
-            }*/
-        }
-    }
-}
+use std::{net::SocketAddr, sync::Arc, time::Instant};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Semaphore,
+};
+
+use crate::{
+    database::QueryLogEntity,
+    error::DnsError,
+    protocol::packet::{
+        flags::{Flags, HeaderFlags, OpCode, ResponseCode},
+        Edns, Packet, PacketBuilder, PacketHeader, UDP_MAX_SIZE,
+    },
+    server::handle_packet::{error_response, handle_packet},
+};
+
+use super::{Backpressure, Policy, ServerConfig};
+
+pub async fn send_packet(
+    server: &tokio::net::UdpSocket,
+    client: SocketAddr,
+    packet: Packet,
+    max_size: usize,
+) -> Result<(), DnsError> {
+    let response = packet.serialize_truncated(max_size);
+
+    match response {
+        Ok(data) => {
+            if let Err(e) = server.send_to(&data, client).await {
+                log::error!("Failed to send response: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to serialize response packet: {}", e),
+    }
+
+    Ok(())
+}
+
+/*
+    Writes a response to a TCP stream, prefixed by its two byte length (RFC 1035, Section 4.2.2)
+*/
+pub async fn send_packet_tcp(
+    stream: &mut tokio::net::TcpStream,
+    packet: Packet,
+) -> Result<(), DnsError> {
+    let data = packet.serialize()?;
+    let length: u16 = data
+        .len()
+        .try_into()
+        .map_err(|_| DnsError::encode("Response exceeds the maximum TCP message size"))?;
+
+    let mut framed = Vec::with_capacity(data.len() + 2);
+    framed.extend(length.to_be_bytes());
+    framed.extend(data);
+    stream.write_all(&framed).await?;
+
+    Ok(())
+}
+
+/*
+    Builds the response for a single raw query, regardless of the transport it arrived on,
+    along with the largest response size the client accepts over UDP.
+    Returns None if the query is too short to even carry a query id.
+    Queries that could be parsed end up in the query log, if there is one.
+*/
+pub async fn process_query(
+    data: &[u8],
+    config: &ServerConfig,
+    policy: &Policy<'_>,
+) -> Option<(Packet, usize)> {
+    let started = Instant::now();
+    let _in_flight = config.metrics().map(|metrics| metrics.start_query());
+
+    // not even a query id was sent that could
+    // be used to return a meaningful error
+    if data.len() < 2 {
+        return None;
+    }
+
+    let packet_deserialized = match Packet::deserialize(data) {
+        Ok(packet) => packet,
+        Err(err) => {
+            // couldn't parse received packet
+            log::warn!("Received malformed packet ({}). Trying to reconstruct and answer.", err);
+            if let Ok(header) = PacketHeader::deserialize(data) {
+                // try and preserve header
+                let header_flags: HeaderFlags = header.flags.into();
+                return Some((
+                    PacketBuilder::new()
+                        .with_flags(
+                            HeaderFlags::new()
+                                .with_opcode(header_flags.0)
+                                .with_rcode(ResponseCode::FormatError)
+                                .with_flag(Flags::QR)
+                                .with_flag(Flags::RA),
+                        )
+                        .with_id(header.id)
+                        .build(),
+                    UDP_MAX_SIZE,
+                ));
+            }
+
+            // fallback to only query ID
+            let query_id = u16::from_be_bytes([data[0], data[1]]);
+            return Some((
+                PacketBuilder::new()
+                    .with_flags(
+                        HeaderFlags::new()
+                            .with_opcode(OpCode::Query)
+                            .with_rcode(ResponseCode::FormatError)
+                            .with_flag(Flags::QR)
+                            .with_flag(Flags::RA),
+                    )
+                    .with_id(query_id)
+                    .build(),
+                UDP_MAX_SIZE,
+            ));
+        }
+    };
+
+    // EDNS clients get an OPT record back advertising our own payload size,
+    // responses to them may grow up to the smaller of both sizes (RFC 6891, Section 6.2.5)
+    let (response_edns, max_udp_size) = match &packet_deserialized.edns {
+        Some(edns) => (
+            Some(Edns::new(config.udp_payload_size()).with_dnssec_ok(edns.dnssec_ok)),
+            edns.max_udp_size()
+                .min(config.udp_payload_size() as usize)
+                .max(UDP_MAX_SIZE),
+        ),
+        None => (None, UDP_MAX_SIZE),
+    };
+
+    // options set while answering (e.g. Extended DNS Errors) go into our OPT record
+    let mut response_options = Vec::new();
+    let mut answered_from = None;
+    let response = match handle_packet(packet_deserialized.clone(), config, policy).await {
+        Ok((response_packet, source)) => {
+            if let Some(edns) = &response_packet.edns {
+                response_options.extend(edns.options.clone());
+            }
+            answered_from = Some(source);
+            PacketBuilder::from_packet(response_packet)
+        }
+        Err(err) => {
+            log::trace!("Could not answer a single question (total failure): {}", err);
+            error_response(&packet_deserialized, &err)
+        }
+    };
+
+    let response = match response_edns {
+        Some(mut edns) => {
+            edns.options = response_options;
+            response.with_edns(edns)
+        }
+        None => response.without_edns(),
+    }
+    .build();
+
+    // queries carry a single question in practice
+    let question = packet_deserialized.questions.first();
+    let qtype = question.map_or(0, |question| question.qtype_code());
+    if let Some(metrics) = config.metrics() {
+        metrics.record_query(qtype, response.rcode(), answered_from, started.elapsed());
+    }
+    if let Some(query_log) = config.query_log() {
+        query_log.record(QueryLogEntity::new(
+            policy.client,
+            question.map(|question| question.name()).unwrap_or_default(),
+            qtype,
+            response.rcode() as u16,
+            answered_from,
+            started.elapsed(),
+        ));
+    }
+
+    Some((response, max_udp_size))
+}
+
+/*
+    Builds a REFUSED response for a query we don't have the capacity to answer.
+    Returns None if the query can't even be parsed, in which case it is dropped.
+*/
+fn refuse_query(data: &[u8]) -> Option<Packet> {
+    let packet = Packet::deserialize(data).ok()?;
+    let err = DnsError::Refused("too many queries in flight".to_string());
+
+    Some(error_response(&packet, &err).build())
+}
+
+/*
+    Answers every datagram on its own task. At most max_concurrent_queries are handled
+    at once, queries arriving beyond that are dealt with according to the backpressure policy.
+*/
+pub async fn serve_udp(config: Arc<ServerConfig>) -> Result<(), DnsError> {
+    let server =
+        tokio::net::UdpSocket::bind(format!("{}:{}", config.listen_addr(), config.udp_port()))
+            .await?;
+    serve_udp_socket(config, server).await
+}
+
+/*
+    Like serve_udp, but on a socket that is already bound
+*/
+pub async fn serve_udp_socket(config: Arc<ServerConfig>, server: tokio::net::UdpSocket) -> Result<(), DnsError> {
+    let server = Arc::new(server);
+    let permits = Arc::new(Semaphore::new(config.max_concurrent_queries()));
+
+    loop {
+        // EDNS clients may send queries larger than 512 bytes as well
+        let mut buf = vec![0; (config.udp_payload_size() as usize).max(UDP_MAX_SIZE)];
+
+        let Ok((size, client)) = server.recv_from(&mut buf).await else {
+            continue;
+        };
+        buf.truncate(size);
+        log::trace!(
+            "Received {} bytes from {}:{}",
+            size,
+            client.ip(),
+            client.port()
+        );
+
+        let permit = match config.backpressure() {
+            Backpressure::Wait => permits
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("query semaphore is never closed"),
+            Backpressure::Drop => match permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    log::warn!("Too many queries in flight, dropping query from {}", client);
+                    continue;
+                }
+            },
+            Backpressure::Refuse => match permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    log::warn!("Too many queries in flight, refusing query from {}", client);
+                    if let Some(response_packet) = refuse_query(&buf) {
+                        let _ = send_packet(&server, client, response_packet, UDP_MAX_SIZE).await;
+                    }
+                    continue;
+                }
+            },
+        };
+
+        let server = server.clone();
+        let config = config.clone();
+        tokio::spawn(async move {
+            let policy = config.policy_for(client.ip());
+            if let Some(group) = policy.group() {
+                log::trace!("{} belongs to client group {}", client, group);
+            }
+
+            if let Some((response_packet, max_size)) = process_query(&buf, &config, &policy).await {
+                let _ = send_packet(&server, client, response_packet, max_size).await;
+            }
+            drop(permit);
+        });
+    }
+}
+
+/*
+    Reads exactly buf.len() bytes from the stream. Returns Ok(false) if the client
+    closed the connection or stayed idle for longer than the configured timeout.
+*/
+async fn read_tcp_message(
+    stream: &mut tokio::net::TcpStream,
+    buf: &mut [u8],
+    idle_timeout: std::time::Duration,
+) -> Result<bool, DnsError> {
+    match tokio::time::timeout(idle_timeout, stream.read_exact(buf)).await {
+        Ok(Ok(_)) => Ok(true),
+        Ok(Err(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Ok(Err(err)) => Err(err.into()),
+        Err(_) => Ok(false),
+    }
+}
+
+/*
+    Answers length-prefixed queries on a single connection until the client
+    closes it or stays idle for too long. Pipelined queries are answered in order.
+*/
+async fn handle_tcp_connection(
+    mut stream: tokio::net::TcpStream,
+    client: SocketAddr,
+    config: &ServerConfig,
+) -> Result<(), DnsError> {
+    let policy = config.policy_for(client.ip());
+
+    loop {
+        let mut length = [0u8; 2];
+        if !read_tcp_message(&mut stream, &mut length, config.tcp_idle_timeout()).await? {
+            log::trace!("Closing TCP connection to {}", client);
+            return Ok(());
+        }
+
+        let mut buf = vec![0u8; u16::from_be_bytes(length) as usize];
+        if !read_tcp_message(&mut stream, &mut buf, config.tcp_idle_timeout()).await? {
+            log::trace!("TCP client {} sent an incomplete message", client);
+            return Ok(());
+        }
+
+        log::trace!(
+            "Received {} bytes from {}:{} (tcp)",
+            buf.len(),
+            client.ip(),
+            client.port()
+        );
+
+        match process_query(&buf, config, &policy).await {
+            Some((response_packet, _)) => send_packet_tcp(&mut stream, response_packet).await?,
+            // nothing to answer with, and the framing can't be trusted anymore
+            None => return Ok(()),
+        }
+    }
+}
+
+pub async fn serve_tcp(config: Arc<ServerConfig>) -> Result<(), DnsError> {
+    let listener =
+        tokio::net::TcpListener::bind(format!("{}:{}", config.listen_addr(), config.tcp_port()))
+            .await?;
+    serve_tcp_listener(config, listener).await
+}
+
+/*
+    Like serve_tcp, but on a listener that is already bound
+*/
+pub async fn serve_tcp_listener(config: Arc<ServerConfig>, listener: tokio::net::TcpListener) -> Result<(), DnsError> {
+    let permits = Arc::new(Semaphore::new(config.max_tcp_connections()));
+
+    loop {
+        // waiting leaves further connections in the listen backlog
+        let waited = match config.backpressure() {
+            Backpressure::Wait => Some(
+                permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("connection semaphore is never closed"),
+            ),
+            Backpressure::Drop | Backpressure::Refuse => None,
+        };
+
+        let (stream, client) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log::warn!("Failed to accept TCP connection: {}", err);
+                continue;
+            }
+        };
+
+        // there's no query to refuse yet, so either way the connection is closed right away
+        let permit = match waited {
+            Some(permit) => permit,
+            None => match permits.clone().try_acquire_owned() {
+                Ok(permit) => permit,
+                Err(_) => {
+                    log::warn!("Too many TCP connections, closing connection from {}", client);
+                    continue;
+                }
+            },
+        };
+        log::trace!("Accepted TCP connection from {}", client);
+
+        let config = config.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle_tcp_connection(stream, client, &config).await {
+                log::warn!("TCP connection failed: {}", err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        filter::{BlockMode, Blocklist, Filter, FilterRule, RuleAction, RuleKind, RuleSet},
+        protocol::packet::{ExtendedError, Question, RData, ResourceRecord},
+        resolver::Resolver,
+        server::ClientGroup,
+    };
+
+    use super::*;
+
+    /*
+        Answers a query as if it was sent by client
+    */
+    async fn query_from(config: &ServerConfig, data: &[u8], client: &str) -> (Packet, usize) {
+        let policy = config.policy_for(client.parse().unwrap());
+        process_query(data, config, &policy).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_edns_negotiation() {
+        let config = ServerConfig::default().with_udp_payload_size(1400);
+        let query = |edns: Option<Edns>| {
+            let builder = PacketBuilder::new()
+                .with_id(0x1234)
+                .with_qentries(vec![Question::default().with_name("dns.is.tiny".to_string())]);
+            match edns {
+                Some(edns) => builder.with_edns(edns),
+                None => builder,
+            }
+            .build()
+            .serialize()
+            .unwrap()
+        };
+
+        // plain DNS clients neither get an OPT record nor more than 512 bytes
+        let (response, max_size) = query_from(&config, &query(None), "127.0.0.1").await;
+        assert!(response.edns.is_none());
+        assert_eq!(max_size, UDP_MAX_SIZE);
+
+        // the smaller of both payload sizes wins, the DO bit is mirrored
+        let edns = Edns::new(4096).with_dnssec_ok(true);
+        let (response, max_size) = query_from(&config, &query(Some(edns)), "127.0.0.1").await;
+        let edns = response.edns.unwrap();
+        assert_eq!(edns.udp_payload_size, 1400);
+        assert!(edns.dnssec_ok);
+        assert_eq!(max_size, 1400);
+
+        let mut unsupported = Edns::new(4096);
+        unsupported.version = 1;
+        let (response, _) = query_from(&config, &query(Some(unsupported)), "127.0.0.1").await;
+        assert_eq!(response.rcode(), ResponseCode::BadVersion);
+
+        // the extended response code survives the trip over the wire
+        let response = Packet::deserialize(&response.serialize().unwrap()).unwrap();
+        assert_eq!(response.rcode(), ResponseCode::BadVersion);
+        assert_eq!(response.edns.unwrap().version, 0);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_query() {
+        // the fallback server is unreachable, so any answer has to come from the blocklist
+        let config = ServerConfig::default()
+            .with_resolver(Resolver::default().with_fallback_server(("127.0.0.1".to_string(), 9)))
+            .with_blocklist(Blocklist::new().with_domain("ads.example.com"));
+
+        let query = PacketBuilder::new()
+            .with_id(0x4242)
+            .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+            .with_qentries(vec![Question::default().with_name("x.ads.example.com".to_string())])
+            .build()
+            .serialize()
+            .unwrap();
+
+        let (response, _) = query_from(&config, &query, "127.0.0.1").await;
+        assert_eq!(response.header.id, 0x4242);
+        assert_eq!(response.rcode(), ResponseCode::NameError);
+        assert_eq!(response.questions.len(), 1);
+        // no OPT record was sent, so there's no room for an Extended DNS Error either
+        assert!(response.edns.is_none());
+
+        let config = config.with_blocklist(
+            Blocklist::new()
+                .with_mode(BlockMode::NullIp { ttl: 10 })
+                .with_domain("ads.example.com"),
+        );
+        let query = PacketBuilder::new()
+            .with_id(0x4243)
+            .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+            .with_qentries(vec![Question::default().with_name("ads.example.com".to_string())])
+            .with_edns(Edns::default())
+            .build()
+            .serialize()
+            .unwrap();
+
+        let (response, _) = query_from(&config, &query, "127.0.0.1").await;
+        let response = Packet::deserialize(&response.serialize().unwrap()).unwrap();
+        assert_eq!(response.rcode(), ResponseCode::NoError);
+        assert_eq!(response.answers[0].rdata(), RData::A(std::net::Ipv4Addr::UNSPECIFIED));
+        let (code, _) = response.edns.unwrap().extended_error().unwrap();
+        assert_eq!(code, ExtendedError::Blocked as u16);
+
+        // block rules filter on behalf of the clients rather than the operator
+        let rules = RuleSet::new()
+            .with_rule(FilterRule {
+                id: 1,
+                action: RuleAction::Block,
+                kind: RuleKind::Exact,
+                pattern: "ads.example.com".to_string(),
+                schedule: None,
+            })
+            .unwrap();
+        let config = config.with_filter(Filter::new().with_rules(rules));
+        let (response, _) = query_from(&config, &query, "127.0.0.1").await;
+        let response = Packet::deserialize(&response.serialize().unwrap()).unwrap();
+        assert_eq!(response.rcode(), ResponseCode::NameError);
+        let (code, _) = response.edns.unwrap().extended_error().unwrap();
+        assert_eq!(code, ExtendedError::Filtered as u16);
+
+        let config = config
+            .with_filter(Filter::new())
+            .with_blocklist(Blocklist::new().with_mode(BlockMode::Refused).with_domain("ads.example.com"));
+        let (response, _) = query_from(&config, &query, "127.0.0.1").await;
+        let response = Packet::deserialize(&response.serialize().unwrap()).unwrap();
+        assert_eq!(response.rcode(), ResponseCode::Refused);
+        assert_eq!(response.questions.len(), 1);
+        let (code, _) = response.edns.unwrap().extended_error().unwrap();
+        assert_eq!(code, ExtendedError::Blocked as u16);
+    }
+
+    #[tokio::test]
+    async fn test_truncated_upstream_response() {
+        let upstream = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let config = ServerConfig::default().with_resolver(
+            Resolver::default().with_fallback_server(("127.0.0.1".to_string(), upstream.local_addr().unwrap().port())),
+        );
+
+        // answers with a part of the records only, flagged as truncated
+        let fake_upstream = async {
+            let mut buf = [0u8; 512];
+            let (size, client) = upstream.recv_from(&mut buf).await.unwrap();
+            let query = Packet::deserialize(&buf[..size]).unwrap();
+            let response = PacketBuilder::new()
+                .with_id(query.header.id)
+                .with_flags(HeaderFlags::new().with_flag(Flags::QR).with_flag(Flags::TC))
+                .with_aentries(vec![ResourceRecord::default()
+                    .with_name(query.questions[0].name())
+                    .with_rdata(RData::A(std::net::Ipv4Addr::new(192, 0, 2, 1)))])
+                .with_qentries(query.questions)
+                .build();
+            upstream.send_to(&response.serialize().unwrap(), client).await.unwrap();
+        };
+
+        let query = PacketBuilder::new()
+            .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+            .with_qentries(vec![Question::default().with_name("big.is.tiny".to_string())])
+            .build()
+            .serialize()
+            .unwrap();
+        let ((response, _), _) = tokio::join!(query_from(&config, &query, "127.0.0.1"), fake_upstream);
+
+        // the client has to learn that it didn't get the whole answer
+        let response = Packet::deserialize(&response.serialize().unwrap()).unwrap();
+        assert_ne!(response.header.flags & Flags::TC as u16, 0);
+    }
+
+    #[tokio::test]
+    async fn test_client_group_policy() {
+        let kids = ClientGroup::new("kids")
+            .with_client("10.0.0.0/24".parse().unwrap())
+            .with_filter(
+                Filter::new().with_blocklist(
+                    Blocklist::new()
+                        .with_mode(BlockMode::NullIp { ttl: 10 })
+                        .with_domain("games.example.com"),
+                ),
+            );
+        let config = ServerConfig::default().with_group(kids);
+
+        let query = PacketBuilder::new()
+            .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+            .with_qentries(vec![Question::default().with_name("games.example.com".to_string())])
+            .build()
+            .serialize()
+            .unwrap();
+
+        let (response, _) = query_from(&config, &query, "10.0.0.5").await;
+        assert_eq!(response.answers.len(), 1);
+
+        // clients outside of the group aren't affected by its blocklist
+        let (response, _) = query_from(&config, &query, "10.0.1.5").await;
+        assert!(response.answers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_udp_backpressure_refuse() {
+        // an upstream that never answers keeps the first query busy
+        let upstream = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+
+        // bound up front on any free port, so that the client can't be faster than the server
+        let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server.local_addr().unwrap();
+
+        let config = Arc::new(
+            ServerConfig::default()
+                .with_resolver(
+                    Resolver::default().with_fallback_server(("127.0.0.1".to_string(), upstream_port)),
+                )
+                .with_max_concurrent_queries(1)
+                .with_backpressure(Backpressure::Refuse),
+        );
+
+        let client = async {
+            let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            socket.connect(server_addr).await.unwrap();
+
+            for id in [0x1111u16, 0x2222] {
+                let query = PacketBuilder::new()
+                    .with_id(id)
+                    .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+                    .with_qentries(vec![Question::default().with_name("dns.is.tiny".to_string())])
+                    .build()
+                    .serialize()
+                    .unwrap();
+                socket.send(&query).await.unwrap();
+            }
+
+            // only the second query gets an answer before the upstream times out
+            let mut buf = [0u8; 512];
+            let size = socket.recv(&mut buf).await.unwrap();
+            Packet::deserialize(&buf[..size]).unwrap()
+        };
+
+        tokio::select! {
+            response = client => {
+                assert_eq!(response.header.id, 0x2222);
+                assert_eq!(response.rcode(), ResponseCode::Refused);
+            },
+            result = serve_udp_socket(config.clone(), server) => panic!("UDP server exited: {:?}", result.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_pipelined_queries() {
+        let config = Arc::new(ServerConfig::default());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let client = async {
+            let mut stream = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+
+            // send two queries back to back before reading any response
+            let mut queries = Vec::new();
+            for id in [0x1111u16, 0x2222] {
+                let query = PacketBuilder::new()
+                    .with_id(id)
+                    .with_flags(HeaderFlags::new().with_flag(Flags::RD))
+                    .with_qentries(vec![Question::default().with_name("dns.is.tiny".to_string())])
+                    .build()
+                    .serialize()
+                    .unwrap();
+                queries.extend((query.len() as u16).to_be_bytes());
+                queries.extend(query);
+            }
+            stream.write_all(&queries).await.unwrap();
+
+            let mut ids = Vec::new();
+            for _ in 0..2 {
+                let mut length = [0u8; 2];
+                stream.read_exact(&mut length).await.unwrap();
+                let mut buf = vec![0u8; u16::from_be_bytes(length) as usize];
+                stream.read_exact(&mut buf).await.unwrap();
+                ids.push(Packet::deserialize(&buf).unwrap().header.id);
+            }
+            ids
+        };
+
+        tokio::select! {
+            ids = client => assert_eq!(ids, vec![0x1111, 0x2222]),
+            result = serve_tcp_listener(config.clone(), listener) => panic!("TCP server exited: {:?}", result.err()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_connection_limit() {
+        let config = Arc::new(
+            ServerConfig::default()
+                .with_max_tcp_connections(1)
+                .with_backpressure(Backpressure::Drop),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = listener.local_addr().unwrap();
+
+        let query = PacketBuilder::new()
+            .with_id(0x3333)
+            .with_qentries(vec![Question::default().with_name("dns.is.tiny".to_string())])
+            .build()
+            .serialize()
+            .unwrap();
+        let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+        framed.extend(query);
+
+        let client = async {
+            // an answer means the first connection holds the only permit
+            let mut first = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+            first.write_all(&framed).await.unwrap();
+            let mut length = [0u8; 2];
+            first.read_exact(&mut length).await.unwrap();
+
+            // the second one is closed right away
+            let mut second = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+            assert_eq!(second.read(&mut length).await.unwrap(), 0);
+
+            // once the first connection is gone, there's room again
+            drop(first);
+            loop {
+                let mut third = tokio::net::TcpStream::connect(server_addr).await.unwrap();
+                if third.write_all(&framed).await.is_ok() && third.read_exact(&mut length).await.is_ok() {
+                    break;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = client => {},
+            result = serve_tcp_listener(config.clone(), listener) => panic!("TCP server exited: {:?}", result.err()),
+        }
+    }
+}
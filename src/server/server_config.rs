@@ -1,57 +1,508 @@
-use crate::{nameserver::Nameserver, resolver::Resolver};
-
-pub struct ServerConfig<'a> {
-    udp_port: u16,
-    listen_addr: String,
-    resolver: Resolver,
-    nameserver: Option<Nameserver<'a>>
-}
-
-impl<'a> Default for ServerConfig<'a> {
-    fn default() -> Self {
-        ServerConfig {
-            udp_port: 53,
-            listen_addr: "127.0.0.1".to_string(),
-            resolver: Resolver::default(),
-            nameserver: None,
-        }
-    }
-}
-
-impl<'a> ServerConfig<'a> {
-    pub fn with_udp_port(mut self, port: u16) -> Self {
-        self.udp_port = port;
-        self
-    }
-
-    pub fn with_listen_addr(mut self, addr: String) -> Self {
-        self.listen_addr = addr;
-        self
-    }
-
-    pub fn with_resolver(mut self, resolver: Resolver) -> Self {
-        self.resolver = resolver;
-        self
-    }
-
-    pub fn with_nameserver(mut self, nameserver: Nameserver<'a>) -> Self {
-        self.nameserver = Some(nameserver);
-        self
-    }
-
-    pub fn udp_port(&self) -> u16 {
-        self.udp_port
-    }
-
-    pub fn listen_addr(&self) -> &str {
-        &self.listen_addr
-    }
-
-    pub fn resolver(&self) -> &Resolver {
-        &self.resolver
-    }
-
-    pub fn nameserver(&self) -> Option<&Nameserver<'a>> {
-        self.nameserver.as_ref()
-    }
-}
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    database::{ClientGroupEntity, Database, UpstreamEntity},
+    error::DnsError,
+    filter::{Blocklist, Filter},
+    metrics::Metrics,
+    nameserver::Nameserver,
+    protocol::packet::EDNS_DEFAULT_PAYLOAD_SIZE,
+    querylog::QueryLog,
+    resolver::Resolver,
+};
+
+use super::{ClientGroup, Policy};
+
+/*
+    Upstream servers stored in the database for a client group,
+    or for clients outside of any group if group is None
+*/
+async fn load_upstreams(db: &Database, group: Option<&str>) -> Result<Vec<(String, u16)>, DnsError> {
+    let upstreams =
+        UpstreamEntity::_fetch_for_group(db.get_pool(), db.upstream_servers_tbl(), group).await?;
+    Ok(upstreams.iter().map(UpstreamEntity::server).collect())
+}
+
+/*
+    What serve_udp does with a query that arrives while
+    the maximum number of queries is already being handled
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backpressure {
+    // stop reading from the socket until a query finishes
+    Wait,
+    // silently discard the query
+    Drop,
+    // answer with REFUSED right away
+    Refuse,
+}
+
+pub struct ServerConfig {
+    udp_port: u16,
+    tcp_port: u16,
+    tcp_idle_timeout: Duration,
+    udp_payload_size: u16,
+    max_concurrent_queries: usize,
+    max_tcp_connections: usize,
+    backpressure: Backpressure,
+    listen_addr: String,
+    resolver: Resolver,
+    // fallback servers the resolver was configured with, used while the database has none
+    configured_upstreams: Vec<(String, u16)>,
+    nameserver: Option<Nameserver>,
+    filter: Filter,
+    // blocklists of the client groups that have lists of their own, by group name
+    group_blocklists: HashMap<String, Arc<Blocklist>>,
+    // replaced as a whole whenever the groups change
+    groups: RwLock<Vec<Arc<ClientGroup>>>,
+    query_log: Option<QueryLog>,
+    metrics: Option<Arc<Metrics>>,
+    metrics_port: u16,
+    admin_port: u16,
+    // how often to check the database for changes made by other processes
+    reload_interval: Duration,
+    // config version of the database the last reload saw
+    config_version: AtomicI64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            udp_port: 53,
+            tcp_port: 53,
+            tcp_idle_timeout: Duration::from_secs(10),
+            udp_payload_size: EDNS_DEFAULT_PAYLOAD_SIZE,
+            max_concurrent_queries: 128,
+            max_tcp_connections: 64,
+            backpressure: Backpressure::Wait,
+            listen_addr: "127.0.0.1".to_string(),
+            resolver: Resolver::default(),
+            configured_upstreams: Vec::new(),
+            nameserver: None,
+            filter: Filter::default(),
+            group_blocklists: HashMap::new(),
+            groups: RwLock::default(),
+            query_log: None,
+            metrics: None,
+            metrics_port: 9153,
+            admin_port: 8053,
+            reload_interval: Duration::from_secs(5),
+            config_version: AtomicI64::new(-1),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn with_udp_port(mut self, port: u16) -> Self {
+        self.udp_port = port;
+        self
+    }
+
+    pub fn with_tcp_port(mut self, port: u16) -> Self {
+        self.tcp_port = port;
+        self
+    }
+
+    pub fn with_tcp_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.tcp_idle_timeout = timeout;
+        self
+    }
+
+    /*
+        Largest UDP response we're willing to send to EDNS clients,
+        which is also advertised in our OPT records
+    */
+    pub fn with_udp_payload_size(mut self, size: u16) -> Self {
+        self.udp_payload_size = size;
+        self
+    }
+
+    /*
+        Maximum number of UDP queries being answered at the same time (at least one)
+    */
+    pub fn with_max_concurrent_queries(mut self, max: usize) -> Self {
+        self.max_concurrent_queries = max.max(1);
+        self
+    }
+
+    /*
+        Maximum number of TCP connections being served at the same time (at least one),
+        connections beyond that are dealt with according to the backpressure policy
+    */
+    pub fn with_max_tcp_connections(mut self, max: usize) -> Self {
+        self.max_tcp_connections = max.max(1);
+        self
+    }
+
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    pub fn with_listen_addr(mut self, addr: String) -> Self {
+        self.listen_addr = addr;
+        self
+    }
+
+    pub fn with_resolver(mut self, resolver: Resolver) -> Self {
+        self.configured_upstreams = resolver.fallback_servers();
+        self.resolver = resolver;
+        self
+    }
+
+    pub fn with_nameserver(mut self, nameserver: Nameserver) -> Self {
+        self.nameserver = Some(nameserver);
+        self
+    }
+
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.filter = self.filter.with_blocklist(blocklist);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /*
+        Blocklist the filter of a client group gets instead of the server's,
+        once the group is loaded from the database
+    */
+    pub fn with_group_blocklist(mut self, group: &str, blocklist: Blocklist) -> Self {
+        self.group_blocklists.insert(group.to_string(), Arc::new(blocklist));
+        self
+    }
+
+    /*
+        Adds a client group. Clients belonging to several groups get the policy of the first one.
+    */
+    pub fn with_group(mut self, group: ClientGroup) -> Self {
+        self.groups.get_mut().unwrap().push(Arc::new(group));
+        self
+    }
+
+    pub fn set_groups(&self, groups: Vec<ClientGroup>) {
+        *self.groups.write().unwrap() = groups.into_iter().map(Arc::new).collect();
+    }
+
+    pub fn with_query_log(mut self, query_log: QueryLog) -> Self {
+        self.query_log = Some(query_log);
+        self
+    }
+
+    /*
+        Metrics to record queries in. Resolvers record their upstream queries
+        only if they were given the same metrics.
+    */
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /*
+        Port of the HTTP server exposing the metrics on /metrics
+    */
+    pub fn with_metrics_port(mut self, port: u16) -> Self {
+        self.metrics_port = port;
+        self
+    }
+
+    /*
+        Port of the HTTP server exposing the admin API
+    */
+    pub fn with_admin_port(mut self, port: u16) -> Self {
+        self.admin_port = port;
+        self
+    }
+
+    pub fn with_reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    pub fn udp_port(&self) -> u16 {
+        self.udp_port
+    }
+
+    pub fn tcp_port(&self) -> u16 {
+        self.tcp_port
+    }
+
+    pub fn tcp_idle_timeout(&self) -> Duration {
+        self.tcp_idle_timeout
+    }
+
+    pub fn udp_payload_size(&self) -> u16 {
+        self.udp_payload_size
+    }
+
+    pub fn max_concurrent_queries(&self) -> usize {
+        self.max_concurrent_queries
+    }
+
+    pub fn max_tcp_connections(&self) -> usize {
+        self.max_tcp_connections
+    }
+
+    pub fn backpressure(&self) -> Backpressure {
+        self.backpressure
+    }
+
+    pub fn listen_addr(&self) -> &str {
+        &self.listen_addr
+    }
+
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    pub fn nameserver(&self) -> Option<&Nameserver> {
+        self.nameserver.as_ref()
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub fn groups(&self) -> Vec<Arc<ClientGroup>> {
+        self.groups.read().unwrap().clone()
+    }
+
+    pub fn query_log(&self) -> Option<&QueryLog> {
+        self.query_log.as_ref()
+    }
+
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+
+    pub fn metrics_port(&self) -> u16 {
+        self.metrics_port
+    }
+
+    pub fn admin_port(&self) -> u16 {
+        self.admin_port
+    }
+
+    /*
+        Picks the filter and resolver for queries of a client
+    */
+    pub fn policy_for(&self, client: IpAddr) -> Policy<'_> {
+        let group = self
+            .groups
+            .read()
+            .unwrap()
+            .iter()
+            .find(|group| group.contains(client))
+            .cloned();
+
+        Policy {
+            client,
+            group,
+            default_filter: &self.filter,
+            default_resolver: &self.resolver,
+        }
+    }
+
+    /*
+        Applies the filter rules, upstream servers and client groups stored in the database,
+        replacing the groups configured in code, and the configured fallback servers unless the
+        database has none. Every group gets its own filter with the rules for all clients plus its
+        own, sharing the server's blocklist unless the group has one, and its own resolver if it
+        has upstream servers.
+    */
+    pub async fn reload(&self, db: &Database) -> Result<(), DnsError> {
+        // read first, so that changes made while reloading cause another reload
+        self.config_version.store(db.config_version().await?, Ordering::Relaxed);
+        self.filter.reload_rules(db, None).await?;
+        let upstreams = load_upstreams(db, None).await?;
+        self.resolver.set_fallback_servers(if upstreams.is_empty() {
+            self.configured_upstreams.clone()
+        } else {
+            upstreams
+        });
+
+        let current_groups = self.groups();
+        let mut groups = Vec::new();
+        for entity in ClientGroupEntity::_fetch_all(db.get_pool(), db.client_groups_tbl()).await? {
+            let clients = match entity.to_matchers() {
+                Ok(clients) => clients,
+                Err(err) => {
+                    log::warn!("Skipping client group {}: {}", entity.name(), err);
+                    continue;
+                }
+            };
+
+            let mut group = ClientGroup::new(entity.name());
+            for client in clients {
+                group = group.with_client(client);
+            }
+
+            let mut filter = self.filter.fork();
+            if let Some(blocklist) = self.group_blocklists.get(entity.name()) {
+                filter = filter.with_shared_blocklist(blocklist.clone());
+            }
+            filter.reload_rules(db, Some(entity.name())).await?;
+            group = group.with_filter(filter);
+
+            let upstreams = load_upstreams(db, Some(entity.name())).await?;
+            if !upstreams.is_empty() {
+                let current = current_groups.iter().find(|group| group.name() == entity.name());
+                group = group.with_resolver(self.group_resolver(current, upstreams));
+            }
+
+            groups.push(group);
+        }
+
+        log::info!("Loaded {} client groups", groups.len());
+        self.set_groups(groups);
+        Ok(())
+    }
+
+    /*
+        Resolver for a group with upstream servers of its own, caching as much as the default one.
+        The resolver the group had before is kept along with its cache, unless its servers changed.
+    */
+    fn group_resolver(&self, current: Option<&Arc<ClientGroup>>, upstreams: Vec<(String, u16)>) -> Arc<Resolver> {
+        if let Some(resolver) = current
+            .and_then(|group| group.resolver())
+            .filter(|resolver| resolver.fallback_servers() == upstreams)
+        {
+            return resolver.clone();
+        }
+
+        let mut resolver = Resolver::default().with_cache_capacity(self.resolver.cache().capacity());
+        for upstream in upstreams {
+            resolver = resolver.with_fallback_server(upstream);
+        }
+        if let Some(metrics) = &self.metrics {
+            resolver = resolver.with_metrics(metrics.clone());
+        }
+        Arc::new(resolver)
+    }
+
+    /*
+        Reloads whenever the database was changed behind our back, e.g. by tinydns-ctl
+    */
+    pub async fn watch(&self, db: &Database) -> Result<(), DnsError> {
+        let mut interval = tokio::time::interval(self.reload_interval);
+        loop {
+            interval.tick().await;
+            match db.config_version().await {
+                Ok(version) if version != self.config_version.load(Ordering::Relaxed) => {
+                    log::info!("Configuration changed, reloading");
+                    if let Err(err) = self.reload(db).await {
+                        log::error!("Failed to reload the configuration: {}", err);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => log::error!("Failed to check for configuration changes: {}", err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        database::FilterRuleEntity,
+        filter::{RuleAction, RuleKind, Verdict},
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reload_group_rules() {
+        let db = Database::init_mem().await.unwrap();
+        ClientGroupEntity::default()
+            .with_name("kids".to_string())
+            .with_clients(&["10.0.0.0/24".to_string()])
+            ._insert(db.get_pool(), db.client_groups_tbl())
+            .await
+            .unwrap();
+        for (pattern, group) in [("ads.example.com", None), ("games.example.com", Some("kids"))] {
+            let mut rule = FilterRuleEntity::default()
+                .with_action(RuleAction::Block)
+                .with_kind(RuleKind::Exact)
+                .with_pattern(pattern.to_string());
+            if let Some(group) = group {
+                rule = rule.with_client_group(group.to_string());
+            }
+            rule._insert(db.get_pool(), db.filter_rules_tbl()).await.unwrap();
+        }
+
+        let config = ServerConfig::default()
+            .with_blocklist(Blocklist::new().with_domain("tracker.example.com"))
+            .with_group_blocklist(
+                "kids",
+                Blocklist::new()
+                    .with_domain("tracker.example.com")
+                    .with_domain("videos.example.com"),
+            );
+        config.reload(&db).await.unwrap();
+
+        // a group with rules of its own still gets the rules for all clients
+        let kids = config.policy_for("10.0.0.5".parse().unwrap());
+        assert_eq!(kids.group(), Some("kids"));
+        assert!(matches!(kids.filter().check("ads.example.com"), Some(Verdict::Blocked(_))));
+        assert!(matches!(kids.filter().check("games.example.com"), Some(Verdict::Blocked(_))));
+        assert!(kids.filter().blocklist().is_blocked("videos.example.com"));
+
+        let others = config.policy_for("10.0.1.5".parse().unwrap());
+        assert!(matches!(others.filter().check("ads.example.com"), Some(Verdict::Blocked(_))));
+        assert!(others.filter().check("games.example.com").is_none());
+        assert!(others.filter().blocklist().is_blocked("tracker.example.com"));
+        assert!(!others.filter().blocklist().is_blocked("videos.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_reload_group_resolvers() {
+        let db = Database::init_mem().await.unwrap();
+        ClientGroupEntity::default()
+            .with_name("iot".to_string())
+            .with_clients(&["10.0.2.0/24".to_string()])
+            ._insert(db.get_pool(), db.client_groups_tbl())
+            .await
+            .unwrap();
+        UpstreamEntity::default()
+            .with_address("9.9.9.9".to_string())
+            .with_client_group("iot".to_string())
+            ._insert(db.get_pool(), db.upstream_servers_tbl())
+            .await
+            .unwrap();
+
+        let config = ServerConfig::default().with_resolver(Resolver::default().with_cache_capacity(0));
+        config.reload(&db).await.unwrap();
+        let resolver = |config: &ServerConfig| config.groups()[0].resolver().unwrap().clone();
+        let first = resolver(&config);
+        // group resolvers cache as much as the default one
+        assert_eq!(first.cache().capacity(), 0);
+
+        // the resolver and its cache survive reloads that leave its servers alone
+        config.reload(&db).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &resolver(&config)));
+
+        UpstreamEntity::default()
+            .with_address("149.112.112.112".to_string())
+            .with_client_group("iot".to_string())
+            ._insert(db.get_pool(), db.upstream_servers_tbl())
+            .await
+            .unwrap();
+        config.reload(&db).await.unwrap();
+        let second = resolver(&config);
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(second.fallback_servers().len(), 2);
+    }
+}
@@ -0,0 +1,3 @@
+mod stats;
+
+pub use stats::{summary, QueryStats, TopEntry};
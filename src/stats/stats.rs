@@ -0,0 +1,209 @@
+use chrono::{DurationRound, NaiveDateTime, TimeDelta};
+use serde::Serialize;
+
+use crate::{
+    database::{Database, QueryLogEntity},
+    error::DnsError,
+    protocol::answer::AnswerSource,
+};
+
+/*
+    Number of queries per outcome
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Totals {
+    pub queries: u64,
+    pub local: u64,
+    pub cached: u64,
+    pub upstream: u64,
+    pub blocked: u64,
+    // queries that couldn't be answered at all
+    pub failed: u64,
+    // share of blocked queries, from 0 to 100
+    pub blocked_percentage: f64,
+}
+
+/*
+    A domain or client along with its number of queries
+*/
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopEntry {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HourlyCount {
+    // start of the hour (UTC)
+    pub hour: NaiveDateTime,
+    pub queries: u64,
+    pub blocked: u64,
+}
+
+/*
+    Everything a dashboard shows about a time window
+*/
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryStats {
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    pub totals: Totals,
+    pub top_domains: Vec<TopEntry>,
+    pub top_blocked_domains: Vec<TopEntry>,
+    pub top_clients: Vec<TopEntry>,
+    pub hourly: Vec<HourlyCount>,
+}
+
+fn to_entries(rows: Vec<(String, i64)>) -> Vec<TopEntry> {
+    rows.into_iter()
+        .map(|(name, count)| TopEntry { name, count: count as u64 })
+        .collect()
+}
+
+fn start_of_hour(time: NaiveDateTime) -> NaiveDateTime {
+    time.duration_trunc(TimeDelta::hours(1)).unwrap_or(time)
+}
+
+/*
+    Counts the queries logged within [since, until) (UTC)
+*/
+pub async fn totals(db: &Database, since: NaiveDateTime, until: NaiveDateTime) -> Result<Totals, DnsError> {
+    let rows = QueryLogEntity::_count_by_source(db.get_pool(), db.query_log_tbl(), since, until).await?;
+
+    let mut totals = Totals::default();
+    for (source, count) in rows {
+        let count = count as u64;
+        totals.queries += count;
+        match source.as_deref() {
+            Some("local") => totals.local += count,
+            Some("cache") => totals.cached += count,
+            Some("upstream") => totals.upstream += count,
+            Some("blocked") => totals.blocked += count,
+            _ => totals.failed += count,
+        }
+    }
+
+    if totals.queries > 0 {
+        totals.blocked_percentage = totals.blocked as f64 * 100.0 / totals.queries as f64;
+    }
+    Ok(totals)
+}
+
+/*
+    Most queried domains within [since, until), blocked or not
+*/
+pub async fn top_domains(db: &Database, since: NaiveDateTime, until: NaiveDateTime, limit: u32) -> Result<Vec<TopEntry>, DnsError> {
+    let rows = QueryLogEntity::_top_values(db.get_pool(), db.query_log_tbl(), "qname", since, until, None, limit).await?;
+    Ok(to_entries(rows))
+}
+
+pub async fn top_blocked_domains(db: &Database, since: NaiveDateTime, until: NaiveDateTime, limit: u32) -> Result<Vec<TopEntry>, DnsError> {
+    let rows = QueryLogEntity::_top_values(db.get_pool(), db.query_log_tbl(), "qname", since, until, Some(AnswerSource::Blocked), limit).await?;
+    Ok(to_entries(rows))
+}
+
+pub async fn top_clients(db: &Database, since: NaiveDateTime, until: NaiveDateTime, limit: u32) -> Result<Vec<TopEntry>, DnsError> {
+    let rows = QueryLogEntity::_top_values(db.get_pool(), db.query_log_tbl(), "client", since, until, None, limit).await?;
+    Ok(to_entries(rows))
+}
+
+/*
+    Queries per hour within [since, until). Every hour of the window is listed,
+    including those without any queries.
+*/
+pub async fn hourly(db: &Database, since: NaiveDateTime, until: NaiveDateTime) -> Result<Vec<HourlyCount>, DnsError> {
+    let mut rows = QueryLogEntity::_count_by_hour(db.get_pool(), db.query_log_tbl(), since, until)
+        .await?
+        .into_iter()
+        .peekable();
+
+    let mut histogram = Vec::new();
+    let mut hour = start_of_hour(since);
+    while hour < until {
+        let mut count = HourlyCount { hour, queries: 0, blocked: 0 };
+        if let Some((_, queries, blocked)) = rows.next_if(|(row_hour, _, _)| *row_hour == hour) {
+            count.queries = queries as u64;
+            count.blocked = blocked as u64;
+        }
+        histogram.push(count);
+        hour += TimeDelta::hours(1);
+    }
+
+    Ok(histogram)
+}
+
+/*
+    All of the above for [since, until), with at most limit entries per top list
+*/
+pub async fn summary(db: &Database, since: NaiveDateTime, until: NaiveDateTime, limit: u32) -> Result<QueryStats, DnsError> {
+    Ok(QueryStats {
+        since,
+        until,
+        totals: totals(db, since, until).await?,
+        top_domains: top_domains(db, since, until, limit).await?,
+        top_blocked_domains: top_blocked_domains(db, since, until, limit).await?,
+        top_clients: top_clients(db, since, until, limit).await?,
+        hourly: hourly(db, since, until).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_summary() {
+        let db = Database::init_mem().await.unwrap();
+        let at = |hour: u32, minute: u32| {
+            NaiveDate::from_ymd_opt(2026, 10, 18)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap()
+        };
+
+        let entries: Vec<_> = [
+            (at(10, 5), "10.0.0.1", "example.com", Some(AnswerSource::Upstream)),
+            (at(10, 6), "10.0.0.1", "example.com", Some(AnswerSource::Cache)),
+            (at(10, 30), "10.0.0.2", "ads.example.com", Some(AnswerSource::Blocked)),
+            (at(12, 0), "10.0.0.1", "ads.example.com", Some(AnswerSource::Blocked)),
+            (at(12, 1), "10.0.0.1", "dns.is.tiny", Some(AnswerSource::Local)),
+            (at(12, 2), "10.0.0.3", "broken.example", None),
+            // outside of the window
+            (at(14, 0), "10.0.0.3", "late.example", Some(AnswerSource::Upstream)),
+        ]
+        .into_iter()
+        .map(|(timestamp, client, qname, source)| {
+            QueryLogEntity::new(client.parse().unwrap(), qname.to_string(), 1, 0, source, Duration::ZERO)
+                .with_timestamp(timestamp)
+        })
+        .collect();
+        QueryLogEntity::_insert_batch(&entries, db.get_pool(), db.query_log_tbl()).await.unwrap();
+
+        let stats = summary(&db, at(10, 0), at(13, 0), 2).await.unwrap();
+        assert_eq!(
+            stats.totals,
+            Totals {
+                queries: 6,
+                local: 1,
+                cached: 1,
+                upstream: 1,
+                blocked: 2,
+                failed: 1,
+                blocked_percentage: 100.0 / 3.0,
+            }
+        );
+
+        let entry = |name: &str, count| TopEntry { name: name.to_string(), count };
+        // ties are broken alphabetically
+        assert_eq!(stats.top_domains, vec![entry("ads.example.com", 2), entry("example.com", 2)]);
+        assert_eq!(stats.top_blocked_domains, vec![entry("ads.example.com", 2)]);
+        assert_eq!(stats.top_clients, vec![entry("10.0.0.1", 4), entry("10.0.0.2", 1)]);
+
+        let hourly: Vec<_> = stats.hourly.iter().map(|count| (count.hour, count.queries, count.blocked)).collect();
+        assert_eq!(hourly, vec![(at(10, 0), 3, 1), (at(11, 0), 0, 0), (at(12, 0), 3, 1)]);
+    }
+}